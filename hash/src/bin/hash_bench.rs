@@ -0,0 +1,111 @@
+//! Compares the `SimpleHashMap` and `SwissHashMap` layouts with std's
+//! `HashMap`, all using the same hasher.
+//!
+//! Run with `cargo run --release -p hash --bin hash_bench -- [items]`.
+
+use std::collections::HashMap;
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use hash::hasher::FxBuildHasher;
+use hash::{SimpleHashMap, SwissHashMap};
+
+const ROUNDS: usize = 5;
+
+/// The operations every map under test supports.
+trait BenchMap: Default {
+    fn insert(&mut self, k: u64, v: u64);
+    fn get(&self, k: &u64) -> Option<&u64>;
+    fn remove(&mut self, k: &u64) -> Option<u64>;
+}
+
+impl BenchMap for SimpleHashMap<u64, u64, FxBuildHasher> {
+    fn insert(&mut self, k: u64, v: u64) { SimpleHashMap::insert(self, k, v); }
+    fn get(&self, k: &u64) -> Option<&u64> { SimpleHashMap::get(self, k) }
+    fn remove(&mut self, k: &u64) -> Option<u64> { SimpleHashMap::remove(self, k) }
+}
+
+impl BenchMap for SwissHashMap<u64, u64, FxBuildHasher> {
+    fn insert(&mut self, k: u64, v: u64) { SwissHashMap::insert(self, k, v); }
+    fn get(&self, k: &u64) -> Option<&u64> { SwissHashMap::get(self, k) }
+    fn remove(&mut self, k: &u64) -> Option<u64> { SwissHashMap::remove(self, k) }
+}
+
+impl BenchMap for HashMap<u64, u64, FxBuildHasher> {
+    fn insert(&mut self, k: u64, v: u64) { HashMap::insert(self, k, v); }
+    fn get(&self, k: &u64) -> Option<&u64> { HashMap::get(self, k) }
+    fn remove(&mut self, k: &u64) -> Option<u64> { HashMap::remove(self, k) }
+}
+
+/// Pseudo random keys, the same on every run.
+fn keys(n: usize, seed: u64) -> Vec<u64> {
+    let mut x = seed;
+    (0..n).map(|_| {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        x
+    }).collect()
+}
+
+#[derive(Default)]
+struct Timings {
+    insert: Duration,
+    hit: Duration,
+    miss: Duration,
+    remove: Duration,
+}
+
+fn run<M: BenchMap>(present: &[u64], absent: &[u64]) -> Timings {
+    let mut timings = Timings::default();
+    for _ in 0..ROUNDS {
+        let mut map = M::default();
+
+        let start = Instant::now();
+        for (i, key) in present.iter().enumerate() {
+            map.insert(*key, i as u64);
+        }
+        timings.insert += start.elapsed();
+
+        let start = Instant::now();
+        for key in present {
+            black_box(map.get(key));
+        }
+        timings.hit += start.elapsed();
+
+        let start = Instant::now();
+        for key in absent {
+            black_box(map.get(key));
+        }
+        timings.miss += start.elapsed();
+
+        let start = Instant::now();
+        for key in present {
+            black_box(map.remove(key));
+        }
+        timings.remove += start.elapsed();
+    }
+    timings
+}
+
+fn report(name: &str, timings: &Timings, items: usize) {
+    let per_op = |d: Duration| d.as_nanos() as f64 / (items * ROUNDS) as f64;
+    println!("{:<16} {:>10.1} {:>10.1} {:>10.1} {:>10.1}",
+             name, per_op(timings.insert), per_op(timings.hit),
+             per_op(timings.miss), per_op(timings.remove));
+}
+
+fn main() {
+    let items = env::args().nth(1)
+        .map(|arg| arg.parse().expect("items must be a number"))
+        .unwrap_or(100_000);
+    let present = keys(items, 0x2545_f491_4f6c_dd1d);
+    let absent = keys(items, 0x9e37_79b9_7f4a_7c15);
+
+    println!("{} items, {} rounds, ns per operation", items, ROUNDS);
+    println!("{:<16} {:>10} {:>10} {:>10} {:>10}", "map", "insert", "get hit", "get miss", "remove");
+    report("SimpleHashMap", &run::<SimpleHashMap<u64, u64, FxBuildHasher>>(&present, &absent), items);
+    report("SwissHashMap", &run::<SwissHashMap<u64, u64, FxBuildHasher>>(&present, &absent), items);
+    report("std HashMap", &run::<HashMap<u64, u64, FxBuildHasher>>(&present, &absent), items);
+}
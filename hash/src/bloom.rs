@@ -0,0 +1,607 @@
+//! Bloom filters: compact sets which may answer "present" for an absent
+//! item, but never "absent" for a present one.
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::hash::{Hash, BuildHasher};
+use std::marker::PhantomData;
+
+use super::hasher::{FnvBuildHasher, mix};
+
+const BLOOM_MAGIC: &[u8; 4] = b"BLM1";
+const COUNTING_MAGIC: &[u8; 4] = b"CBF1";
+/// Magic, number of hashes (u32) and size (u64), all little endian.
+const HEADER_LEN: usize = 16;
+/// Most hashes per item, far more than any useful false positive rate
+/// needs, and a bound on the work a decoded header can ask for.
+const MAX_HASHES: u32 = 64;
+
+/// Errors of filling, combining and decoding filters and sketches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterError {
+    /// The filters or sketches differ in size or number of hashes.
+    Incompatible,
+    /// The bytes do not start with the magic of the filter type.
+    BadMagic,
+    /// The bytes end before the filter does, or go on after it.
+    BadLength,
+    /// The filter has no room for another item.
+    Full,
+}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let message = match self {
+            FilterError::Incompatible => "filters or sketches differ in size or number of hashes",
+            FilterError::BadMagic => "bytes are not an encoded filter of this type",
+            FilterError::BadLength => "encoded filter has the wrong length",
+            FilterError::Full => "filter is full",
+        };
+        f.write_str(message)
+    }
+}
+
+impl Error for FilterError {}
+
+/// Bits for `expected_items` items at `false_positive_rate`, and the number
+/// of hashes minimizing the rate for that size.
+fn optimal_size(expected_items: usize, false_positive_rate: f64) -> (usize, u32) {
+    assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be between 0 and 1, got {}", false_positive_rate);
+    let n = expected_items.max(1) as f64;
+    let ln2 = std::f64::consts::LN_2;
+    let bits = (-n * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(1.0);
+    let hashes = (bits / n * ln2).round().max(1.0);
+    (bits as usize, hashes as u32)
+}
+
+/// `hashes` clamped to between 1 and `MAX_HASHES`, and to no more than the
+/// `size` slots, so every filter built encodes a header that decodes.
+fn clamp_hashes(hashes: u32, size: usize) -> u32 {
+    let most = u32::try_from(size).unwrap_or(u32::MAX).min(MAX_HASHES);
+    hashes.clamp(1, most.max(1))
+}
+
+/// Slots of an item with `hash` in a filter of `size` slots, by double
+/// hashing: `h1 + i * h2`, so one hash gives any number of them.
+pub(crate) fn indexes(hash: u64, hashes: u32, size: usize) -> impl Iterator<Item = usize> {
+    let h1 = mix(hash);
+    let h2 = mix(h1) | 1;
+    (0..hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % size as u64) as usize)
+}
+
+fn encode_header(magic: &[u8; 4], hashes: u32, size: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN);
+    bytes.extend_from_slice(magic);
+    bytes.extend_from_slice(&hashes.to_le_bytes());
+    bytes.extend_from_slice(&(size as u64).to_le_bytes());
+    bytes
+}
+
+/// Returns the number of hashes, the size and the rest of `bytes`.
+fn decode_header<'a>(magic: &[u8; 4], bytes: &'a [u8]) -> Result<(u32, usize, &'a [u8]), FilterError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(FilterError::BadLength);
+    }
+    if &bytes[..4] != magic {
+        return Err(FilterError::BadMagic);
+    }
+    let mut hashes = [0u8; 4];
+    let mut size = [0u8; 8];
+    hashes.copy_from_slice(&bytes[4..8]);
+    size.copy_from_slice(&bytes[8..16]);
+    let hashes = u32::from_le_bytes(hashes);
+    let size = usize::try_from(u64::from_le_bytes(size)).map_err(|_| FilterError::BadLength)?;
+    if hashes == 0 || size == 0 || hashes > MAX_HASHES || hashes as usize > size {
+        return Err(FilterError::BadLength);
+    }
+    Ok((hashes, size, &bytes[HEADER_LEN..]))
+}
+
+/// Bloom filter over a bit array, hashing items with `S`.
+///
+/// Both sides of `union`, `intersect` and serialization must use the same
+/// hasher, the default is the 64 bit FNV-1a, which is the same in every
+/// process.
+pub struct BloomFilter<T: ?Sized, S = FnvBuildHasher> {
+    bits: Vec<u64>,
+    num_bits: usize,
+    num_hashes: u32,
+    hash_builder: S,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash + ?Sized> BloomFilter<T, FnvBuildHasher> {
+
+    /// Creates a filter for `expected_items` with at most
+    /// `false_positive_rate` false positives once they are inserted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `false_positive_rate` is not between 0 and 1.
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> BloomFilter<T, FnvBuildHasher> {
+        BloomFilter::with_hasher(expected_items, false_positive_rate, Default::default())
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> BloomFilter<T, S> {
+
+    pub fn with_hasher(expected_items: usize, false_positive_rate: f64, hash_builder: S) -> BloomFilter<T, S> {
+        let (num_bits, num_hashes) = optimal_size(expected_items, false_positive_rate);
+        BloomFilter::with_size_and_hasher(num_bits, num_hashes, hash_builder)
+    }
+
+    /// Creates a filter of `num_bits` bits setting `num_hashes` bits per
+    /// item, both at least 1, and `num_hashes` at most 64 and `num_bits`.
+    pub fn with_size_and_hasher(num_bits: usize, num_hashes: u32, hash_builder: S) -> BloomFilter<T, S> {
+        let num_bits = num_bits.max(1);
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64)],
+            num_bits,
+            num_hashes: clamp_hashes(num_hashes, num_bits),
+            hash_builder,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn num_bits(&self) -> usize { self.num_bits }
+
+    pub fn num_hashes(&self) -> u32 { self.num_hashes }
+
+    pub fn hasher(&self) -> &S { &self.hash_builder }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|word| *word == 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.iter_mut().for_each(|word| *word = 0);
+    }
+
+    pub fn insert(&mut self, item: &T) {
+        for i in indexes(self.hash_builder.hash_one(item), self.num_hashes, self.num_bits) {
+            self.bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    /// Returns false if `item` was never inserted, true if it probably was.
+    pub fn contains(&self, item: &T) -> bool {
+        indexes(self.hash_builder.hash_one(item), self.num_hashes, self.num_bits)
+            .all(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    fn ones(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Estimates the number of distinct items inserted from the share of
+    /// bits set.
+    pub fn estimated_len(&self) -> f64 {
+        let (m, k) = (self.num_bits as f64, self.num_hashes as f64);
+        let ones = self.ones() as f64;
+        if ones >= m {
+            return f64::INFINITY;
+        }
+        -m / k * (1.0 - ones / m).ln()
+    }
+
+    /// Returns the chance that an absent item is reported present, given the
+    /// bits set now.
+    pub fn false_positive_rate(&self) -> f64 {
+        (self.ones() as f64 / self.num_bits as f64).powi(self.num_hashes as i32)
+    }
+
+    fn check_compatible(&self, other: &BloomFilter<T, S>) -> Result<(), FilterError> {
+        if self.num_bits == other.num_bits && self.num_hashes == other.num_hashes {
+            Ok(())
+        } else {
+            Err(FilterError::Incompatible)
+        }
+    }
+
+    /// Adds the items of `other`, afterwards `self` is the filter of all the
+    /// items inserted into either.
+    pub fn union(&mut self, other: &BloomFilter<T, S>) -> Result<(), FilterError> {
+        self.check_compatible(other)?;
+        self.bits.iter_mut().zip(&other.bits).for_each(|(a, b)| *a |= b);
+        Ok(())
+    }
+
+    /// Keeps only the bits set in both. Afterwards `self` contains every item
+    /// inserted into both, but has more false positives than a filter built
+    /// from the common items.
+    pub fn intersect(&mut self, other: &BloomFilter<T, S>) -> Result<(), FilterError> {
+        self.check_compatible(other)?;
+        self.bits.iter_mut().zip(&other.bits).for_each(|(a, b)| *a &= b);
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = encode_header(BLOOM_MAGIC, self.num_hashes, self.num_bits);
+        for word in &self.bits {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a filter written by `to_bytes`, which must have used the same
+    /// kind of hasher.
+    pub fn from_bytes_with_hasher(bytes: &[u8], hash_builder: S) -> Result<BloomFilter<T, S>, FilterError> {
+        let (num_hashes, num_bits, words) = decode_header(BLOOM_MAGIC, bytes)?;
+        if words.len() % 8 != 0 || words.len() / 8 != num_bits.div_ceil(64) {
+            return Err(FilterError::BadLength);
+        }
+        let bits = words.chunks_exact(8).map(|chunk| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        }).collect();
+        Ok(BloomFilter { bits, num_bits, num_hashes, hash_builder, marker: PhantomData })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<BloomFilter<T, S>, FilterError> where S: Default {
+        BloomFilter::from_bytes_with_hasher(bytes, Default::default())
+    }
+}
+
+impl<T: ?Sized, S: Clone> Clone for BloomFilter<T, S> {
+    fn clone(&self) -> BloomFilter<T, S> {
+        BloomFilter {
+            bits: self.bits.clone(),
+            num_bits: self.num_bits,
+            num_hashes: self.num_hashes,
+            hash_builder: self.hash_builder.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Bloom filter with a counter per slot instead of a bit, so items can be
+/// removed again.
+///
+/// Counters stop at 255: such a slot stays set for good, since it is unknown
+/// how many of its items are gone. Removing an item which was never
+/// inserted corrupts the filter, unless `contains` says it is absent.
+pub struct CountingBloomFilter<T: ?Sized, S = FnvBuildHasher> {
+    counters: Vec<u8>,
+    num_hashes: u32,
+    hash_builder: S,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash + ?Sized> CountingBloomFilter<T, FnvBuildHasher> {
+
+    /// Creates a filter for `expected_items` with at most
+    /// `false_positive_rate` false positives once they are inserted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `false_positive_rate` is not between 0 and 1.
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> CountingBloomFilter<T, FnvBuildHasher> {
+        CountingBloomFilter::with_hasher(expected_items, false_positive_rate, Default::default())
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> CountingBloomFilter<T, S> {
+
+    pub fn with_hasher(expected_items: usize, false_positive_rate: f64, hash_builder: S) -> CountingBloomFilter<T, S> {
+        let (num_counters, num_hashes) = optimal_size(expected_items, false_positive_rate);
+        CountingBloomFilter::with_size_and_hasher(num_counters, num_hashes, hash_builder)
+    }
+
+    /// Creates a filter of `num_counters` counters, incrementing
+    /// `num_hashes` of them per item, both at least 1, and `num_hashes` at
+    /// most 64 and `num_counters`.
+    pub fn with_size_and_hasher(num_counters: usize, num_hashes: u32, hash_builder: S) -> CountingBloomFilter<T, S> {
+        let num_counters = num_counters.max(1);
+        CountingBloomFilter {
+            counters: vec![0; num_counters],
+            num_hashes: clamp_hashes(num_hashes, num_counters),
+            hash_builder,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn num_counters(&self) -> usize { self.counters.len() }
+
+    pub fn num_hashes(&self) -> u32 { self.num_hashes }
+
+    pub fn hasher(&self) -> &S { &self.hash_builder }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.iter().all(|c| *c == 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.counters.iter_mut().for_each(|c| *c = 0);
+    }
+
+    fn indexes(&self, item: &T) -> impl Iterator<Item = usize> {
+        indexes(self.hash_builder.hash_one(item), self.num_hashes, self.counters.len())
+    }
+
+    pub fn insert(&mut self, item: &T) {
+        for i in self.indexes(item) {
+            self.counters[i] = self.counters[i].saturating_add(1);
+        }
+    }
+
+    /// Returns false if `item` is not in the filter, true if it probably is.
+    pub fn contains(&self, item: &T) -> bool {
+        self.indexes(item).all(|i| self.counters[i] > 0)
+    }
+
+    /// Removes one insertion of `item`, returns false if it is not in the
+    /// filter and nothing changed.
+    pub fn remove(&mut self, item: &T) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+        for i in self.indexes(item) {
+            if self.counters[i] != u8::MAX {
+                self.counters[i] -= 1;
+            }
+        }
+        true
+    }
+
+    fn check_compatible(&self, other: &CountingBloomFilter<T, S>) -> Result<(), FilterError> {
+        if self.counters.len() == other.counters.len() && self.num_hashes == other.num_hashes {
+            Ok(())
+        } else {
+            Err(FilterError::Incompatible)
+        }
+    }
+
+    /// Adds the insertions of `other` to `self`.
+    pub fn union(&mut self, other: &CountingBloomFilter<T, S>) -> Result<(), FilterError> {
+        self.check_compatible(other)?;
+        self.counters.iter_mut().zip(&other.counters).for_each(|(a, b)| *a = a.saturating_add(*b));
+        Ok(())
+    }
+
+    /// Keeps the lower counter of each slot, see `BloomFilter::intersect`.
+    pub fn intersect(&mut self, other: &CountingBloomFilter<T, S>) -> Result<(), FilterError> {
+        self.check_compatible(other)?;
+        self.counters.iter_mut().zip(&other.counters).for_each(|(a, b)| *a = (*a).min(*b));
+        Ok(())
+    }
+
+    /// Returns the plain filter of the same items.
+    pub fn to_bloom_filter(&self) -> BloomFilter<T, S> where S: Clone {
+        let mut filter = BloomFilter::with_size_and_hasher(self.counters.len(), self.num_hashes, self.hash_builder.clone());
+        for (i, _) in self.counters.iter().enumerate().filter(|(_, c)| **c > 0) {
+            filter.bits[i / 64] |= 1 << (i % 64);
+        }
+        filter
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = encode_header(COUNTING_MAGIC, self.num_hashes, self.counters.len());
+        bytes.extend_from_slice(&self.counters);
+        bytes
+    }
+
+    /// Decodes a filter written by `to_bytes`, which must have used the same
+    /// kind of hasher.
+    pub fn from_bytes_with_hasher(bytes: &[u8], hash_builder: S) -> Result<CountingBloomFilter<T, S>, FilterError> {
+        let (num_hashes, num_counters, counters) = decode_header(COUNTING_MAGIC, bytes)?;
+        if counters.len() != num_counters {
+            return Err(FilterError::BadLength);
+        }
+        Ok(CountingBloomFilter { counters: counters.to_vec(), num_hashes, hash_builder, marker: PhantomData })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<CountingBloomFilter<T, S>, FilterError> where S: Default {
+        CountingBloomFilter::from_bytes_with_hasher(bytes, Default::default())
+    }
+}
+
+impl<T: ?Sized, S: Clone> Clone for CountingBloomFilter<T, S> {
+    fn clone(&self) -> CountingBloomFilter<T, S> {
+        CountingBloomFilter {
+            counters: self.counters.clone(),
+            num_hashes: self.num_hashes,
+            hash_builder: self.hash_builder.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_bloom {
+    use std::hash::BuildHasher;
+    use super::{BloomFilter, CountingBloomFilter, FilterError, optimal_size};
+    use crate::hasher::{FnvBuildHasher, FxBuildHasher, BKDRBuildHasher};
+    use crate::RSBuildHasher;
+
+    #[test]
+    fn test_optimal_size() {
+        assert_eq!(optimal_size(1000, 0.01), (9586, 7));
+        assert_eq!(optimal_size(1_000_000, 0.001), (14_377_588, 10));
+        assert_eq!(optimal_size(0, 0.5).1, 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_rate() {
+        BloomFilter::<str>::new(100, 1.5);
+    }
+
+    fn measured_false_positive_rate<S: BuildHasher>(hash_builder: S) -> f64 {
+        let mut filter = BloomFilter::with_hasher(10_000, 0.01, hash_builder);
+        for i in 0..10_000 {
+            filter.insert(&format!("present-{}", i));
+        }
+        for i in 0..10_000 {
+            assert!(filter.contains(&format!("present-{}", i)));
+        }
+        let trials = 100_000;
+        let false_positives = (0..trials).filter(|i| filter.contains(&format!("absent-{}", i))).count();
+        false_positives as f64 / trials as f64
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        for rate in [
+            measured_false_positive_rate(FnvBuildHasher::default()),
+            measured_false_positive_rate(FxBuildHasher::default()),
+            measured_false_positive_rate(RSBuildHasher::default()),
+            measured_false_positive_rate(BKDRBuildHasher::default()),
+        ] {
+            assert!(rate < 0.015, "false positive rate {}", rate);
+        }
+    }
+
+    #[test]
+    fn test_estimates() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        assert!(filter.is_empty());
+        assert_eq!(filter.false_positive_rate(), 0.0);
+        for i in 0..1000u32 {
+            filter.insert(&i);
+        }
+        let len = filter.estimated_len();
+        assert!((len - 1000.0).abs() < 50.0, "{}", len);
+        let rate = filter.false_positive_rate();
+        assert!(rate > 0.005 && rate < 0.015, "{}", rate);
+
+        filter.clear();
+        assert!(filter.is_empty());
+        assert!(!filter.contains(&1));
+    }
+
+    #[test]
+    fn test_union_and_intersect() {
+        let mut a = BloomFilter::new(1000, 0.01);
+        let mut b = BloomFilter::new(1000, 0.01);
+        for i in 0..300u32 {
+            a.insert(&i);
+        }
+        for i in 200..500u32 {
+            b.insert(&i);
+        }
+
+        let mut union = a.clone();
+        union.union(&b).unwrap();
+        assert!((0..500u32).all(|i| union.contains(&i)));
+
+        let mut common = a.clone();
+        common.intersect(&b).unwrap();
+        assert!((200..300u32).all(|i| common.contains(&i)));
+        let others = (0..200u32).chain(300..500).filter(|i| common.contains(i)).count();
+        assert!(others < 40, "{}", others);
+
+        let mut other_size = BloomFilter::new(2000, 0.01);
+        assert_eq!(other_size.union(&a), Err(FilterError::Incompatible));
+        assert_eq!(a.intersect(&other_size), Err(FilterError::Incompatible));
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut filter = BloomFilter::new(100, 0.01);
+        for word in &["alpha", "beta", "gamma"] {
+            filter.insert(*word);
+        }
+        let bytes = filter.to_bytes();
+        assert_eq!(&bytes[..4], b"BLM1");
+        assert_eq!(bytes.len(), 16 + filter.num_bits().div_ceil(64) * 8);
+
+        let decoded: BloomFilter<str> = BloomFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.num_bits(), filter.num_bits());
+        assert_eq!(decoded.num_hashes(), filter.num_hashes());
+        assert!(decoded.contains("alpha") && decoded.contains("beta") && decoded.contains("gamma"));
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_bad_bytes() {
+        let bytes = BloomFilter::<str>::new(100, 0.01).to_bytes();
+        assert_eq!(BloomFilter::<str>::from_bytes(&bytes[..10]).err(), Some(FilterError::BadLength));
+        assert_eq!(BloomFilter::<str>::from_bytes(&bytes[..bytes.len() - 1]).err(), Some(FilterError::BadLength));
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(BloomFilter::<str>::from_bytes(&longer).err(), Some(FilterError::BadLength));
+        assert_eq!(CountingBloomFilter::<str>::from_bytes(&bytes).err(), Some(FilterError::BadMagic));
+
+        // a header asking for more hashes than allowed, or than bits
+        let mut many = bytes.clone();
+        many[4..8].copy_from_slice(&65u32.to_le_bytes());
+        assert_eq!(BloomFilter::<str>::from_bytes(&many).err(), Some(FilterError::BadLength));
+        many[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(BloomFilter::<str>::from_bytes(&many).err(), Some(FilterError::BadLength));
+        let small = CountingBloomFilter::<str>::with_size_and_hasher(4, 3, Default::default()).to_bytes();
+        let mut over = small.clone();
+        over[4..8].copy_from_slice(&5u32.to_le_bytes());
+        assert_eq!(CountingBloomFilter::<str>::from_bytes(&over).err(), Some(FilterError::BadLength));
+        assert!(CountingBloomFilter::<str>::from_bytes(&small).is_ok());
+    }
+
+    #[test]
+    fn test_hashes_are_clamped() {
+        let filter = BloomFilter::<str>::with_size_and_hasher(1000, 500, Default::default());
+        assert_eq!(filter.num_hashes(), 64);
+        let filter = BloomFilter::<str>::with_size_and_hasher(3, 10, Default::default());
+        assert_eq!(filter.num_hashes(), 3);
+        assert!(BloomFilter::<str>::from_bytes(&filter.to_bytes()).is_ok());
+        let filter = BloomFilter::<str>::new(10, 1e-30);
+        assert_eq!(filter.num_hashes(), 64);
+        assert!(BloomFilter::<str>::from_bytes(&filter.to_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_counting_remove() {
+        let mut filter = CountingBloomFilter::new(1000, 0.01);
+        for i in 0..1000u32 {
+            filter.insert(&i);
+        }
+        for i in (0..1000u32).step_by(2) {
+            assert!(filter.remove(&i));
+        }
+        assert!((1..1000u32).step_by(2).all(|i| filter.contains(&i)));
+        let remaining = (0..1000u32).step_by(2).filter(|i| filter.contains(i)).count();
+        assert!(remaining < 20, "{}", remaining);
+
+        for i in (1..1000u32).step_by(2) {
+            assert!(filter.remove(&i));
+        }
+        assert!(filter.is_empty());
+        assert!(!filter.remove(&1));
+    }
+
+    #[test]
+    fn test_counting_saturates() {
+        let mut filter = CountingBloomFilter::with_size_and_hasher(64, 3, RSBuildHasher::default());
+        for _ in 0..300 {
+            filter.insert("hot");
+        }
+        for _ in 0..300 {
+            filter.remove("hot");
+        }
+        assert!(filter.contains("hot"));
+    }
+
+    #[test]
+    fn test_counting_union_and_bytes() {
+        let mut a = CountingBloomFilter::new(100, 0.01);
+        let mut b = CountingBloomFilter::new(100, 0.01);
+        a.insert("x");
+        b.insert("x");
+        b.insert("y");
+        a.union(&b).unwrap();
+        assert!(a.remove("x"));
+        assert!(a.contains("x") && a.contains("y"));
+
+        let mut c = a.clone();
+        c.intersect(&b).unwrap();
+        assert!(c.contains("x") && c.contains("y"));
+
+        let decoded: CountingBloomFilter<str> = CountingBloomFilter::from_bytes(&a.to_bytes()).unwrap();
+        assert_eq!(decoded.to_bytes(), a.to_bytes());
+
+        let plain = a.to_bloom_filter();
+        assert!(plain.contains("x") && plain.contains("y"));
+        assert_eq!(CountingBloomFilter::new(200, 0.01).union(&a), Err(FilterError::Incompatible));
+    }
+}
@@ -0,0 +1,496 @@
+//! Bounded cache on top of `SimpleHashMap`.
+
+use std::hash::{Hash, BuildHasher};
+use std::mem;
+
+use super::{SimpleHashMap, RSBuildHasher};
+
+const NIL: usize = usize::MAX;
+
+/// Which item a full `Cache` evicts to make room for a new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Policy {
+    /// Evicts the least recently used item.
+    #[default]
+    Lru,
+    /// Evicts the least frequently used item, the least recently used one
+    /// among those with the same count.
+    Lfu,
+    /// Evicts the oldest inserted item, lookups do not matter.
+    Fifo,
+}
+
+/// Counters of a `Cache`, see `Cache::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl CacheStats {
+
+    /// Returns the share of lookups which found their key, 0 without lookups.
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+    freq: u64,
+}
+
+/// Cache holding at most `capacity` items, evicting one by its `Policy`
+/// when a new key is inserted into a full cache.
+///
+/// The map points every key to a node of an intrusive list, kept in a slab
+/// and ordered from the item to evict last (head) to the one to evict first
+/// (tail), so lookups, insertions and evictions are all O(1). `Lfu` keeps
+/// the list sorted by use count and remembers the head of every count.
+pub struct Cache<K, V, S = RSBuildHasher> {
+    map: SimpleHashMap<K, usize, S>,
+    nodes: Vec<Option<Node<K, V>>>,
+    free: Vec<usize>,
+    head: usize,
+    tail: usize,
+    freq_heads: SimpleHashMap<u64, usize>,
+    capacity: usize,
+    policy: Policy,
+    stats: CacheStats,
+    on_evict: Option<Box<dyn FnMut(K, V) + Send>>,
+}
+
+/// Iterator over a `Cache`, from the item to evict last to the one to evict
+/// first.
+pub struct Iter<'a, K: 'a, V: 'a> {
+    nodes: &'a [Option<Node<K, V>>],
+    next: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let node = self.nodes.get(self.next)?.as_ref()?;
+        self.next = node.next;
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cache<K, V, RSBuildHasher> {
+
+    /// Creates an LRU cache holding at most `capacity` items.
+    pub fn new(capacity: usize) -> Cache<K, V, RSBuildHasher> {
+        Cache::with_policy(capacity, Policy::Lru)
+    }
+
+    pub fn with_policy(capacity: usize, policy: Policy) -> Cache<K, V, RSBuildHasher> {
+        Cache::with_policy_and_hasher(capacity, policy, Default::default())
+    }
+}
+
+impl<K, V, S> Cache<K, V, S> where K: Hash + Eq + Clone, S: BuildHasher {
+
+    pub fn with_policy_and_hasher(capacity: usize, policy: Policy, hash_builder: S) -> Cache<K, V, S> {
+        Cache {
+            map: SimpleHashMap::with_capacity_and_hasher(capacity, hash_builder),
+            nodes: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            freq_heads: SimpleHashMap::new(),
+            capacity,
+            policy,
+            stats: CacheStats::default(),
+            on_evict: None,
+        }
+    }
+
+    /// Calls `f` with every item evicted to make room for a new one. Items
+    /// removed by `remove` are returned instead.
+    pub fn on_evict<F>(&mut self, f: F) where F: FnMut(K, V) + Send + 'static {
+        self.on_evict = Some(Box::new(f));
+    }
+
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    pub fn policy(&self) -> Policy { self.policy }
+
+    pub fn len(&self) -> usize { self.map.len() }
+
+    pub fn is_empty(&self) -> bool { self.map.is_empty() }
+
+    pub fn stats(&self) -> CacheStats { self.stats }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { nodes: &self.nodes, next: self.head }
+    }
+
+    fn node(&self, i: usize) -> &Node<K, V> {
+        self.nodes[i].as_ref().expect("linked node is vacant")
+    }
+
+    fn node_mut(&mut self, i: usize) -> &mut Node<K, V> {
+        self.nodes[i].as_mut().expect("linked node is vacant")
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = { let node = self.node(i); (node.prev, node.next) };
+        if prev == NIL { self.head = next } else { self.node_mut(prev).next = next }
+        if next == NIL { self.tail = prev } else { self.node_mut(next).prev = prev }
+    }
+
+    /// Links `i` in front of `anchor`, or at the tail if `anchor` is `NIL`.
+    fn link_before(&mut self, i: usize, anchor: usize) {
+        let prev = if anchor == NIL { self.tail } else { self.node(anchor).prev };
+        {
+            let node = self.node_mut(i);
+            node.prev = prev;
+            node.next = anchor;
+        }
+        if prev == NIL { self.head = i } else { self.node_mut(prev).next = i }
+        if anchor == NIL { self.tail = i } else { self.node_mut(anchor).prev = i }
+    }
+
+    /// Unlinks `i`, handing the head of its count over to the next node.
+    fn detach(&mut self, i: usize) {
+        if self.policy == Policy::Lfu {
+            let (freq, next) = { let node = self.node(i); (node.freq, node.next) };
+            if self.freq_heads.get(&freq) == Some(&i) {
+                if next != NIL && self.node(next).freq == freq {
+                    self.freq_heads.insert(freq, next);
+                } else {
+                    self.freq_heads.remove(&freq);
+                }
+            }
+        }
+        self.unlink(i);
+    }
+
+    /// Records a use of the item at `i`.
+    fn touch(&mut self, i: usize) {
+        match self.policy {
+            Policy::Fifo => {}
+            Policy::Lru => {
+                if self.head != i {
+                    self.unlink(i);
+                    let head = self.head;
+                    self.link_before(i, head);
+                }
+            }
+            Policy::Lfu => {
+                let (freq, next) = { let node = self.node(i); (node.freq, node.next) };
+                let group_head = self.freq_heads[&freq];
+                self.detach(i);
+                // without items used once more, the node goes in front of
+                // its current count, or stays in place if it led it already
+                let anchor = match self.freq_heads.get(&(freq + 1)) {
+                    Some(&head) => head,
+                    None if group_head == i => next,
+                    None => group_head,
+                };
+                self.node_mut(i).freq = freq + 1;
+                self.link_before(i, anchor);
+                self.freq_heads.insert(freq + 1, i);
+            }
+        }
+    }
+
+    /// Unlinks the item at `i` and frees its node.
+    fn release(&mut self, i: usize) -> Node<K, V> {
+        self.detach(i);
+        let node = self.nodes[i].take().expect("linked node is vacant");
+        self.free.push(i);
+        self.map.remove(&node.key);
+        node
+    }
+
+    fn evicted(&mut self, k: K, v: V) {
+        self.stats.evictions += 1;
+        if let Some(f) = self.on_evict.as_mut() {
+            f(k, v);
+        }
+    }
+
+    /// Returns the value of `k`, recording the use and a hit or a miss.
+    pub fn get(&mut self, k: &K) -> Option<&V> {
+        let i = self.lookup(k)?;
+        Some(&self.node(i).value)
+    }
+
+    pub fn get_mut(&mut self, k: &K) -> Option<&mut V> {
+        let i = self.lookup(k)?;
+        Some(&mut self.node_mut(i).value)
+    }
+
+    fn lookup(&mut self, k: &K) -> Option<usize> {
+        match self.map.get(k) {
+            Some(&i) => {
+                self.stats.hits += 1;
+                self.touch(i);
+                Some(i)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Returns the value of `k` without counting it as a use.
+    pub fn peek(&self, k: &K) -> Option<&V> {
+        self.map.get(k).map(|&i| &self.node(i).value)
+    }
+
+    pub fn contains_key(&self, k: &K) -> bool {
+        self.map.contains_key(k)
+    }
+
+    /// Inserts or replaces the value of `k`, returning the replaced one.
+    /// Replacing counts as a use, inserting into a full cache evicts one
+    /// item first.
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        if let Some(&i) = self.map.get(&k) {
+            let old = mem::replace(&mut self.node_mut(i).value, v);
+            self.touch(i);
+            return Some(old);
+        }
+        if self.capacity == 0 {
+            self.evicted(k, v);
+            return None;
+        }
+        if self.map.len() == self.capacity {
+            let node = self.release(self.tail);
+            self.evicted(node.key, node.value);
+        }
+
+        let node = Node { key: k.clone(), value: v, prev: NIL, next: NIL, freq: 1 };
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = Some(node);
+                i
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.map.insert(k, i);
+        if self.policy == Policy::Lfu {
+            let anchor = self.freq_heads.get(&1).cloned().unwrap_or(NIL);
+            self.link_before(i, anchor);
+            self.freq_heads.insert(1, i);
+        } else {
+            let head = self.head;
+            self.link_before(i, head);
+        }
+        None
+    }
+
+    pub fn remove(&mut self, k: &K) -> Option<V> {
+        let i = *self.map.get(k)?;
+        Some(self.release(i).value)
+    }
+}
+
+#[cfg(test)]
+mod test_cache {
+    use std::sync::{Arc, Mutex};
+    use super::{Cache, CacheStats, Policy};
+    use crate::hasher::xorshift;
+
+    fn keys<V>(cache: &Cache<i32, V>) -> Vec<i32> {
+        cache.iter().map(|(k, _)| *k).collect()
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let mut cache = Cache::new(3);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        cache.insert(3, "c");
+        assert_eq!(cache.get(&1), Some(&"a"));
+
+        cache.insert(4, "d");
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains_key(&2));
+        assert_eq!(keys(&cache), vec![4, 1, 3]);
+
+        // peeking does not count as a use
+        assert_eq!(cache.peek(&3), Some(&"c"));
+        cache.insert(5, "e");
+        assert!(!cache.contains_key(&3));
+        assert_eq!(keys(&cache), vec![5, 4, 1]);
+    }
+
+    #[test]
+    fn test_replacing_counts_as_use() {
+        let mut cache = Cache::new(2);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        assert_eq!(cache.insert(1, 10), Some(1));
+        cache.insert(3, 3);
+        assert_eq!(cache.peek(&1), Some(&10));
+        assert!(!cache.contains_key(&2));
+    }
+
+    #[test]
+    fn test_fifo_ignores_lookups() {
+        let mut cache = Cache::with_policy(3, Policy::Fifo);
+        cache.insert(1, ());
+        cache.insert(2, ());
+        cache.insert(3, ());
+        cache.get(&1);
+        cache.insert(1, ());
+        cache.insert(4, ());
+        assert_eq!(keys(&cache), vec![4, 3, 2]);
+    }
+
+    #[test]
+    fn test_lfu_evicts_least_frequently_used() {
+        let mut cache = Cache::with_policy(3, Policy::Lfu);
+        cache.insert(1, ());
+        cache.insert(2, ());
+        cache.insert(3, ());
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&3);
+
+        cache.insert(4, ());
+        assert!(!cache.contains_key(&2));
+        assert_eq!(keys(&cache), vec![1, 3, 4]);
+
+        // 4 is used once, like 3, but more recently
+        cache.get(&4);
+        cache.insert(5, ());
+        assert!(!cache.contains_key(&3));
+        assert_eq!(keys(&cache), vec![1, 4, 5]);
+    }
+
+    #[test]
+    fn test_remove_reuses_node() {
+        let mut cache = Cache::new(2);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        assert_eq!(cache.remove(&1), Some(1));
+        assert_eq!(cache.remove(&1), None);
+        cache.insert(3, 3);
+        assert_eq!(cache.nodes.len(), 2);
+        assert_eq!(keys(&cache), vec![3, 2]);
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[test]
+    fn test_eviction_callback() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let mut cache = Cache::new(2);
+        {
+            let evicted = evicted.clone();
+            cache.on_evict(move |k, v| evicted.lock().unwrap().push((k, v)));
+        }
+        for i in 0..5 {
+            cache.insert(i, i * 10);
+        }
+        cache.remove(&4);
+        assert_eq!(*evicted.lock().unwrap(), vec![(0, 0), (1, 10), (2, 20)]);
+        assert_eq!(cache.stats().evictions, 3);
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let mut cache = Cache::new(0);
+        assert_eq!(cache.insert(1, 1), None);
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut cache = Cache::new(2);
+        assert_eq!(cache.stats().hit_ratio(), 0.0);
+        cache.insert(1, 1);
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&2);
+        if let Some(v) = cache.get_mut(&1) {
+            *v += 1;
+        }
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1, evictions: 0 });
+        assert_eq!(cache.stats().hit_ratio(), 0.75);
+        assert_eq!(cache.peek(&1), Some(&2));
+
+        cache.reset_stats();
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+
+    /// Naive O(n) model: items with their count and time of last use.
+    fn model_victim(model: &[(i32, u64, u64, u64)], policy: Policy) -> usize {
+        let (i, _) = model.iter().enumerate().min_by_key(|(_, &(_, freq, used, inserted))| match policy {
+            Policy::Lru => (0, used),
+            Policy::Lfu => (freq, used),
+            Policy::Fifo => (0, inserted),
+        }).unwrap();
+        i
+    }
+
+    #[test]
+    fn test_matches_naive_model() {
+        for &policy in &[Policy::Lru, Policy::Lfu, Policy::Fifo] {
+            let mut cache = Cache::with_policy(16, policy);
+            let mut model: Vec<(i32, u64, u64, u64)> = Vec::new();
+            let mut state = 0x2545_f491u64;
+            for clock in 0..20_000u64 {
+                xorshift(&mut state);
+                let key = (state % 40) as i32;
+                let found = model.iter().position(|item| item.0 == key);
+                match state % 3 {
+                    0 => {
+                        assert_eq!(cache.get(&key).is_some(), found.is_some());
+                        if let Some(j) = found {
+                            model[j].1 += 1;
+                            model[j].2 = clock;
+                        }
+                    }
+                    1 => {
+                        assert_eq!(cache.remove(&key).is_some(), found.is_some());
+                        if let Some(j) = found {
+                            model.remove(j);
+                        }
+                    }
+                    _ => {
+                        cache.insert(key, ());
+                        match found {
+                            Some(j) => {
+                                model[j].1 += 1;
+                                model[j].2 = clock;
+                            }
+                            None => {
+                                if model.len() == 16 {
+                                    let victim = model_victim(&model, policy);
+                                    model.remove(victim);
+                                }
+                                model.push((key, 1, clock, clock));
+                            }
+                        }
+                    }
+                }
+                assert_eq!(cache.len(), model.len());
+            }
+            let mut expected: Vec<_> = model.iter().map(|item| item.0).collect();
+            let mut actual = keys(&cache);
+            expected.sort();
+            actual.sort();
+            assert_eq!(actual, expected, "{:?}", policy);
+        }
+    }
+}
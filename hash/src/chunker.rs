@@ -0,0 +1,243 @@
+//! Content-defined chunking in the style of FastCDC, for deduplication.
+//!
+//! Chunk boundaries depend on the bytes around them rather than on their
+//! offset, so inserting or removing bytes only changes the chunks near the
+//! edit and the rest are found again by their digest.
+
+use std::io::{self, ErrorKind, Read};
+
+use super::rolling::random_table;
+
+static GEAR_TABLE: [u64; 256] = random_table(0x6765_6172_4344_4321);
+
+/// Sizes of the FastCDC paper, 2 KiB, 8 KiB and 64 KiB.
+const DEFAULT_SIZES: (usize, usize, usize) = (2 * 1024, 8 * 1024, 64 * 1024);
+
+/// A piece of the stream, starting at `offset`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// Splits the bytes of a reader into chunks between `min_size` and
+/// `max_size` long, `avg_size` on average. Only the last chunk can be
+/// shorter than `min_size`.
+///
+/// A boundary is where the gear hash, which depends on the last 64 bytes,
+/// has its top bits clear. Before `avg_size` the test checks more bits than
+/// after it, which narrows the spread of chunk sizes around the average.
+pub struct Chunker<R> {
+    reader: R,
+    /// `max_size` bytes, allocated and zeroed once.
+    buffer: Box<[u8]>,
+    /// Number of bytes at the start of `buffer` read but not chunked yet.
+    filled: usize,
+    offset: u64,
+    eof: bool,
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+    /// Mask of chunks shorter than `avg_size`.
+    mask_small: u64,
+    /// Mask of chunks longer than `avg_size`.
+    mask_large: u64,
+}
+
+/// Mask of the `bits` top bits of a word.
+fn top_bits(bits: u32) -> u64 {
+    !0u64 << (64 - bits.clamp(1, 63))
+}
+
+impl<R: Read> Chunker<R> {
+
+    /// Chunker with the sizes of the FastCDC paper.
+    pub fn new(reader: R) -> Chunker<R> {
+        let (min_size, avg_size, max_size) = DEFAULT_SIZES;
+        Chunker::with_sizes(reader, min_size, avg_size, max_size)
+    }
+
+    /// Panics unless `0 < min_size <= avg_size <= max_size`.
+    pub fn with_sizes(reader: R, min_size: usize, avg_size: usize, max_size: usize) -> Chunker<R> {
+        assert!(0 < min_size && min_size <= avg_size && avg_size <= max_size,
+                "chunk sizes must satisfy 0 < min <= avg <= max, got {}, {}, {}", min_size, avg_size, max_size);
+        // One boundary every 2^bits bytes on average, rounding the log.
+        let bits = (avg_size as f64).log2().round() as u32;
+        Chunker {
+            reader,
+            buffer: vec![0; max_size].into_boxed_slice(),
+            filled: 0,
+            offset: 0,
+            eof: false,
+            min_size,
+            avg_size,
+            max_size,
+            mask_small: top_bits(bits + 2),
+            mask_large: top_bits(bits.saturating_sub(2)),
+        }
+    }
+
+    /// Length of the chunk at the start of `data`, assuming it continues
+    /// past `max_size` bytes unless the stream ends there.
+    pub fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        let end = data.len().min(self.max_size);
+        let normal = end.min(self.avg_size);
+        let mut hash = 0u64;
+        for (i, &byte) in data.iter().enumerate().take(end).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR_TABLE[byte as usize]);
+            let mask = if i < normal { self.mask_small } else { self.mask_large };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// Reads until the buffer holds a maximal chunk or the stream ends.
+    fn fill(&mut self) -> io::Result<()> {
+        while self.filled < self.max_size && !self.eof {
+            match self.reader.read(&mut self.buffer[self.filled..]) {
+                Ok(0) => self.eof = true,
+                Ok(read) => self.filled += read,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives the reader back, dropping any bytes read but not chunked yet.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Chunk>;
+
+    /// The next chunk, or the error of the reader. Reading is retried by
+    /// the next call after an error.
+    fn next(&mut self) -> Option<io::Result<Chunk>> {
+        if let Err(e) = self.fill() {
+            return Some(Err(e));
+        }
+        if self.filled == 0 {
+            return None;
+        }
+        let len = self.cut_point(&self.buffer[..self.filled]);
+        let chunk = Chunk { offset: self.offset, data: self.buffer[..len].to_vec() };
+        self.buffer.copy_within(len..self.filled, 0);
+        self.filled -= len;
+        self.offset += len as u64;
+        Some(Ok(chunk))
+    }
+}
+
+#[cfg(test)]
+mod test_chunker {
+    use std::io::{self, Read, ErrorKind};
+    use super::{Chunker, Chunk};
+    use crate::test_util::random_bytes;
+
+    fn chunks(data: &[u8], min: usize, avg: usize, max: usize) -> Vec<Chunk> {
+        Chunker::with_sizes(data, min, avg, max).collect::<io::Result<_>>().unwrap()
+    }
+
+    /// Hands out at most a few bytes per read, failing every third read.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        reads: usize,
+    }
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            if self.reads.is_multiple_of(3) {
+                let kind = if self.reads.is_multiple_of(2) { ErrorKind::Interrupted } else { ErrorKind::Other };
+                return Err(io::Error::new(kind, "trickle"));
+            }
+            let n = (self.reads % 7 + 1).min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_chunks_cover_input() {
+        let data = random_bytes(300_000, 1);
+        let chunks = chunks(&data, 1024, 4096, 16384);
+        let mut offset = 0;
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.offset, offset as u64);
+            assert_eq!(chunk.data, &data[offset..offset + chunk.data.len()]);
+            assert!(chunk.data.len() <= 16384);
+            assert!(chunk.data.len() >= 1024 || i == chunks.len() - 1);
+            offset += chunk.data.len();
+        }
+        assert_eq!(offset, data.len());
+
+        let mean = data.len() / chunks.len();
+        assert!(mean > 2048 && mean < 8192, "mean chunk size {}", mean);
+    }
+
+    #[test]
+    fn test_default_sizes() {
+        let data = random_bytes(1 << 20, 2);
+        let chunks: Vec<Chunk> = Chunker::new(&data[..]).map(Result::unwrap).collect();
+        let mean = data.len() / chunks.len();
+        assert!(mean > 4096 && mean < 16384, "mean chunk size {}", mean);
+    }
+
+    #[test]
+    fn test_small_inputs() {
+        assert!(chunks(&[], 4, 8, 16).is_empty());
+        assert_eq!(chunks(b"abc", 4, 8, 16), vec![Chunk { offset: 0, data: b"abc".to_vec() }]);
+        let zeros = chunks(&[0; 100], 4, 8, 16);
+        assert!(zeros.iter().all(|c| c.data.len() <= 16 && c.data.iter().all(|&b| b == 0)));
+        assert_eq!(zeros.iter().map(|c| c.data.len()).sum::<usize>(), 100);
+    }
+
+    #[test]
+    fn test_edits_keep_most_chunks() {
+        let data = random_bytes(200_000, 3);
+        let mut edited = data.clone();
+        edited.splice(50_000..50_000, b"inserted bytes".iter().cloned());
+        edited.drain(150_000..150_100);
+
+        let original = chunks(&data, 512, 2048, 8192);
+        let after = chunks(&edited, 512, 2048, 8192);
+        let shared = after.iter().filter(|c| original.iter().any(|o| o.data == c.data)).count();
+        assert!(shared + 6 >= after.len(), "{} of {} chunks shared", shared, after.len());
+    }
+
+    #[test]
+    fn test_short_reads_and_errors() {
+        let data = random_bytes(50_000, 4);
+        let expected = chunks(&data, 256, 1024, 4096);
+
+        let chunker = Chunker::with_sizes(Trickle { data: &data, reads: 0 }, 256, 1024, 4096);
+        let mut got = Vec::new();
+        let mut errors = 0;
+        for chunk in chunker {
+            match chunk {
+                Ok(chunk) => got.push(chunk),
+                Err(e) => {
+                    assert_eq!(e.kind(), ErrorKind::Other);
+                    errors += 1;
+                }
+            }
+        }
+        assert!(errors > 0);
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_sizes() {
+        Chunker::with_sizes(&b""[..], 10, 5, 20);
+    }
+}
@@ -0,0 +1,377 @@
+//! Hash map shared between threads.
+
+use std::hash::{Hash, BuildHasher};
+use std::sync::{Mutex, MutexGuard};
+use std::vec;
+
+use super::{SimpleHashMap, Entry, RSBuildHasher};
+
+const DEFAULT_SHARDS: usize = 16;
+
+/// Hash map split into independently locked `SimpleHashMap` shards, so
+/// threads working on keys of different shards do not wait for each other.
+///
+/// Every operation locks exactly one shard, which makes `compute` and
+/// `merge` atomic. Operations spanning the whole map, like `len` or
+/// iteration, visit the shards one after another and may or may not see
+/// concurrent updates.
+pub struct ConcurrentHashMap<K, V, S = RSBuildHasher> {
+    shards: Vec<Mutex<SimpleHashMap<K, V, S>>>,
+    hash_builder: S,
+}
+
+/// Weakly consistent iterator over a `ConcurrentHashMap`, see
+/// `ConcurrentHashMap::iter`.
+pub struct Iter<'a, K: 'a, V: 'a, S: 'a> {
+    map: &'a ConcurrentHashMap<K, V, S>,
+    next_shard: usize,
+    current: vec::IntoIter<(K, V)>,
+}
+
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          S: BuildHasher {
+
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        loop {
+            if let Some(item) = self.current.next() {
+                return Some(item);
+            }
+            if self.next_shard == self.map.shards.len() {
+                return None;
+            }
+            let shard = self.map.lock(self.next_shard);
+            self.current = shard.iter().map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>().into_iter();
+            self.next_shard += 1;
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> ConcurrentHashMap<K, V, RSBuildHasher> {
+
+    pub fn new() -> ConcurrentHashMap<K, V, RSBuildHasher> {
+        ConcurrentHashMap::with_shards_and_hasher(DEFAULT_SHARDS, Default::default())
+    }
+
+    /// Creates a map with `shards` shards, rounded up to a power of two.
+    pub fn with_shards(shards: usize) -> ConcurrentHashMap<K, V, RSBuildHasher> {
+        ConcurrentHashMap::with_shards_and_hasher(shards, Default::default())
+    }
+}
+
+impl<K, V, S> Default for ConcurrentHashMap<K, V, S>
+    where K: Hash + Eq,
+          S: BuildHasher + Clone + Default {
+
+    fn default() -> ConcurrentHashMap<K, V, S> {
+        ConcurrentHashMap::with_hasher(Default::default())
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher + Clone {
+
+    pub fn with_hasher(hash_builder: S) -> ConcurrentHashMap<K, V, S> {
+        ConcurrentHashMap::with_shards_and_hasher(DEFAULT_SHARDS, hash_builder)
+    }
+
+    /// Creates a map with `shards` shards, rounded up to a power of two,
+    /// each one using a clone of `hash_builder`.
+    pub fn with_shards_and_hasher(shards: usize, hash_builder: S) -> ConcurrentHashMap<K, V, S> {
+        let shards = (0..shards.max(1).next_power_of_two())
+            .map(|_| Mutex::new(SimpleHashMap::with_hasher(hash_builder.clone())))
+            .collect();
+        ConcurrentHashMap { shards, hash_builder }
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+
+    /// Returns a reference to the map's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Picks the shard from the high bits of the mixed hash, since the low
+    /// bits choose the slot inside the shard.
+    fn shard_of(&self, k: &K) -> usize {
+        let hash = self.hash_builder.hash_one(k);
+        (hash.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize & (self.shards.len() - 1)
+    }
+
+    /// A panic inside a closure passed to `compute` or `merge` poisons the
+    /// lock, but leaves the shard consistent, so the poison is ignored.
+    fn lock(&self, shard: usize) -> MutexGuard<'_, SimpleHashMap<K, V, S>> {
+        self.shards[shard].lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the number of items, summed shard by shard.
+    pub fn len(&self) -> usize {
+        (0..self.shards.len()).map(|i| self.lock(i).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..self.shards.len()).all(|i| self.lock(i).is_empty())
+    }
+
+    /// Returns a clone of the value, since a reference can not outlive the
+    /// shard lock.
+    pub fn get_cloned(&self, k: &K) -> Option<V> where V: Clone {
+        self.lock(self.shard_of(k)).get(k).cloned()
+    }
+
+    pub fn contains_key(&self, k: &K) -> bool {
+        self.lock(self.shard_of(k)).contains_key(k)
+    }
+
+    pub fn insert(&self, k: K, v: V) -> Option<V> {
+        self.lock(self.shard_of(&k)).insert(k, v)
+    }
+
+    pub fn remove(&self, k: &K) -> Option<V> {
+        self.lock(self.shard_of(k)).remove(k)
+    }
+
+    /// Atomically replaces the value of `k` with `f(current value)`, where
+    /// `None` means absent on either side. Returns the previous value.
+    pub fn compute<F>(&self, k: K, f: F) -> Option<V> where F: FnOnce(Option<&V>) -> Option<V> {
+        let mut shard = self.lock(self.shard_of(&k));
+        match shard.entry(k) {
+            Entry::Occupied(mut entry) => match f(Some(entry.get())) {
+                Some(value) => Some(entry.insert(value)),
+                None => Some(entry.remove()),
+            },
+            Entry::Vacant(entry) => {
+                if let Some(value) = f(None) {
+                    entry.insert(value);
+                }
+                None
+            }
+        }
+    }
+
+    /// Atomically inserts `v` if `k` is absent, or combines it into the
+    /// present value with `f`.
+    pub fn merge<F>(&self, k: K, v: V, f: F) where F: FnOnce(&mut V, V) {
+        let mut shard = self.lock(self.shard_of(&k));
+        match shard.entry(k) {
+            Entry::Occupied(mut entry) => f(entry.get_mut(), v),
+            Entry::Vacant(entry) => { entry.insert(v); }
+        }
+    }
+
+    /// Returns a clone of the value of `k`, inserting `f()` first if absent.
+    pub fn get_or_insert_with<F>(&self, k: K, f: F) -> V where F: FnOnce() -> V, V: Clone {
+        self.lock(self.shard_of(&k)).entry(k).or_insert_with(f).clone()
+    }
+
+    /// Calls `f` on every item, holding the lock of one shard at a time.
+    /// `f` must not access the map itself, or it deadlocks.
+    pub fn for_each<F>(&self, mut f: F) where F: FnMut(&K, &V) {
+        for i in 0..self.shards.len() {
+            for (k, v) in self.lock(i).iter() {
+                f(k, v);
+            }
+        }
+    }
+
+    /// Iterates over clones of the items, copying one shard at a time. Items
+    /// changed while iterating may or may not be seen, but every item present
+    /// during the whole iteration is yielded exactly once.
+    pub fn iter(&self) -> Iter<'_, K, V, S> where K: Clone, V: Clone {
+        Iter { map: self, next_shard: 0, current: Vec::new().into_iter() }
+    }
+}
+
+#[cfg(test)]
+mod test_concurrent_map {
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use super::ConcurrentHashMap;
+
+    #[test]
+    fn test_basic_operations() {
+        let map = ConcurrentHashMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert(1, "a"), None);
+        assert_eq!(map.insert(2, "b"), None);
+        assert_eq!(map.insert(1, "c"), Some("a"));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get_cloned(&1), Some("c"));
+        assert!(map.contains_key(&2));
+        assert_eq!(map.remove(&2), Some("b"));
+        assert_eq!(map.remove(&2), None);
+        assert_eq!(map.get_cloned(&2), None);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_shard_count() {
+        assert_eq!(ConcurrentHashMap::<i32, i32>::with_shards(0).shard_count(), 1);
+        assert_eq!(ConcurrentHashMap::<i32, i32>::with_shards(5).shard_count(), 8);
+        assert_eq!(ConcurrentHashMap::<i32, i32>::new().shard_count(), 16);
+    }
+
+    #[test]
+    fn test_keys_spread_over_shards() {
+        let map = ConcurrentHashMap::with_shards(8);
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+        for i in 0..map.shard_count() {
+            let len = map.lock(i).len();
+            assert!(len > 50, "shard {} has {} items", i, len);
+        }
+    }
+
+    #[test]
+    fn test_compute() {
+        let map = ConcurrentHashMap::new();
+        assert_eq!(map.compute(1, |v| v.map(|v| v + 1)), None);
+        assert!(!map.contains_key(&1));
+
+        assert_eq!(map.compute(1, |v| Some(v.map_or(10, |v| v + 1))), None);
+        assert_eq!(map.compute(1, |v| Some(v.map_or(10, |v| v + 1))), Some(10));
+        assert_eq!(map.get_cloned(&1), Some(11));
+
+        assert_eq!(map.compute(1, |_| None), Some(11));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_merge_and_get_or_insert_with() {
+        let map = ConcurrentHashMap::new();
+        map.merge("a", 1, |v, n| *v += n);
+        map.merge("a", 2, |v, n| *v += n);
+        assert_eq!(map.get_cloned(&"a"), Some(3));
+
+        assert_eq!(map.get_or_insert_with("b", || 7), 7);
+        assert_eq!(map.get_or_insert_with("b", || 8), 7);
+    }
+
+    #[test]
+    fn test_iter() {
+        let map = ConcurrentHashMap::new();
+        for i in 0..100 {
+            map.insert(i, i * 2);
+        }
+        let mut items: Vec<_> = map.iter().collect();
+        items.sort();
+        assert_eq!(items, (0..100).map(|i| (i, i * 2)).collect::<Vec<_>>());
+
+        let mut sum = 0;
+        map.for_each(|_, v| sum += *v);
+        assert_eq!(sum, 9900);
+    }
+
+    #[test]
+    fn test_poisoned_shard_stays_usable() {
+        let map = Arc::new(ConcurrentHashMap::with_shards(1));
+        map.insert(1, 1);
+        let other = map.clone();
+        let result = thread::spawn(move || {
+            other.compute(1, |_| panic!("boom"));
+        }).join();
+        assert!(result.is_err());
+        assert_eq!(map.get_cloned(&1), Some(1));
+        map.insert(2, 2);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_disjoint_inserts() {
+        const THREADS: usize = 8;
+        const PER_THREAD: usize = 2000;
+
+        let map = Arc::new(ConcurrentHashMap::new());
+        let handles: Vec<_> = (0..THREADS).map(|t| {
+            let map = map.clone();
+            thread::spawn(move || {
+                for i in 0..PER_THREAD {
+                    let key = t * PER_THREAD + i;
+                    assert_eq!(map.insert(key, t), None);
+                }
+                for i in (0..PER_THREAD).step_by(2) {
+                    assert_eq!(map.remove(&(t * PER_THREAD + i)), Some(t));
+                }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(map.len(), THREADS * PER_THREAD / 2);
+        for key in 0..THREADS * PER_THREAD {
+            let expected = if key % 2 == 1 { Some(key / PER_THREAD) } else { None };
+            assert_eq!(map.get_cloned(&key), expected);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_counters() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 1000;
+        const KEYS: usize = 10;
+
+        let map = Arc::new(ConcurrentHashMap::with_shards(4));
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let handles: Vec<_> = (0..THREADS).map(|t| {
+            let map = map.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                for i in 0..ROUNDS {
+                    let key = (i + t) % KEYS;
+                    if i % 2 == 0 {
+                        map.merge(key, 1, |v, n| *v += n);
+                    } else {
+                        map.compute(key, |v| Some(v.map_or(1, |v| v + 1)));
+                    }
+                }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let total: usize = map.iter().map(|(_, v)| v).sum();
+        assert_eq!(total, THREADS * ROUNDS);
+        assert_eq!(map.len(), KEYS);
+    }
+
+    #[test]
+    fn test_iterate_while_writing() {
+        const STABLE: usize = 500;
+
+        let map = Arc::new(ConcurrentHashMap::new());
+        for i in 0..STABLE {
+            map.insert(i, i);
+        }
+        let writer = {
+            let map = map.clone();
+            thread::spawn(move || {
+                for round in 0..20 {
+                    for i in STABLE..STABLE + 200 {
+                        map.insert(i, round);
+                    }
+                    for i in STABLE..STABLE + 200 {
+                        map.remove(&i);
+                    }
+                }
+            })
+        };
+        for _ in 0..20 {
+            let mut stable: Vec<_> = map.iter().map(|(k, _)| k).filter(|k| *k < STABLE).collect();
+            stable.sort();
+            assert_eq!(stable, (0..STABLE).collect::<Vec<_>>());
+        }
+        writer.join().unwrap();
+        assert_eq!(map.len(), STABLE);
+    }
+}
@@ -0,0 +1,842 @@
+//! Cuckoo hashing: every key may only live in one of two buckets, so a
+//! lookup inspects at most two buckets and a small stash.
+//!
+//! When both buckets of a new key are full, it takes the slot of a random
+//! item, which moves to its other bucket, possibly kicking out another one,
+//! and so on. A chain which does not end within `MAX_KICKS` moves is undone
+//! and the new item goes to the stash. Once the stash is full as well, the
+//! table is rebuilt with other buckets for every key, and grown if it is
+//! dense.
+//!
+//! Refer to https://www.cs.tau.ac.il/~shanir/advanced-seminar-data-structures-2009/bib/pagh01cuckoo.pdf
+//! and, for the filter, https://www.cs.cmu.edu/~dga/papers/cuckoo-conext2014.pdf
+
+use std::hash::{Hash, BuildHasher};
+use std::mem;
+use std::slice;
+use std::iter::{Flatten, FromIterator};
+use std::fmt::{Debug, Formatter, Result};
+use std::marker::PhantomData;
+
+use super::{RSBuildHasher, HashMapError};
+use super::bloom::FilterError;
+use super::hasher::{FnvBuildHasher, mix, xorshift};
+
+/// Slots per bucket. Four slots let the table fill up to 95% before
+/// insertions start to fail.
+const BUCKET_SLOTS: usize = 4;
+
+/// Items which found no slot, looked up after the buckets.
+const STASH_SIZE: usize = 4;
+
+/// Displacements tried before an item goes to the stash.
+const MAX_KICKS: usize = 256;
+
+/// Seeds, and sizes, tried before an item which does not fit is rejected.
+const MAX_REBUILDS: usize = 8;
+
+const MIN_BUCKETS: usize = 8;
+
+/// Number of items a table with `buckets` buckets can hold before it grows,
+/// a load factor of 7/8 keeps the displacement chains short.
+fn usable_capacity(buckets: usize) -> usize {
+    buckets * BUCKET_SLOTS / 8 * 7
+}
+
+/// Number of buckets required to hold `cap` items without growing.
+fn buckets_for(cap: usize) -> usize {
+    if cap == 0 {
+        return 0;
+    }
+    let min_buckets = cap
+        .checked_mul(8)
+        .map(|n| n.div_ceil(7 * BUCKET_SLOTS))
+        .and_then(usize::checked_next_power_of_two)
+        .expect("capacity overflow");
+    min_buckets.max(MIN_BUCKETS)
+}
+
+struct CuckooItem<K, V> {
+    key: K,
+    value: V,
+    hash: u64,
+}
+
+type Bucket<T> = [Option<T>; BUCKET_SLOTS];
+
+fn empty_buckets<T>(buckets: usize) -> Vec<Bucket<T>> {
+    let mut new_buckets = Vec::with_capacity(buckets);
+    new_buckets.resize_with(buckets, || [None, None, None, None]);
+    new_buckets
+}
+
+/// The two buckets of `hash` among `buckets`, from the low bits of the hash
+/// mixed with `seed` and of that mixed once more.
+fn bucket_indexes(hash: u64, seed: u64, buckets: usize) -> (usize, usize) {
+    let mask = buckets - 1;
+    let hash = mix(hash ^ seed);
+    (hash as usize & mask, mix(hash) as usize & mask)
+}
+
+/// Returns a free slot of `bucket`.
+fn free_slot<T>(bucket: &Bucket<T>) -> Option<usize> {
+    bucket.iter().position(|slot| slot.is_none())
+}
+
+/// Inserts `item` into one of its buckets, kicking other items to their
+/// other bucket if necessary, and returns its slot. The item keeps the
+/// first slot it takes, the kicks never move it again. If `MAX_KICKS` moves
+/// do not free a slot, they are undone and `item` is handed back.
+fn displace<T, F>(buckets: &mut [Bucket<T>], item: T, indexes: F, rng: &mut u64) -> std::result::Result<(usize, usize), T>
+    where F: Fn(&T) -> (usize, usize) {
+
+    let (b1, b2) = indexes(&item);
+    for &b in &[b1, b2] {
+        if let Some(s) = free_slot(&buckets[b]) {
+            buckets[b][s] = Some(item);
+            return Ok((b, s));
+        }
+    }
+
+    let bucket = if xorshift(rng) & 1 == 0 { b1 } else { b2 };
+    let first = (bucket, (xorshift(rng) % BUCKET_SLOTS as u64) as usize);
+    let mut path = vec![first];
+    let mut item = buckets[first.0][first.1].replace(item).expect("full bucket has an empty slot");
+    for _ in 0..MAX_KICKS {
+        let (v1, v2) = indexes(&item);
+        let last = path[path.len() - 1].0;
+        let bucket = if v1 == last { v2 } else { v1 };
+        if let Some(s) = free_slot(&buckets[bucket]) {
+            buckets[bucket][s] = Some(item);
+            return Ok(first);
+        }
+        let mut s = (xorshift(rng) % BUCKET_SLOTS as u64) as usize;
+        if (bucket, s) == first {
+            s = (s + 1) % BUCKET_SLOTS;
+        }
+        path.push((bucket, s));
+        item = buckets[bucket][s].replace(item).expect("full bucket has an empty slot");
+    }
+
+    // every slot on the path gets back the item it had
+    for &(b, s) in path.iter().rev() {
+        item = buckets[b][s].replace(item).expect("full bucket has an empty slot");
+    }
+    Err(item)
+}
+
+/// Where the items of a rebuilt table go, by their index in the order of
+/// `Iter`.
+struct Layout {
+    buckets: Vec<Bucket<usize>>,
+    stash: Vec<usize>,
+    seed: u64,
+}
+
+/// Places items with `hashes` into `buckets` buckets by the same
+/// displacements as insertions, or returns None if more than `STASH_SIZE`
+/// of them find no slot.
+fn layout(hashes: &[u64], buckets: usize, seed: u64, rng: &mut u64) -> Option<Layout> {
+    let mut table = empty_buckets(buckets);
+    let mut stash = Vec::new();
+    for i in 0..hashes.len() {
+        if let Err(i) = displace(&mut table, i, |&i| bucket_indexes(hashes[i], seed, buckets), rng) {
+            if stash.len() == STASH_SIZE {
+                return None;
+            }
+            stash.push(i);
+        }
+    }
+    Some(Layout { buckets: table, stash, seed })
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Location {
+    Bucket(usize, usize),
+    Stash(usize),
+}
+
+/// Hash map using cuckoo hashing over buckets of four slots.
+///
+/// The two buckets of a key come from its hash mixed with a seed. When
+/// neither they nor the stash of `STASH_SIZE` items have room, the table is
+/// rebuilt with a new seed, and grown as well if it is dense. Keys with the
+/// same full hash share both buckets under every seed, so at most 12 of
+/// them fit: `try_insert` then fails with `HashMapError::TooManyCollisions`
+/// and `insert` panics.
+pub struct CuckooHashMap<K, V, S = RSBuildHasher> {
+    buckets: Vec<Bucket<CuckooItem<K, V>>>,
+    stash: Vec<CuckooItem<K, V>>,
+    len: usize,
+    seed: u64,
+    rng: u64,
+    hash_builder: S,
+}
+
+pub struct Iter<'a, K: 'a, V: 'a> {
+    slots: Flatten<slice::Iter<'a, Bucket<CuckooItem<K, V>>>>,
+    stash: slice::Iter<'a, CuckooItem<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        match self.slots.by_ref().flatten().next() {
+            Some(item) => Some((&item.key, &item.value)),
+            None => self.stash.next().map(|item| (&item.key, &item.value)),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> CuckooHashMap<K, V, RSBuildHasher> {
+
+    pub fn new() -> CuckooHashMap<K, V, RSBuildHasher> {
+        Default::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> CuckooHashMap<K, V, RSBuildHasher> {
+        CuckooHashMap::with_capacity_and_hasher(capacity, Default::default())
+    }
+}
+
+impl<K, V, S> Default for CuckooHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher + Default {
+    fn default() -> CuckooHashMap<K, V, S> {
+        CuckooHashMap::with_hasher(Default::default())
+    }
+}
+
+impl<K, V, S> CuckooHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+
+    pub fn with_hasher(hash_builder: S) -> CuckooHashMap<K, V, S> {
+        CuckooHashMap::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> CuckooHashMap<K, V, S> {
+        CuckooHashMap {
+            buckets: empty_buckets(buckets_for(capacity)),
+            stash: Vec::new(),
+            len: 0,
+            seed: 0,
+            rng: 0x2545_f491_4f6c_dd1d,
+            hash_builder,
+        }
+    }
+
+    /// Returns a reference to the map's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Returns the number of items the map can hold without growing.
+    pub fn capacity(&self) -> usize {
+        usable_capacity(self.buckets.len())
+    }
+
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { slots: self.buckets.iter().flatten(), stash: self.stash.iter() }
+    }
+
+    fn bucket_indexes(&self, hash: u64) -> (usize, usize) {
+        bucket_indexes(hash, self.seed, self.buckets.len())
+    }
+
+    /// Finds room for the items, and one more item with hash `extra`, in at
+    /// least `buckets` buckets. Each failed attempt picks a new seed, and
+    /// doubles the buckets if they were more than half full; a sparse table
+    /// fails because keys share buckets, which only a new seed fixes.
+    fn plan(&mut self, buckets: usize, extra: Option<u64>) -> Option<Layout> {
+        let hashes: Vec<u64> = self.buckets.iter().flatten().flatten()
+            .chain(self.stash.iter())
+            .map(|item| item.hash)
+            .chain(extra)
+            .collect();
+        let mut buckets = buckets;
+        let mut seed = self.seed;
+        for _ in 0..MAX_REBUILDS {
+            if let Some(layout) = layout(&hashes, buckets, seed, &mut self.rng) {
+                return Some(layout);
+            }
+            if hashes.len() * 2 > buckets * BUCKET_SLOTS {
+                buckets *= 2;
+            }
+            seed = xorshift(&mut self.rng);
+        }
+        None
+    }
+
+    /// Moves the items, and `extra` last, to where `layout` says, and
+    /// returns the location of `extra`.
+    fn rebuild(&mut self, layout: Layout, extra: Option<CuckooItem<K, V>>) -> Option<Location> {
+        let old_buckets = mem::take(&mut self.buckets);
+        let old_stash = mem::take(&mut self.stash);
+        let has_extra = extra.is_some();
+        let mut items: Vec<_> = old_buckets.into_iter().flatten().flatten()
+            .chain(old_stash)
+            .chain(extra)
+            .map(Some)
+            .collect();
+        let extra_index = if has_extra { Some(items.len() - 1) } else { None };
+        let mut location = None;
+        self.seed = layout.seed;
+        self.buckets = empty_buckets(layout.buckets.len());
+        for (b, bucket) in layout.buckets.into_iter().enumerate() {
+            for (s, &index) in bucket.iter().enumerate() {
+                if let Some(index) = index {
+                    self.buckets[b][s] = items[index].take();
+                    if Some(index) == extra_index {
+                        location = Some(Location::Bucket(b, s));
+                    }
+                }
+            }
+        }
+        for index in layout.stash {
+            if Some(index) == extra_index {
+                location = Some(Location::Stash(self.stash.len()));
+            }
+            self.stash.push(items[index].take().expect("item is laid out twice"));
+        }
+        location
+    }
+
+    /// Puts an item whose key is not in the map yet into one of its buckets,
+    /// or the stash, and hands it back if both are full.
+    fn place(&mut self, item: CuckooItem<K, V>) -> std::result::Result<Location, CuckooItem<K, V>> {
+        let (seed, buckets) = (self.seed, self.buckets.len());
+        match displace(&mut self.buckets, item, |item| bucket_indexes(item.hash, seed, buckets), &mut self.rng) {
+            Ok((b, s)) => Ok(Location::Bucket(b, s)),
+            Err(item) if self.stash.len() < STASH_SIZE => {
+                self.stash.push(item);
+                Ok(Location::Stash(self.stash.len() - 1))
+            }
+            Err(item) => Err(item),
+        }
+    }
+
+    /// Moves stashed items back into their buckets, where slots have freed up.
+    fn unstash(&mut self) {
+        let mut i = 0;
+        while i < self.stash.len() {
+            let (b1, b2) = self.bucket_indexes(self.stash[i].hash);
+            let free = free_slot(&self.buckets[b1]).map(|s| (b1, s)).or_else(|| free_slot(&self.buckets[b2]).map(|s| (b2, s)));
+            match free {
+                Some((b, s)) => self.buckets[b][s] = Some(self.stash.swap_remove(i)),
+                None => i += 1,
+            }
+        }
+    }
+
+    /// Looks `k` up in its two buckets and the stash, and nowhere else.
+    fn find(&self, k: &K, hash: u64) -> Option<Location> {
+        if !self.buckets.is_empty() {
+            let (b1, b2) = self.bucket_indexes(hash);
+            for &b in &[b1, b2] {
+                let found = self.buckets[b].iter()
+                    .position(|slot| slot.as_ref().is_some_and(|item| item.hash == hash && item.key == *k));
+                if let Some(s) = found {
+                    return Some(Location::Bucket(b, s));
+                }
+            }
+        }
+        self.stash.iter().position(|item| item.hash == hash && item.key == *k).map(Location::Stash)
+    }
+
+    fn item(&self, location: Location) -> &CuckooItem<K, V> {
+        match location {
+            Location::Bucket(b, s) => self.buckets[b][s].as_ref().expect("found slot is empty"),
+            Location::Stash(i) => &self.stash[i],
+        }
+    }
+
+    fn item_mut(&mut self, location: Location) -> &mut CuckooItem<K, V> {
+        match location {
+            Location::Bucket(b, s) => self.buckets[b][s].as_mut().expect("found slot is empty"),
+            Location::Stash(i) => &mut self.stash[i],
+        }
+    }
+
+    pub fn get(&self, k: &K) -> Option<&V> {
+        let location = self.find(k, self.hash_builder.hash_one(k))?;
+        Some(&self.item(location).value)
+    }
+
+    pub fn get_mut(&mut self, k: &K) -> Option<&mut V> {
+        let location = self.find(k, self.hash_builder.hash_one(k))?;
+        Some(&mut self.item_mut(location).value)
+    }
+
+    pub fn contains_key(&self, k: &K) -> bool {
+        self.get(k).is_some()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the key can not be placed, see `try_insert`.
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let hash = self.hash_builder.hash_one(&k);
+        if let Some(location) = self.find(&k, hash) {
+            return Some(mem::replace(&mut self.item_mut(location).value, v));
+        }
+        if let Err(e) = self.insert_new(CuckooItem { key: k, value: v, hash }) {
+            panic!("{}", e);
+        }
+        None
+    }
+
+    /// Inserts a key-value pair and returns a mutable reference to the
+    /// value, unless the key is in the map already.
+    ///
+    /// Fails with `HashMapError::TooManyCollisions` when more keys share
+    /// buckets than any seed or size separates, which takes 12 keys with the
+    /// same full hash. On error the items of the map are unchanged and `v`
+    /// is dropped.
+    pub fn try_insert(&mut self, k: K, v: V) -> std::result::Result<&mut V, HashMapError> {
+        let hash = self.hash_builder.hash_one(&k);
+        if self.find(&k, hash).is_some() {
+            return Err(HashMapError::DuplicateKey);
+        }
+        let location = self.insert_new(CuckooItem { key: k, value: v, hash })?;
+        Ok(&mut self.item_mut(location).value)
+    }
+
+    /// Inserts an item whose key is not in the map yet, growing or
+    /// rebuilding the table when needed.
+    fn insert_new(&mut self, item: CuckooItem<K, V>) -> std::result::Result<Location, HashMapError> {
+        if self.len >= self.capacity() {
+            // a table which can not grow yet may still have room
+            let buckets = (self.buckets.len() * 2).max(MIN_BUCKETS);
+            if let Some(layout) = self.plan(buckets, None) {
+                self.rebuild(layout, None);
+            }
+        }
+        let location = match self.place(item) {
+            Ok(location) => location,
+            Err(item) => match self.plan(self.buckets.len(), Some(item.hash)) {
+                Some(layout) => self.rebuild(layout, Some(item)).expect("laid out item is missing"),
+                None => return Err(HashMapError::TooManyCollisions),
+            },
+        };
+        self.len += 1;
+        Ok(location)
+    }
+
+    pub fn remove(&mut self, k: &K) -> Option<V> {
+        let location = self.find(k, self.hash_builder.hash_one(k))?;
+        self.len -= 1;
+        let item = match location {
+            Location::Bucket(b, s) => {
+                let item = self.buckets[b][s].take().expect("found slot is empty");
+                self.unstash();
+                item
+            }
+            Location::Stash(i) => self.stash.swap_remove(i),
+        };
+        Some(item.value)
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a CuckooHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// # Panics
+///
+/// Panics if a key can not be placed, see `CuckooHashMap::try_insert`.
+impl<K, V, S> FromIterator<(K, V)> for CuckooHashMap<K, V, S>
+    where K: Hash + Eq,
+          S: BuildHasher + Default {
+
+    fn from_iter<T: IntoIterator<Item=(K, V)>>(iter: T) -> CuckooHashMap<K, V, S> {
+        let mut map = CuckooHashMap::default();
+        for (k, v) in iter {
+            map.insert(k, v);
+        }
+        map
+    }
+}
+
+impl<K, V, S> Debug for CuckooHashMap<K, V, S>
+    where K: Hash + Eq + Debug, V: Debug, S: BuildHasher {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Cuckoo filter: a cuckoo table of 16 bit fingerprints. Unlike a Bloom
+/// filter it supports removal, and it needs fewer bits per item below a
+/// false positive rate of about 3%; with four slots per bucket the rate is
+/// at most 8 / 2^16 = 0.012%.
+///
+/// The other bucket of a fingerprint is its bucket XOR the hash of the
+/// fingerprint, so it is found without the item. The filter can not grow,
+/// since the items are gone: when a displacement chain does not end, its
+/// last fingerprint is kept aside and later insertions fail with
+/// `FilterError::Full`.
+///
+/// Inserting an item twice stores two fingerprints, and removing an item
+/// which was never inserted may remove another item with the same
+/// fingerprint.
+pub struct CuckooFilter<T: ?Sized, S = FnvBuildHasher> {
+    buckets: Vec<[u16; BUCKET_SLOTS]>,
+    victim: Option<(usize, u16)>,
+    len: usize,
+    rng: u64,
+    hash_builder: S,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash + ?Sized> CuckooFilter<T, FnvBuildHasher> {
+
+    /// Creates a filter with room for at least `capacity` items.
+    pub fn new(capacity: usize) -> CuckooFilter<T, FnvBuildHasher> {
+        CuckooFilter::with_hasher(capacity, Default::default())
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> CuckooFilter<T, S> {
+
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> CuckooFilter<T, S> {
+        // stay below the 95% load at which insertions start to fail
+        let buckets = capacity
+            .checked_mul(20)
+            .map(|n| n.div_ceil(19 * BUCKET_SLOTS))
+            .and_then(usize::checked_next_power_of_two)
+            .expect("capacity overflow")
+            .max(1);
+        CuckooFilter {
+            buckets: vec![[0; BUCKET_SLOTS]; buckets],
+            victim: None,
+            len: 0,
+            rng: 0x2545_f491_4f6c_dd1d,
+            hash_builder,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of fingerprints the buckets hold.
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SLOTS
+    }
+
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Returns the nonzero fingerprint of `item` and its first bucket.
+    fn fingerprint(&self, item: &T) -> (u16, usize) {
+        let hash = mix(self.hash_builder.hash_one(item));
+        let fingerprint = ((hash >> 48) as u16).max(1);
+        (fingerprint, hash as usize & (self.buckets.len() - 1))
+    }
+
+    fn other_bucket(&self, bucket: usize, fingerprint: u16) -> usize {
+        (bucket ^ mix(fingerprint as u64) as usize) & (self.buckets.len() - 1)
+    }
+
+    fn put(&mut self, bucket: usize, fingerprint: u16) -> bool {
+        match self.buckets[bucket].iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fingerprint;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stores `fingerprint` in `bucket` or its other bucket, kicking other
+    /// fingerprints to their other bucket if necessary. Returns the one left
+    /// without a slot after `MAX_KICKS` moves, with its bucket.
+    fn place(&mut self, bucket: usize, fingerprint: u16) -> Option<(usize, u16)> {
+        let other = self.other_bucket(bucket, fingerprint);
+        if self.put(bucket, fingerprint) || self.put(other, fingerprint) {
+            return None;
+        }
+
+        let mut fingerprint = fingerprint;
+        let mut bucket = if xorshift(&mut self.rng) & 1 == 0 { bucket } else { other };
+        for _ in 0..MAX_KICKS {
+            let s = (xorshift(&mut self.rng) % BUCKET_SLOTS as u64) as usize;
+            fingerprint = mem::replace(&mut self.buckets[bucket][s], fingerprint);
+            bucket = self.other_bucket(bucket, fingerprint);
+            if self.put(bucket, fingerprint) {
+                return None;
+            }
+        }
+        Some((bucket, fingerprint))
+    }
+
+    /// Adds `item`, fails if the filter is full.
+    pub fn insert(&mut self, item: &T) -> std::result::Result<(), FilterError> {
+        if self.victim.is_some() {
+            return Err(FilterError::Full);
+        }
+        let (fingerprint, bucket) = self.fingerprint(item);
+        self.len += 1;
+        // the item is in even if some fingerprint is left over, it is kept
+        // aside and looked up with the buckets
+        self.victim = self.place(bucket, fingerprint);
+        Ok(())
+    }
+
+    /// Returns false if `item` is not in the filter, true if it probably is.
+    pub fn contains(&self, item: &T) -> bool {
+        let (fingerprint, b1) = self.fingerprint(item);
+        let b2 = self.other_bucket(b1, fingerprint);
+        self.buckets[b1].contains(&fingerprint)
+            || self.buckets[b2].contains(&fingerprint)
+            || self.victim.is_some_and(|(b, f)| f == fingerprint && (b == b1 || b == b2))
+    }
+
+    /// Removes one insertion of `item`, returns false if it is not in the
+    /// filter.
+    pub fn remove(&mut self, item: &T) -> bool {
+        let (fingerprint, b1) = self.fingerprint(item);
+        let b2 = self.other_bucket(b1, fingerprint);
+        if self.victim.is_some_and(|(b, f)| f == fingerprint && (b == b1 || b == b2)) {
+            self.victim = None;
+            self.len -= 1;
+            return true;
+        }
+        for &b in &[b1, b2] {
+            if let Some(slot) = self.buckets[b].iter_mut().find(|slot| **slot == fingerprint) {
+                *slot = 0;
+                self.len -= 1;
+                if let Some((bucket, fingerprint)) = self.victim.take() {
+                    self.victim = self.place(bucket, fingerprint);
+                }
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test_cuckoo {
+    use std::collections::HashMap;
+    use std::hash::{BuildHasherDefault, Hasher};
+    use super::{CuckooHashMap, CuckooFilter, BUCKET_SLOTS, STASH_SIZE};
+    use crate::bloom::FilterError;
+    use crate::hasher::xorshift;
+    use crate::HashMapError;
+    use crate::test_util::ConstantHasher;
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut m = CuckooHashMap::new();
+        assert_eq!(m.capacity(), 0);
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.insert(1, 2), None);
+        assert_eq!(m.insert(2, 4), None);
+        assert_eq!(m.insert(1, 3), Some(2));
+        assert_eq!(m.len(), 2);
+        assert_eq!(*m.get(&1).unwrap(), 3);
+        *m.get_mut(&2).unwrap() += 1;
+        assert_eq!(m.get(&2), Some(&5));
+        assert_eq!(m.remove(&1), Some(3));
+        assert_eq!(m.remove(&1), None);
+        assert!(!m.contains_key(&1));
+        assert_eq!(m.len(), 1);
+    }
+
+    #[test]
+    fn test_grows_and_keeps_items() {
+        let mut m = CuckooHashMap::new();
+        for i in 0..10_000 {
+            m.insert(i, i * 2);
+        }
+        assert_eq!(m.len(), 10_000);
+        assert!(m.capacity() >= 10_000);
+        assert!(m.stash.len() <= STASH_SIZE);
+        for i in 0..10_000 {
+            assert_eq!(m.get(&i), Some(&(i * 2)));
+        }
+        assert_eq!(m.iter().count(), 10_000);
+    }
+
+    #[test]
+    fn test_with_capacity_does_not_grow() {
+        let mut m = CuckooHashMap::with_capacity(1000);
+        let buckets = m.buckets.len();
+        assert!(m.capacity() >= 1000);
+        for i in 0..1000 {
+            m.insert(i, ());
+        }
+        assert_eq!(m.buckets.len(), buckets);
+    }
+
+    #[test]
+    fn test_items_stay_in_their_buckets() {
+        let m: CuckooHashMap<u32, ()> = (0..5000).map(|i| (i, ())).collect();
+        for (b, bucket) in m.buckets.iter().enumerate() {
+            for item in bucket.iter().flatten() {
+                let (b1, b2) = m.bucket_indexes(item.hash);
+                assert!(b == b1 || b == b2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_agrees_with_std() {
+        let mut m = CuckooHashMap::new();
+        let mut std = HashMap::new();
+        let mut state = 0x1234_5678u64;
+        for _ in 0..50_000 {
+            xorshift(&mut state);
+            let key = state % 2000;
+            if state.is_multiple_of(3) {
+                assert_eq!(m.remove(&key), std.remove(&key));
+            } else {
+                assert_eq!(m.insert(key, state), std.insert(key, state));
+            }
+            assert_eq!(m.len(), std.len());
+        }
+        for (k, v) in &std {
+            assert_eq!(m.get(k), Some(v));
+        }
+    }
+
+    #[test]
+    fn test_colliding_keys_use_stash() {
+        let mut m: CuckooHashMap<u32, u32, BuildHasherDefault<ConstantHasher>> = CuckooHashMap::default();
+        let n = 2 * BUCKET_SLOTS + STASH_SIZE;
+        for i in 0..n as u32 {
+            assert_eq!(*m.try_insert(i, i).unwrap(), i);
+        }
+        assert_eq!(m.stash.len(), STASH_SIZE);
+
+        // no seed or size separates equal hashes, and the stash stays bounded
+        let buckets = m.buckets.len();
+        assert_eq!(m.try_insert(n as u32, 0), Err(HashMapError::TooManyCollisions));
+        assert_eq!(m.try_insert(0, 0), Err(HashMapError::DuplicateKey));
+        assert_eq!(m.len(), n);
+        assert_eq!(m.stash.len(), STASH_SIZE);
+        assert!(m.buckets.len() <= buckets * 2);
+        for i in 0..n as u32 {
+            assert_eq!(m.get(&i), Some(&i));
+        }
+
+        // removing from a bucket pulls an item out of the stash
+        assert_eq!(m.remove(&0), Some(0));
+        assert_eq!(m.stash.len(), STASH_SIZE - 1);
+        for i in 1..n as u32 {
+            assert_eq!(m.remove(&i), Some(i));
+        }
+        assert!(m.is_empty());
+        assert!(m.stash.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "too many keys collide")]
+    fn test_insert_panics_on_colliding_keys() {
+        let mut m: CuckooHashMap<u32, u32, BuildHasherDefault<ConstantHasher>> = CuckooHashMap::default();
+        for i in 0..=(2 * BUCKET_SLOTS + STASH_SIZE) as u32 {
+            m.insert(i, i);
+        }
+    }
+
+    /// Only 64 distinct hashes, so many keys share buckets under any seed.
+    #[derive(Default)]
+    struct NarrowHasher(u64);
+
+    impl Hasher for NarrowHasher {
+        fn finish(&self) -> u64 {
+            self.0 % 64
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = self.0.wrapping_mul(31).wrapping_add(b as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stash_stays_bounded() {
+        let mut m: CuckooHashMap<u32, u32, BuildHasherDefault<NarrowHasher>> = CuckooHashMap::default();
+        let mut inserted = Vec::new();
+        for i in 0..2000u32 {
+            if m.try_insert(i, i).is_ok() {
+                inserted.push(i);
+            }
+            assert!(m.stash.len() <= STASH_SIZE);
+        }
+        assert!(inserted.len() >= 64 * 2 * BUCKET_SLOTS / 2);
+        assert_eq!(m.len(), inserted.len());
+        for i in &inserted {
+            assert_eq!(m.get(i), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut m = CuckooHashMap::new();
+        m.insert(1, 2);
+        assert_eq!(format!("{:?}", m), "{1: 2}");
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut filter = CuckooFilter::new(10_000);
+        for i in 0..10_000u32 {
+            filter.insert(&i).unwrap();
+        }
+        assert_eq!(filter.len(), 10_000);
+        assert!((0..10_000u32).all(|i| filter.contains(&i)));
+
+        let false_positives = (10_000..110_000u32).filter(|i| filter.contains(i)).count();
+        assert!(false_positives < 50, "{}", false_positives);
+
+        for i in (0..10_000u32).step_by(2) {
+            assert!(filter.remove(&i));
+        }
+        assert_eq!(filter.len(), 5000);
+        assert!((1..10_000u32).step_by(2).all(|i| filter.contains(&i)));
+        let remaining = (0..10_000u32).step_by(2).filter(|i| filter.contains(i)).count();
+        assert!(remaining < 10, "{}", remaining);
+    }
+
+    #[test]
+    fn test_filter_duplicates() {
+        let mut filter = CuckooFilter::new(100);
+        filter.insert("x").unwrap();
+        filter.insert("x").unwrap();
+        assert!(filter.remove("x"));
+        assert!(filter.contains("x"));
+        assert!(filter.remove("x"));
+        assert!(!filter.contains("x"));
+        assert!(!filter.remove("x"));
+    }
+
+    #[test]
+    fn test_filter_full() {
+        let mut filter = CuckooFilter::new(64);
+        let capacity = filter.capacity() as u32;
+        let mut inserted = 0;
+        while filter.insert(&inserted).is_ok() {
+            inserted += 1;
+            assert!(inserted <= capacity + 1);
+        }
+        assert_eq!(filter.insert(&inserted), Err(FilterError::Full));
+        assert!(inserted as f64 > capacity as f64 * 0.8, "{} of {}", inserted, capacity);
+        // nothing inserted got lost
+        assert!((0..inserted).all(|i| filter.contains(&i)));
+
+        // removing makes room again
+        for i in 0..inserted / 10 {
+            assert!(filter.remove(&i));
+        }
+        assert!((inserted / 10..inserted).all(|i| filter.contains(&i)));
+        assert!(filter.insert(&inserted).is_ok());
+        assert!(filter.contains(&inserted));
+    }
+}
@@ -0,0 +1,533 @@
+//! Checksums and message digests: CRC32, MD5, SHA-1 and SHA-256.
+//!
+//! MD5 and SHA-1 are broken as cryptographic hashes. They are here for
+//! checksums and protocols which still require them, such as Content-MD5.
+
+use std::fmt::Write;
+use std::hash::Hasher;
+
+/// Streaming message digest. Feeding the message in several `update`
+/// calls gives the same digest as a single call with all of it.
+pub trait Digest {
+
+    /// Feeds `data` into the digest.
+    fn update(&mut self, data: &[u8]);
+
+    /// Digest of everything fed since creation or the last `reset`. The
+    /// state is left untouched, so more data can follow.
+    fn finalize(&self) -> Vec<u8>;
+
+    /// Starts over with an empty message.
+    fn reset(&mut self);
+
+    /// Bytes of the digest.
+    fn output_size(&self) -> usize;
+
+    /// Bytes the digest consumes at once, 64 for the MD5 and SHA families.
+    fn block_size(&self) -> usize;
+
+    /// Digest in lowercase hex.
+    fn hex_digest(&self) -> String {
+        to_hex(&self.finalize())
+    }
+
+    /// Digest of `data` with a fresh instance.
+    fn digest(data: &[u8]) -> Vec<u8> where Self: Default + Sized {
+        let mut digest = Self::default();
+        digest.update(data);
+        digest.finalize()
+    }
+}
+
+/// Adapter running a `Hasher` as a `Digest`, the output being `finish` in
+/// big endian. Only as strong as the hasher, which is rarely enough against
+/// an adversary.
+#[derive(Clone, Default)]
+pub struct HasherDigest<H>(H);
+
+impl<H: Hasher + Default> Digest for HasherDigest<H> {
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.write(data);
+    }
+
+    fn finalize(&self) -> Vec<u8> {
+        self.0.finish().to_be_bytes().to_vec()
+    }
+
+    fn reset(&mut self) {
+        self.0 = H::default();
+    }
+
+    fn output_size(&self) -> usize {
+        8
+    }
+
+    fn block_size(&self) -> usize {
+        1
+    }
+}
+
+/// Lowercase hex of `bytes`, two digits per byte.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+/// Table driven reflected CRC32 of `poly`, one byte per lookup.
+const fn crc32_table(poly: u32) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { crc >> 1 ^ poly } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static IEEE_TABLE: [u32; 256] = crc32_table(0xEDB8_8320);
+static CASTAGNOLI_TABLE: [u32; 256] = crc32_table(0x82F6_3B78);
+
+/// CRC32 checksum, either the IEEE 802.3 one of zip and PNG (the default)
+/// or Castagnoli's CRC32C of iSCSI and ext4.
+/// The digest is the checksum in big endian.
+#[derive(Clone)]
+pub struct Crc32 {
+    table: &'static [u32; 256],
+    crc: u32,
+}
+
+impl Crc32 {
+
+    pub fn new() -> Crc32 {
+        Crc32 { table: &IEEE_TABLE, crc: !0 }
+    }
+
+    pub fn castagnoli() -> Crc32 {
+        Crc32 { table: &CASTAGNOLI_TABLE, crc: !0 }
+    }
+
+    /// Checksum of everything fed so far.
+    pub fn value(&self) -> u32 {
+        !self.crc
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+impl Digest for Crc32 {
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = self.table[((self.crc ^ byte as u32) & 0xff) as usize] ^ self.crc >> 8;
+        }
+    }
+
+    fn finalize(&self) -> Vec<u8> {
+        self.value().to_be_bytes().to_vec()
+    }
+
+    fn reset(&mut self) {
+        self.crc = !0;
+    }
+
+    fn output_size(&self) -> usize {
+        4
+    }
+
+    fn block_size(&self) -> usize {
+        1
+    }
+}
+
+const BLOCK_SIZE: usize = 64;
+
+/// Message buffering and padding shared by MD5, SHA-1 and SHA-256, which
+/// all compress 64 byte blocks and end the message with its length in bits.
+#[derive(Clone)]
+struct BlockBuffer {
+    block: [u8; BLOCK_SIZE],
+    filled: usize,
+    /// Bytes fed so far.
+    len: u64,
+}
+
+impl BlockBuffer {
+
+    fn new() -> BlockBuffer {
+        BlockBuffer { block: [0; BLOCK_SIZE], filled: 0, len: 0 }
+    }
+
+    /// Buffers `data`, handing every completed block to `compress`.
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8; BLOCK_SIZE])) {
+        self.len = self.len.wrapping_add(data.len() as u64);
+        while !data.is_empty() {
+            let n = (BLOCK_SIZE - self.filled).min(data.len());
+            self.block[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+            self.filled += n;
+            data = &data[n..];
+            if self.filled == BLOCK_SIZE {
+                compress(&self.block);
+                self.filled = 0;
+            }
+        }
+    }
+
+    /// Appends the `0x80` byte, zeros and the bit length, which is little
+    /// endian for MD5 and big endian for SHA.
+    fn pad(mut self, big_endian: bool, mut compress: impl FnMut(&[u8; BLOCK_SIZE])) {
+        let bits = self.len.wrapping_mul(8);
+        self.block[self.filled] = 0x80;
+        self.block[self.filled + 1..].iter_mut().for_each(|b| *b = 0);
+        if self.filled + 1 > BLOCK_SIZE - 8 {
+            compress(&self.block);
+            self.block = [0; BLOCK_SIZE];
+        }
+        let length = if big_endian { bits.to_be_bytes() } else { bits.to_le_bytes() };
+        self.block[BLOCK_SIZE - 8..].copy_from_slice(&length);
+        compress(&self.block);
+    }
+}
+
+/// Declares the `Digest` impl of a 64 byte block hash with `state` words,
+/// written out in `$to_bytes` order after padding.
+macro_rules! block_digest {
+    ($name:ident, $init:expr, $compress:ident, $big_endian:expr, $to_bytes:ident, $output_size:expr) => {
+        impl $name {
+            pub fn new() -> $name {
+                $name { state: $init, buffer: BlockBuffer::new() }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> $name {
+                $name::new()
+            }
+        }
+
+        impl Digest for $name {
+
+            fn update(&mut self, data: &[u8]) {
+                let state = &mut self.state;
+                self.buffer.update(data, |block| $compress(state, block));
+            }
+
+            fn finalize(&self) -> Vec<u8> {
+                let mut state = self.state;
+                self.buffer.clone().pad($big_endian, |block| $compress(&mut state, block));
+                state.iter().flat_map(|word| word.$to_bytes().to_vec()).collect()
+            }
+
+            fn reset(&mut self) {
+                *self = $name::new();
+            }
+
+            fn output_size(&self) -> usize {
+                $output_size
+            }
+
+            fn block_size(&self) -> usize {
+                BLOCK_SIZE
+            }
+        }
+    };
+}
+
+/// MD5, RFC 1321.
+#[derive(Clone)]
+pub struct Md5 {
+    state: [u32; 4],
+    buffer: BlockBuffer,
+}
+
+const MD5_INIT: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+/// `floor(abs(sin(i + 1)) * 2^32)`.
+const MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// Left rotations of each round, four per group of sixteen steps.
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+fn md5_compress(state: &mut [u32; 4], block: &[u8; BLOCK_SIZE]) {
+    let mut m = [0u32; 16];
+    for (word, bytes) in m.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    let [mut a, mut b, mut c, mut d] = *state;
+    for i in 0..64 {
+        let (f, g) = match i / 16 {
+            0 => (b & c | !b & d, i),
+            1 => (d & b | !d & c, (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), 7 * i % 16),
+        };
+        let rotated = a.wrapping_add(f).wrapping_add(MD5_K[i]).wrapping_add(m[g])
+            .rotate_left(MD5_SHIFTS[i / 16 * 4 + i % 4]);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(rotated);
+    }
+    for (word, value) in state.iter_mut().zip(&[a, b, c, d]) {
+        *word = word.wrapping_add(*value);
+    }
+}
+
+block_digest!(Md5, MD5_INIT, md5_compress, false, to_le_bytes, 16);
+
+/// SHA-1, FIPS 180-4.
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    buffer: BlockBuffer,
+}
+
+const SHA1_INIT: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+
+fn sha1_compress(state: &mut [u32; 5], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0u32; 80];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (i, &word) in w.iter().enumerate() {
+        let (f, k) = match i / 20 {
+            0 => (b & c | !b & d, 0x5a82_7999),
+            1 => (b ^ c ^ d, 0x6ed9_eba1),
+            2 => (b & c | b & d | c & d, 0x8f1b_bcdc),
+            _ => (b ^ c ^ d, 0xca62_c1d6),
+        };
+        let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+    for (word, value) in state.iter_mut().zip(&[a, b, c, d, e]) {
+        *word = word.wrapping_add(*value);
+    }
+}
+
+block_digest!(Sha1, SHA1_INIT, sha1_compress, true, to_be_bytes, 20);
+
+/// SHA-256, FIPS 180-4.
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: BlockBuffer,
+}
+
+/// First 32 bits of the fractional parts of the square roots of the first
+/// eight primes.
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// First 32 bits of the fractional parts of the cube roots of the first
+/// 64 primes.
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0u32; 64];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ w[i - 15] >> 3;
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ w[i - 2] >> 10;
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (&word, &k) in w.iter().zip(&SHA256_K) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = e & f ^ !e & g;
+        let temp1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(k).wrapping_add(word);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = a & b ^ a & c ^ b & c;
+        let temp2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+    for (word, value) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(*value);
+    }
+}
+
+block_digest!(Sha256, SHA256_INIT, sha256_compress, true, to_be_bytes, 32);
+
+#[cfg(test)]
+mod test_digest {
+    use super::{Digest, Crc32, Md5, Sha1, Sha256, HasherDigest, to_hex};
+    use crate::hasher::FnvHasher;
+
+    const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog";
+    const SHA_TWO_BLOCKS: &[u8] = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+
+    fn hex<D: Digest + Default>(data: &[u8]) -> String {
+        to_hex(&D::digest(data))
+    }
+
+    /// Feeds `data` in uneven pieces, crossing block boundaries.
+    fn hex_in_pieces<D: Digest + Default>(data: &[u8]) -> String {
+        let mut digest = D::default();
+        for (i, piece) in data.chunks(7).enumerate() {
+            let (head, tail) = piece.split_at(i % piece.len().max(1));
+            digest.update(head);
+            digest.update(&[]);
+            digest.update(tail);
+        }
+        digest.hex_digest()
+    }
+
+    #[test]
+    fn test_to_hex() {
+        assert_eq!(to_hex(&[]), "");
+        assert_eq!(to_hex(&[0, 0x0f, 0xa0, 0xff]), "000fa0ff");
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(Crc32::new().value(), 0);
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.value(), 0xCBF4_3926);
+        assert_eq!(crc.finalize(), vec![0xCB, 0xF4, 0x39, 0x26]);
+        crc.reset();
+        crc.update(FOX);
+        assert_eq!(crc.value(), 0x414F_A339);
+
+        let mut crc = Crc32::castagnoli();
+        crc.update(b"123456789");
+        assert_eq!(crc.value(), 0xE306_9283);
+        // RFC 3720, 32 bytes of zeros and of ones.
+        crc.reset();
+        crc.update(&[0; 32]);
+        assert_eq!(crc.value(), 0x8A91_36AA);
+        crc.reset();
+        crc.update(&[0xff; 32]);
+        assert_eq!(crc.value(), 0x62A8_AB43);
+    }
+
+    #[test]
+    fn test_md5() {
+        // RFC 1321 test suite.
+        assert_eq!(hex::<Md5>(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex::<Md5>(b"a"), "0cc175b9c0f1b6a831c399e269772661");
+        assert_eq!(hex::<Md5>(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hex::<Md5>(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+        assert_eq!(hex::<Md5>(b"abcdefghijklmnopqrstuvwxyz"), "c3fcd3d76192e4007dfb496cca67e13b");
+        assert_eq!(hex::<Md5>(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"),
+                   "d174ab98d277d9f5a5611c2c9f419d9f");
+        let digits = b"12345678901234567890123456789012345678901234567890123456789012345678901234567890";
+        assert_eq!(hex::<Md5>(digits), "57edf4a22be3c955ac49da2e2107b67a");
+        assert_eq!(hex_in_pieces::<Md5>(digits), "57edf4a22be3c955ac49da2e2107b67a");
+        assert_eq!(hex::<Md5>(FOX), "9e107d9d372bb6826bd81d3542a419d6");
+    }
+
+    #[test]
+    fn test_sha1() {
+        // FIPS 180 examples.
+        assert_eq!(hex::<Sha1>(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex::<Sha1>(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hex::<Sha1>(SHA_TWO_BLOCKS), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        assert_eq!(hex_in_pieces::<Sha1>(SHA_TWO_BLOCKS), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        assert_eq!(hex::<Sha1>(&[b'a'; 1_000_000]), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+        assert_eq!(hex::<Sha1>(FOX), "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12");
+    }
+
+    #[test]
+    fn test_sha256() {
+        // FIPS 180 examples.
+        assert_eq!(hex::<Sha256>(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(hex::<Sha256>(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(hex::<Sha256>(SHA_TWO_BLOCKS),
+                   "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+        assert_eq!(hex_in_pieces::<Sha256>(SHA_TWO_BLOCKS),
+                   "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+        assert_eq!(hex::<Sha256>(&[b'a'; 1_000_000]),
+                   "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+        assert_eq!(hex::<Sha256>(FOX), "d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592");
+    }
+
+    #[test]
+    fn test_padding_boundaries() {
+        // 55 bytes leave room for the length in the last block, 56 do not.
+        for len in 54..=65 {
+            let data = vec![b'x'; len];
+            assert_eq!(hex::<Sha256>(&data), hex_in_pieces::<Sha256>(&data), "length {}", len);
+            assert_eq!(hex::<Md5>(&data), hex_in_pieces::<Md5>(&data), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_finalize_keeps_state() {
+        let mut sha = Sha1::new();
+        sha.update(b"ab");
+        assert_eq!(sha.finalize(), Sha1::digest(b"ab"));
+        sha.update(b"c");
+        assert_eq!(sha.hex_digest(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        sha.reset();
+        assert_eq!(sha.finalize(), Sha1::digest(b""));
+    }
+
+    #[test]
+    fn test_hasher_digest() {
+        let mut digest = HasherDigest::<FnvHasher>::default();
+        digest.update(b"a");
+        assert_eq!(digest.hex_digest(), "af63dc4c8601ec8c");
+        digest.reset();
+        assert_eq!(digest.finalize(), 0xcbf2_9ce4_8422_2325u64.to_be_bytes());
+    }
+
+    #[test]
+    fn test_sizes() {
+        let digests: Vec<Box<dyn Digest>> = vec![
+            Box::new(Crc32::castagnoli()), Box::new(Md5::new()), Box::new(Sha1::new()), Box::new(Sha256::new()),
+        ];
+        for digest in &digests {
+            assert_eq!(digest.finalize().len(), digest.output_size());
+        }
+        assert_eq!(digests.iter().map(|d| d.output_size()).collect::<Vec<_>>(), vec![4, 16, 20, 32]);
+    }
+}
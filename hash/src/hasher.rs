@@ -0,0 +1,498 @@
+//! General purpose hash functions.
+//!
+//! The `Simple*Hasher`s are streaming ports of the 32 bit functions from
+//! http://www.partow.net/programming/hashfunctions/, several `write` calls
+//! give the same result as a single call with the concatenated bytes.
+
+use std::hash::{Hasher, BuildHasherDefault};
+
+/// Declares a Partow hasher whose whole state is the running 32 bit hash.
+macro_rules! simple_hasher {
+    ($(#[$attr:meta])* $name:ident, $build:ident, $init:expr, |$hash:ident, $byte:ident| $step:expr) => {
+        $(#[$attr])*
+        pub struct $name(u32);
+
+        impl Default for $name {
+            fn default() -> $name {
+                $name($init)
+            }
+        }
+
+        impl Hasher for $name {
+
+            fn finish(&self) -> u64 {
+                self.0 as u64
+            }
+
+            fn write(&mut self, bytes: &[u8]) {
+                let mut $hash: u32 = self.0;
+                for byte in bytes {
+                    let $byte = *byte as u32;
+                    $hash = $step;
+                }
+                self.0 = $hash;
+            }
+        }
+
+        pub type $build = BuildHasherDefault<$name>;
+    };
+}
+
+///Use simple RS hash function from Robert Sedgwicks Algorithms in C.
+///
+///Refer to http://www.partow.net/programming/hashfunctions/
+///
+///The state is 64 bits wide, its low 32 bits are the RS hash of the
+///reference.
+///
+pub struct SimpleRSHasher {
+    hash: u64,
+    a: u64,
+}
+
+impl SimpleRSHasher {
+
+    pub fn new() -> SimpleRSHasher {
+        SimpleRSHasher { hash: 0, a: 63689 }
+    }
+}
+
+impl Default for SimpleRSHasher {
+    fn default() -> SimpleRSHasher {
+        SimpleRSHasher::new()
+    }
+}
+
+impl Hasher for SimpleRSHasher {
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let b: u64 = 378551;
+
+        for byte in bytes {
+            self.hash = self.hash.wrapping_mul(self.a).wrapping_add(*byte as u64);
+            self.a = self.a.wrapping_mul(b);
+        }
+    }
+}
+
+/// Default hasher builder of `SimpleHashMap`.
+pub type RSBuildHasher = BuildHasherDefault<SimpleRSHasher>;
+
+simple_hasher!(
+    ///Use bitwise hash function written by Justin Sobel.
+    SimpleJSHasher, JSBuildHasher, 1315423911,
+    |hash, byte| hash ^ (hash << 5).wrapping_add(byte).wrapping_add(hash >> 2)
+);
+
+simple_hasher!(
+    ///Use hash function by Peter J. Weinberger of AT&T Bell Labs.
+    SimplePJWHasher, PJWBuildHasher, 0,
+    |hash, byte| {
+        let hash = (hash << 4).wrapping_add(byte);
+        let test = hash & 0xF000_0000;
+        if test != 0 {
+            (hash ^ (test >> 24)) & !0xF000_0000
+        } else {
+            hash
+        }
+    }
+);
+
+simple_hasher!(
+    ///Use the PJW variant used in the Unix ELF object file format.
+    SimpleELFHasher, ELFBuildHasher, 0,
+    |hash, byte| {
+        let mut hash = (hash << 4).wrapping_add(byte);
+        let x = hash & 0xF000_0000;
+        if x != 0 {
+            hash ^= x >> 24;
+        }
+        hash & !x
+    }
+);
+
+simple_hasher!(
+    ///Use hash function from Brian Kernighan and Dennis Ritchie's
+    ///"The C Programming Language".
+    SimpleBKDRHasher, BKDRBuildHasher, 0,
+    |hash, byte| hash.wrapping_mul(131).wrapping_add(byte)
+);
+
+simple_hasher!(
+    ///Use hash function from the open source SDBM project.
+    SimpleSDBMHasher, SDBMBuildHasher, 0,
+    |hash, byte| byte.wrapping_add(hash << 6).wrapping_add(hash << 16).wrapping_sub(hash)
+);
+
+simple_hasher!(
+    ///Use hash function by Professor Daniel J. Bernstein.
+    SimpleDJBHasher, DJBBuildHasher, 5381,
+    |hash, byte| (hash << 5).wrapping_add(hash).wrapping_add(byte)
+);
+
+simple_hasher!(
+    ///Use BP hash function.
+    SimpleBPHasher, BPBuildHasher, 0,
+    |hash, byte| (hash << 7) ^ byte
+);
+
+simple_hasher!(
+    ///Use the FNV variant of the Partow reference, which multiplies by
+    ///`0x811C9DC5` starting from zero. Prefer `FnvHasher` for real FNV-1a.
+    SimpleFNVHasher, PartowFNVBuildHasher, 0,
+    |hash, byte| hash.wrapping_mul(0x811C_9DC5) ^ byte
+);
+
+///Use hash function by Donald E. Knuth in "The Art Of Computer Programming
+///Volume 3".
+///
+///The reference seeds the hash with the input length, which is unknown
+///while streaming. Seeding only rotates the seed and xors it in, so it is
+///folded in by `finish` instead.
+///
+#[derive(Default)]
+pub struct SimpleDEKHasher {
+    hash: u32,
+    len: u32,
+}
+
+impl Hasher for SimpleDEKHasher {
+
+    fn finish(&self) -> u64 {
+        let seed = self.len.rotate_left(self.len.wrapping_mul(5) % 32);
+        (self.hash ^ seed) as u64
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash = self.hash.rotate_left(5) ^ (*byte as u32);
+        }
+        self.len = self.len.wrapping_add(bytes.len() as u32);
+    }
+}
+
+pub type DEKBuildHasher = BuildHasherDefault<SimpleDEKHasher>;
+
+///Use hash function by Arash Partow, alternating between two mixing steps
+///on even and odd byte positions.
+///
+pub struct SimpleAPHasher {
+    hash: u32,
+    odd: bool,
+}
+
+impl Default for SimpleAPHasher {
+    fn default() -> SimpleAPHasher {
+        SimpleAPHasher { hash: 0xAAAA_AAAA, odd: false }
+    }
+}
+
+impl Hasher for SimpleAPHasher {
+
+    fn finish(&self) -> u64 {
+        self.hash as u64
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            let hash = self.hash;
+            let byte = *byte as u32;
+            self.hash ^= if self.odd {
+                !((hash << 11).wrapping_add(byte ^ (hash >> 5)))
+            } else {
+                (hash << 7) ^ byte.wrapping_mul(hash >> 3)
+            };
+            self.odd = !self.odd;
+        }
+    }
+}
+
+pub type APBuildHasher = BuildHasherDefault<SimpleAPHasher>;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+///Use 64 bit FNV-1a hash function.
+///
+///Refer to http://www.isthe.com/chongo/tech/comp/fnv/
+///
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for FnvHasher {
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let mut hash = self.0;
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        self.0 = hash;
+    }
+}
+
+pub type FnvBuildHasher = BuildHasherDefault<FnvHasher>;
+
+const FX_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+///Use the word-at-a-time hash function from the Firefox and rustc
+///code bases. Very fast, but not resistant against malicious keys.
+///
+///Refer to https://github.com/rust-lang/rustc-hash
+///
+#[derive(Default)]
+pub struct FxHasher(u64);
+
+impl FxHasher {
+
+    #[inline]
+    fn add_to_hash(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(FX_SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(chunk);
+            self.add_to_hash(u64::from_le_bytes(word));
+        }
+
+        let mut rest = chunks.remainder();
+        if rest.len() >= 4 {
+            let mut word = [0u8; 4];
+            word.copy_from_slice(&rest[..4]);
+            self.add_to_hash(u32::from_le_bytes(word) as u64);
+            rest = &rest[4..];
+        }
+        if rest.len() >= 2 {
+            let mut word = [0u8; 2];
+            word.copy_from_slice(&rest[..2]);
+            self.add_to_hash(u16::from_le_bytes(word) as u64);
+            rest = &rest[2..];
+        }
+        if let Some(byte) = rest.first() {
+            self.add_to_hash(*byte as u64);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add_to_hash(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add_to_hash(i as u64);
+    }
+}
+
+pub type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
+/// Spreads the bits of a hash over the whole word with the finalizer of
+/// MurmurHash3, for users of all 64 bits.
+pub(crate) const fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Xorshift step, a cheap and reproducible stream of pseudo random words.
+pub(crate) fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+#[cfg(test)]
+mod test_hasher {
+    use super::*;
+    use std::hash::{Hash, Hasher};
+
+    /// Input of the test program shipped with the Partow reference.
+    const PARTOW_KEY: &[u8] = b"abcdefghijklmnopqrstuvwxyz1234567890";
+
+    fn hash_bytes<H: Hasher + Default>(bytes: &[u8]) -> u64 {
+        let mut hasher = H::default();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    fn hash_split<H: Hasher + Default>(bytes: &[u8], at: usize) -> u64 {
+        let mut hasher = H::default();
+        hasher.write(&bytes[..at]);
+        hasher.write(&bytes[at..]);
+        hasher.finish()
+    }
+
+    fn hash_value<H: Hasher + Default, T: Hash>(t: &T) -> u64 {
+        let mut hasher = H::default();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn check_streaming<H: Hasher + Default>() {
+        let expected = hash_bytes::<H>(PARTOW_KEY);
+        for at in 0..=PARTOW_KEY.len() {
+            assert_eq!(hash_split::<H>(PARTOW_KEY, at), expected, "split at {}", at);
+        }
+
+        let mut hasher = H::default();
+        for byte in PARTOW_KEY {
+            hasher.write(&[*byte]);
+        }
+        assert_eq!(hasher.finish(), expected);
+    }
+
+    fn check_composite_key<H: Hasher + Default>() {
+        // every field has to reach the hash, not just the last one
+        assert_ne!(hash_value::<H, _>(&(1u32, 7u32)), hash_value::<H, _>(&(2u32, 7u32)));
+        assert_ne!(hash_value::<H, _>(&("foo", "bar")), hash_value::<H, _>(&("baz", "bar")));
+    }
+
+    #[test]
+    fn test_partow_known_answers() {
+        assert_eq!(hash_bytes::<SimpleRSHasher>(PARTOW_KEY) as u32, 4097835502);
+        assert_eq!(hash_bytes::<SimpleJSHasher>(PARTOW_KEY), 1651003062);
+        assert_eq!(hash_bytes::<SimplePJWHasher>(PARTOW_KEY), 126631744);
+        assert_eq!(hash_bytes::<SimpleELFHasher>(PARTOW_KEY), 126631744);
+        assert_eq!(hash_bytes::<SimpleBKDRHasher>(PARTOW_KEY), 3153586616);
+        assert_eq!(hash_bytes::<SimpleSDBMHasher>(PARTOW_KEY), 3449571336);
+        assert_eq!(hash_bytes::<SimpleDJBHasher>(PARTOW_KEY), 729241521);
+        assert_eq!(hash_bytes::<SimpleDEKHasher>(PARTOW_KEY), 2923964919);
+        assert_eq!(hash_bytes::<SimpleFNVHasher>(PARTOW_KEY), 3243095106);
+        assert_eq!(hash_bytes::<SimpleBPHasher>(PARTOW_KEY), 1726880944);
+        assert_eq!(hash_bytes::<SimpleAPHasher>(PARTOW_KEY), 882643939);
+    }
+
+    #[test]
+    fn test_partow_empty_input() {
+        assert_eq!(hash_bytes::<SimpleRSHasher>(b""), 0);
+        assert_eq!(hash_bytes::<SimpleJSHasher>(b""), 1315423911);
+        assert_eq!(hash_bytes::<SimpleDJBHasher>(b""), 5381);
+        assert_eq!(hash_bytes::<SimpleDEKHasher>(b""), 0);
+        assert_eq!(hash_bytes::<SimpleAPHasher>(b""), 0xAAAA_AAAA);
+        assert_eq!(hash_bytes::<SimpleDEKHasher>(b"a"), 65);
+        assert_eq!(hash_bytes::<SimpleDJBHasher>(b"a"), 177670);
+    }
+
+    #[test]
+    fn test_rs_hash_is_64_bits() {
+        let mut hasher = SimpleRSHasher::new();
+        hasher.write(PARTOW_KEY);
+        assert_eq!(hasher.finish(), hash_bytes::<SimpleRSHasher>(PARTOW_KEY));
+        assert!(hasher.finish() > u32::MAX as u64);
+    }
+
+    #[test]
+    fn test_partow_streaming() {
+        check_streaming::<SimpleRSHasher>();
+        check_streaming::<SimpleJSHasher>();
+        check_streaming::<SimplePJWHasher>();
+        check_streaming::<SimpleELFHasher>();
+        check_streaming::<SimpleBKDRHasher>();
+        check_streaming::<SimpleSDBMHasher>();
+        check_streaming::<SimpleDJBHasher>();
+        check_streaming::<SimpleDEKHasher>();
+        check_streaming::<SimpleFNVHasher>();
+        check_streaming::<SimpleBPHasher>();
+        check_streaming::<SimpleAPHasher>();
+        check_streaming::<FnvHasher>();
+    }
+
+    #[test]
+    fn test_composite_keys() {
+        check_composite_key::<SimpleRSHasher>();
+        check_composite_key::<SimpleJSHasher>();
+        check_composite_key::<SimplePJWHasher>();
+        check_composite_key::<SimpleELFHasher>();
+        check_composite_key::<SimpleBKDRHasher>();
+        check_composite_key::<SimpleSDBMHasher>();
+        check_composite_key::<SimpleDJBHasher>();
+        check_composite_key::<SimpleDEKHasher>();
+        check_composite_key::<SimpleFNVHasher>();
+        check_composite_key::<SimpleAPHasher>();
+        // BP shifts every byte out after five more, so only the tail of a
+        // key counts by design and it is left out here
+        check_composite_key::<FnvHasher>();
+        check_composite_key::<FxHasher>();
+    }
+
+    fn fnv(bytes: &[u8]) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    fn fx<T: Hash>(t: &T) -> u64 {
+        let mut hasher = FxHasher::default();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_fnv1a_known_answers() {
+        assert_eq!(fnv(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_fnv1a_streaming() {
+        let mut hasher = FnvHasher::default();
+        hasher.write(b"foo");
+        hasher.write(b"bar");
+        assert_eq!(hasher.finish(), fnv(b"foobar"));
+    }
+
+    #[test]
+    fn test_fx_words() {
+        assert_eq!(fx(&0u64), 0);
+        assert_eq!(fx(&1u64), FX_SEED);
+        assert_eq!(fx(&1u8), FX_SEED);
+
+        let mut hasher = FxHasher::default();
+        hasher.write(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(hasher.finish(), FX_SEED);
+    }
+
+    #[test]
+    fn test_fx_composite_keys() {
+        assert_ne!(fx(&(1u32, 2u32)), fx(&(2u32, 1u32)));
+        assert_ne!(fx(&"abcdefghi"), fx(&"abcdefghj"));
+        assert_ne!(fx(&"ab"), fx(&"ba"));
+    }
+}
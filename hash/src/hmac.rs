@@ -0,0 +1,265 @@
+//! Keyed message authentication (HMAC, RFC 2104) and key derivation
+//! (HKDF, RFC 5869) over any `Digest`.
+
+use std::hint::black_box;
+
+use super::digest::Digest;
+
+const IPAD: u8 = 0x36;
+const OPAD: u8 = 0x5c;
+
+/// HMAC of the digest `D`. Being a `Digest` itself, it takes the message
+/// in pieces and `reset` starts a new message with the same key.
+#[derive(Clone)]
+pub struct Hmac<D> {
+    /// Inner digest right after absorbing the padded key, for `reset`.
+    keyed: D,
+    inner: D,
+    /// Outer digest right after absorbing the padded key.
+    outer: D,
+}
+
+impl<D> Hmac<D> where D: Digest + Default + Clone {
+
+    /// Keys longer than the block size of `D` are hashed first, as the RFC
+    /// requires, and shorter ones are padded with zeros.
+    pub fn new(key: &[u8]) -> Hmac<D> {
+        let mut inner = D::default();
+        let mut block = if key.len() > inner.block_size() { D::digest(key) } else { key.to_vec() };
+        block.resize(inner.block_size(), 0);
+
+        let mut outer = D::default();
+        inner.update(&block.iter().map(|b| b ^ IPAD).collect::<Vec<_>>());
+        outer.update(&block.iter().map(|b| b ^ OPAD).collect::<Vec<_>>());
+        Hmac { keyed: inner.clone(), inner, outer }
+    }
+
+    /// Tag of `data` under `key`.
+    pub fn mac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut hmac = Hmac::<D>::new(key);
+        hmac.update(data);
+        hmac.finalize()
+    }
+
+    /// Whether `tag` is the tag of the message fed so far. The comparison
+    /// takes the same time wherever the first wrong byte is, so it does not
+    /// tell an attacker how much of a forged tag is right.
+    pub fn verify(&self, tag: &[u8]) -> bool {
+        constant_time_eq(&self.finalize(), tag)
+    }
+}
+
+impl<D> Digest for Hmac<D> where D: Digest + Default + Clone {
+
+    fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    fn finalize(&self) -> Vec<u8> {
+        let mut outer = self.outer.clone();
+        outer.update(&self.inner.finalize());
+        outer.finalize()
+    }
+
+    fn reset(&mut self) {
+        self.inner = self.keyed.clone();
+    }
+
+    fn output_size(&self) -> usize {
+        self.outer.output_size()
+    }
+
+    fn block_size(&self) -> usize {
+        self.outer.block_size()
+    }
+}
+
+/// Compares two byte strings in time depending only on their lengths,
+/// which are not secret for tags and digests.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0, |diff, (x, y)| black_box(diff | (x ^ y)));
+    diff == 0
+}
+
+/// HKDF-Extract: a pseudorandom key from input keying material `ikm`. An
+/// empty `salt` stands for a string of zeros as long as the digest.
+pub fn hkdf_extract<D>(salt: &[u8], ikm: &[u8]) -> Vec<u8> where D: Digest + Default + Clone {
+    Hmac::<D>::mac(salt, ikm)
+}
+
+/// HKDF-Expand: `len` bytes of output keying material from the
+/// pseudorandom key `prk`, bound to the context `info`.
+///
+/// Panics if `len` exceeds 255 digests.
+pub fn hkdf_expand<D>(prk: &[u8], info: &[u8], len: usize) -> Vec<u8> where D: Digest + Default + Clone {
+    let hmac = Hmac::<D>::new(prk);
+    assert!(len <= 255 * hmac.output_size(),
+            "HKDF output is limited to 255 digests, got {} bytes", len);
+    let mut okm = Vec::with_capacity(len);
+    let mut block = Vec::new();
+    for counter in 1..=255u8 {
+        if okm.len() >= len {
+            break;
+        }
+        let mut step = hmac.clone();
+        step.update(&block);
+        step.update(info);
+        step.update(&[counter]);
+        block = step.finalize();
+        okm.extend_from_slice(&block);
+    }
+    okm.truncate(len);
+    okm
+}
+
+/// Extract then expand: `len` bytes of keying material from `ikm`.
+pub fn hkdf<D>(salt: &[u8], ikm: &[u8], info: &[u8], len: usize) -> Vec<u8> where D: Digest + Default + Clone {
+    hkdf_expand::<D>(&hkdf_extract::<D>(salt, ikm), info, len)
+}
+
+#[cfg(test)]
+mod test_hmac {
+    use super::{Hmac, constant_time_eq, hkdf, hkdf_extract, hkdf_expand};
+    use crate::digest::{Digest, Md5, Sha1, Sha256, to_hex};
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_hmac_md5() {
+        // RFC 2104 appendix.
+        assert_eq!(to_hex(&Hmac::<Md5>::mac(&[0x0b; 16], b"Hi There")), "9294727a3638bb1c13f48ef8158bfc9d");
+        assert_eq!(to_hex(&Hmac::<Md5>::mac(b"Jefe", b"what do ya want for nothing?")),
+                   "750c783e6ab0b503eaa86e310a5db738");
+        assert_eq!(to_hex(&Hmac::<Md5>::mac(&[0xaa; 16], &[0xdd; 50])), "56be34521d144c88dbb8c733f0e8b3f6");
+    }
+
+    #[test]
+    fn test_hmac_sha1() {
+        // RFC 2202.
+        assert_eq!(to_hex(&Hmac::<Sha1>::mac(&[0x0b; 20], b"Hi There")),
+                   "b617318655057264e28bc0b6fb378c8ef146be00");
+        assert_eq!(to_hex(&Hmac::<Sha1>::mac(b"Jefe", b"what do ya want for nothing?")),
+                   "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79");
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231 test cases 1 to 7.
+        let long_key = [0xaa; 131];
+        let key_25: Vec<u8> = (1..=25).collect();
+        let cases: [(&[u8], &[u8], &str); 6] = [
+            (&[0x0b; 20], b"Hi There", "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"),
+            (b"Jefe", b"what do ya want for nothing?",
+             "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"),
+            (&[0xaa; 20], &[0xdd; 50], "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe"),
+            (&key_25, &[0xcd; 50], "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b"),
+            (&long_key, b"Test Using Larger Than Block-Size Key - Hash Key First",
+             "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"),
+            (&long_key, b"This is a test using a larger than block-size key and a larger than block-size data. \
+                          The key needs to be hashed before being used by the HMAC algorithm.",
+             "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2"),
+        ];
+        for &(key, data, tag) in &cases {
+            assert_eq!(to_hex(&Hmac::<Sha256>::mac(key, data)), tag);
+        }
+        let truncated = Hmac::<Sha256>::mac(&[0x0c; 20], b"Test With Truncation");
+        assert_eq!(to_hex(&truncated[..16]), "a3b6167473100ee06e0c796c2955552b");
+    }
+
+    #[test]
+    fn test_streaming_and_reset() {
+        let mut hmac = Hmac::<Sha256>::new(b"Jefe");
+        hmac.update(b"what do ya ");
+        hmac.update(b"want for nothing?");
+        let tag = from_hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert_eq!(hmac.finalize(), tag);
+        assert!(hmac.verify(&tag));
+        assert_eq!(hmac.output_size(), 32);
+
+        hmac.reset();
+        assert!(!hmac.verify(&tag));
+        hmac.update(b"what do ya want for nothing?");
+        assert!(hmac.verify(&tag));
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut hmac = Hmac::<Sha1>::new(b"secret");
+        hmac.update(b"payload");
+        let mut tag = hmac.finalize();
+        assert!(hmac.verify(&tag));
+        assert!(!hmac.verify(&tag[..19]));
+        assert!(!hmac.verify(&[]));
+        tag[19] ^= 1;
+        assert!(!hmac.verify(&tag));
+        assert!(!Hmac::<Sha1>::new(b"other").verify(&Hmac::<Sha1>::mac(b"secret", b"")));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+
+    #[test]
+    fn test_hkdf_sha256() {
+        // RFC 5869 test case 1.
+        let ikm = [0x0b; 22];
+        let salt: Vec<u8> = (0x00..=0x0c).collect();
+        let info: Vec<u8> = (0xf0..=0xf9).collect();
+        let prk = hkdf_extract::<Sha256>(&salt, &ikm);
+        assert_eq!(to_hex(&prk), "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5");
+        assert_eq!(to_hex(&hkdf_expand::<Sha256>(&prk, &info, 42)),
+                   "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865");
+
+        // Test case 2, longer inputs and outputs.
+        let ikm: Vec<u8> = (0x00..=0x4f).collect();
+        let salt: Vec<u8> = (0x60..=0xaf).collect();
+        let info: Vec<u8> = (0xb0..=0xff).collect();
+        assert_eq!(to_hex(&hkdf_extract::<Sha256>(&salt, &ikm)),
+                   "06a6b88c5853361a06104c9ceb35b45cef760014904671014a193f40c15fc244");
+        assert_eq!(to_hex(&hkdf::<Sha256>(&salt, &ikm, &info, 82)),
+                   "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97c\
+                    59045a99cac7827271cb41c65e590e09da3275600c2f09b8367793a9aca3db71\
+                    cc30c58179ec3e87c14c01d5c1f3434f1d87");
+
+        // Test case 3, empty salt and info.
+        assert_eq!(to_hex(&hkdf_extract::<Sha256>(&[], &[0x0b; 22])),
+                   "19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04");
+        assert_eq!(to_hex(&hkdf::<Sha256>(&[], &[0x0b; 22], &[], 42)),
+                   "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8");
+    }
+
+    #[test]
+    fn test_hkdf_sha1() {
+        // RFC 5869 test case 4.
+        let salt: Vec<u8> = (0x00..=0x0c).collect();
+        let info: Vec<u8> = (0xf0..=0xf9).collect();
+        let prk = hkdf_extract::<Sha1>(&salt, &[0x0b; 11]);
+        assert_eq!(to_hex(&prk), "9b6c18c432a7bf8f0e71c8eb88f4b30baa2ba243");
+        assert_eq!(to_hex(&hkdf_expand::<Sha1>(&prk, &info, 42)),
+                   "085a01ea1b10f36933068b56efa5ad81a4f14b822f5b091568a9cdd4f155fda2c22e422478d305f3f896");
+    }
+
+    #[test]
+    fn test_hkdf_lengths() {
+        let prk = hkdf_extract::<Sha256>(b"salt", b"key");
+        assert!(hkdf_expand::<Sha256>(&prk, b"", 0).is_empty());
+        let longest = hkdf_expand::<Sha256>(&prk, b"", 255 * 32);
+        assert_eq!(longest.len(), 255 * 32);
+        assert_eq!(hkdf_expand::<Sha256>(&prk, b"", 33), &longest[..33]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_hkdf_too_long() {
+        hkdf_expand::<Sha256>(&[0; 32], b"", 255 * 32 + 1);
+    }
+}
@@ -0,0 +1,723 @@
+//! Insertion-ordered hash map: the entries sit in a dense `Vec` in the order
+//! they were inserted, and an open addressing table maps hashes to their
+//! positions in it.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash, BuildHasher};
+use std::iter::FromIterator;
+use std::ops::{Index, IndexMut};
+use std::slice;
+use std::vec;
+
+use super::{RSBuildHasher, buckets_for, usable_capacity};
+
+/// Free slot of the position table.
+const EMPTY: usize = usize::MAX;
+
+#[derive(Clone)]
+struct Bucket<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// Hash map iterating in insertion order, like `IndexMap`.
+///
+/// Each entry also has a position, `0..len` in iteration order, through
+/// which it can be read, removed or reordered. Inserting an existing key
+/// updates its value in place. `swap_remove` fills the hole with the last
+/// entry in O(1), `shift_remove` keeps the order of the others in O(n).
+pub struct IndexedHashMap<K, V, S = RSBuildHasher> {
+    entries: Vec<Bucket<K, V>>,
+    /// Positions in `entries` by hash, probed linearly. Removal shifts the
+    /// following slots back instead of leaving tombstones.
+    indices: Vec<usize>,
+    hash_builder: S,
+}
+
+impl<K: Hash + Eq, V> IndexedHashMap<K, V, RSBuildHasher> {
+
+    pub fn new() -> IndexedHashMap<K, V, RSBuildHasher> {
+        IndexedHashMap::with_hasher(Default::default())
+    }
+
+    pub fn with_capacity(capacity: usize) -> IndexedHashMap<K, V, RSBuildHasher> {
+        IndexedHashMap::with_capacity_and_hasher(capacity, Default::default())
+    }
+}
+
+impl<K: Hash + Eq, V> Default for IndexedHashMap<K, V, RSBuildHasher> {
+    fn default() -> IndexedHashMap<K, V, RSBuildHasher> {
+        IndexedHashMap::new()
+    }
+}
+
+impl<K, V, S> IndexedHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+
+    pub fn with_hasher(hash_builder: S) -> IndexedHashMap<K, V, S> {
+        IndexedHashMap { entries: Vec::new(), indices: Vec::new(), hash_builder }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> IndexedHashMap<K, V, S> {
+        IndexedHashMap {
+            entries: Vec::with_capacity(capacity),
+            indices: vec![EMPTY; buckets_for(capacity)],
+            hash_builder,
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of entries the map holds without growing its position table.
+    pub fn capacity(&self) -> usize {
+        usable_capacity(self.indices.len())
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.indices.iter_mut().for_each(|slot| *slot = EMPTY);
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let wanted = self.len().checked_add(additional).expect("capacity overflow");
+        self.entries.reserve(additional);
+        if wanted > self.capacity() {
+            self.rebuild_index(buckets_for(wanted));
+        }
+    }
+
+    fn make_hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hash_builder.hash_one(key)
+    }
+
+    fn mask(&self) -> usize {
+        self.indices.len() - 1
+    }
+
+    /// Slot and position of `key`.
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<(usize, usize)> where K: Borrow<Q>, Q: Eq + ?Sized {
+        if self.indices.is_empty() {
+            return None;
+        }
+        let mut slot = hash as usize & self.mask();
+        loop {
+            let pos = self.indices[slot];
+            if pos == EMPTY {
+                return None;
+            }
+            let bucket = &self.entries[pos];
+            if bucket.hash == hash && bucket.key.borrow() == key {
+                return Some((slot, pos));
+            }
+            slot = (slot + 1) & self.mask();
+        }
+    }
+
+    /// Slot holding position `pos`, whose entry has `hash`.
+    fn slot_of(&self, hash: u64, pos: usize) -> usize {
+        let mut slot = hash as usize & self.mask();
+        while self.indices[slot] != pos {
+            slot = (slot + 1) & self.mask();
+        }
+        slot
+    }
+
+    fn insert_index(&mut self, hash: u64, pos: usize) {
+        let mut slot = hash as usize & self.mask();
+        while self.indices[slot] != EMPTY {
+            slot = (slot + 1) & self.mask();
+        }
+        self.indices[slot] = pos;
+    }
+
+    /// Frees `slot`, moving back the following slots which may then be
+    /// reached sooner. Reads the hashes of `entries`, so it must run before
+    /// they change.
+    fn remove_slot(&mut self, slot: usize) {
+        let mask = self.mask();
+        let mut hole = slot;
+        let mut next = (slot + 1) & mask;
+        loop {
+            let pos = self.indices[next];
+            if pos == EMPTY {
+                break;
+            }
+            let ideal = self.entries[pos].hash as usize & mask;
+            if next.wrapping_sub(ideal) & mask >= next.wrapping_sub(hole) & mask {
+                self.indices[hole] = pos;
+                hole = next;
+            }
+            next = (next + 1) & mask;
+        }
+        self.indices[hole] = EMPTY;
+    }
+
+    fn rebuild_index(&mut self, buckets: usize) {
+        self.indices = vec![EMPTY; buckets];
+        for pos in 0..self.entries.len() {
+            self.insert_index(self.entries[pos].hash, pos);
+        }
+    }
+
+    /// Inserts `value` under `key`, at the end unless the key is already
+    /// there, in which case the value is replaced in place and the old one
+    /// returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_full(key, value).1
+    }
+
+    /// Like `insert`, also returning the position of the entry.
+    pub fn insert_full(&mut self, key: K, value: V) -> (usize, Option<V>) {
+        let hash = self.make_hash(&key);
+        if let Some((_, pos)) = self.find(hash, &key) {
+            return (pos, Some(std::mem::replace(&mut self.entries[pos].value, value)));
+        }
+        if self.len() + 1 > self.capacity() {
+            self.rebuild_index(buckets_for(self.len() + 1));
+        }
+        let pos = self.entries.len();
+        self.entries.push(Bucket { hash, key, value });
+        self.insert_index(hash, pos);
+        (pos, None)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let (_, pos) = self.find(self.make_hash(key), key)?;
+        Some(&self.entries[pos].value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let (_, pos) = self.find(self.make_hash(key), key)?;
+        Some(&mut self.entries[pos].value)
+    }
+
+    /// Position, key and value of `key`.
+    pub fn get_full<Q>(&self, key: &Q) -> Option<(usize, &K, &V)> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let (_, pos) = self.find(self.make_hash(key), key)?;
+        let bucket = &self.entries[pos];
+        Some((pos, &bucket.key, &bucket.value))
+    }
+
+    pub fn get_index_of<Q>(&self, key: &Q) -> Option<usize> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        self.find(self.make_hash(key), key).map(|(_, pos)| pos)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        self.get_index_of(key).is_some()
+    }
+
+    /// Removes `key`, moving the last entry into its position.
+    pub fn swap_remove<Q>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let (slot, pos) = self.find(self.make_hash(key), key)?;
+        Some(self.swap_remove_found(slot, pos).1)
+    }
+
+    /// Removes `key`, moving all the following entries down by one.
+    pub fn shift_remove<Q>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let (slot, pos) = self.find(self.make_hash(key), key)?;
+        Some(self.shift_remove_found(slot, pos).1)
+    }
+
+    /// Removes the entry at `index`, moving the last entry into its place.
+    pub fn swap_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        let hash = self.entries.get(index)?.hash;
+        let slot = self.slot_of(hash, index);
+        Some(self.swap_remove_found(slot, index))
+    }
+
+    /// Removes the entry at `index`, moving all the following entries down
+    /// by one.
+    pub fn shift_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        let hash = self.entries.get(index)?.hash;
+        let slot = self.slot_of(hash, index);
+        Some(self.shift_remove_found(slot, index))
+    }
+
+    /// Removes the last entry.
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        self.swap_remove_index(self.len().checked_sub(1)?)
+    }
+
+    fn swap_remove_found(&mut self, slot: usize, pos: usize) -> (K, V) {
+        self.remove_slot(slot);
+        let last = self.entries.len() - 1;
+        if pos != last {
+            let moved = self.slot_of(self.entries[last].hash, last);
+            self.indices[moved] = pos;
+        }
+        let bucket = self.entries.swap_remove(pos);
+        (bucket.key, bucket.value)
+    }
+
+    fn shift_remove_found(&mut self, slot: usize, pos: usize) -> (K, V) {
+        self.remove_slot(slot);
+        let bucket = self.entries.remove(pos);
+        for index in self.indices.iter_mut().filter(|index| **index != EMPTY && **index > pos) {
+            *index -= 1;
+        }
+        (bucket.key, bucket.value)
+    }
+
+    pub fn get_index(&self, index: usize) -> Option<(&K, &V)> {
+        self.entries.get(index).map(|bucket| (&bucket.key, &bucket.value))
+    }
+
+    pub fn get_index_mut(&mut self, index: usize) -> Option<(&K, &mut V)> {
+        self.entries.get_mut(index).map(|bucket| (&bucket.key, &mut bucket.value))
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.get_index(0)
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.get_index(self.len().checked_sub(1)?)
+    }
+
+    /// Swaps the positions of two entries.
+    ///
+    /// Panics if either index is out of bounds.
+    pub fn swap_indices(&mut self, a: usize, b: usize) {
+        let (slot_a, slot_b) = (self.slot_of(self.entries[a].hash, a), self.slot_of(self.entries[b].hash, b));
+        self.indices.swap(slot_a, slot_b);
+        self.entries.swap(a, b);
+    }
+
+    /// Keeps the entries for which `keep` returns true, in their order.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut keep: F) {
+        let len = self.len();
+        self.entries.retain_mut(|bucket| keep(&bucket.key, &mut bucket.value));
+        if self.len() < len {
+            self.rebuild_index(self.indices.len());
+        }
+    }
+
+    /// Sorts the entries by key, stably.
+    pub fn sort_keys(&mut self) where K: Ord {
+        self.sort_by(|k1, _, k2, _| k1.cmp(k2));
+    }
+
+    /// Sorts the entries stably with `compare`, which sees the key and
+    /// value of both sides.
+    pub fn sort_by<F>(&mut self, mut compare: F) where F: FnMut(&K, &V, &K, &V) -> Ordering {
+        self.entries.sort_by(|a, b| compare(&a.key, &a.value, &b.key, &b.value));
+        self.rebuild_index(self.indices.len());
+    }
+
+    /// Reverses the order of the entries.
+    pub fn reverse(&mut self) {
+        self.entries.reverse();
+        let last = self.len().wrapping_sub(1);
+        for index in self.indices.iter_mut().filter(|index| **index != EMPTY) {
+            *index = last - *index;
+        }
+    }
+
+    /// Entries in order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { inner: self.entries.iter() }
+    }
+
+    /// Entries in order, with mutable values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut { inner: self.entries.iter_mut() }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut { inner: self.iter_mut() }
+    }
+}
+
+pub struct Iter<'a, K: 'a, V: 'a> {
+    inner: slice::Iter<'a, Bucket<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next().map(|bucket| (&bucket.key, &bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next_back().map(|bucket| (&bucket.key, &bucket.value))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    inner: slice::IterMut<'a, Bucket<K, V>>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.inner.next().map(|bucket| (&bucket.key, &mut bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.inner.next_back().map(|bucket| (&bucket.key, &mut bucket.value))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
+
+pub struct Keys<'a, K: 'a, V: 'a> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|(k, _)| k)
+    }
+}
+
+pub struct Values<'a, K: 'a, V: 'a> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+}
+
+pub struct ValuesMut<'a, K: 'a, V: 'a> {
+    inner: IterMut<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+}
+
+pub struct IntoIter<K, V> {
+    inner: vec::IntoIter<Bucket<K, V>>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        self.inner.next().map(|bucket| (bucket.key, bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        self.inner.next_back().map(|bucket| (bucket.key, bucket.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V, S> IntoIterator for IndexedHashMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter { inner: self.entries.into_iter() }
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a IndexedHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut IndexedHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for IndexedHashMap<K, V, S>
+    where K: Hash + Eq,
+          S: BuildHasher + Default {
+
+    fn from_iter<I: IntoIterator<Item=(K, V)>>(iter: I) -> IndexedHashMap<K, V, S> {
+        let mut map = IndexedHashMap::with_hasher(Default::default());
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for IndexedHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+    fn extend<I: IntoIterator<Item=(K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone> Clone for IndexedHashMap<K, V, S> {
+    fn clone(&self) -> IndexedHashMap<K, V, S> {
+        IndexedHashMap {
+            entries: self.entries.clone(),
+            indices: self.indices.clone(),
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+}
+
+/// Maps are equal when they hold the same entries, in any order.
+impl<K, V, S> PartialEq for IndexedHashMap<K, V, S> where K: Hash + Eq, V: PartialEq, S: BuildHasher {
+    fn eq(&self, other: &IndexedHashMap<K, V, S>) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K, V, S> Eq for IndexedHashMap<K, V, S> where K: Hash + Eq, V: Eq, S: BuildHasher {}
+
+impl<K, V, S> Debug for IndexedHashMap<K, V, S>
+    where K: Hash + Eq + Debug,
+          V: Debug,
+          S: BuildHasher {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, Q, S> Index<&Q> for IndexedHashMap<K, V, S>
+    where K: Hash + Eq + Borrow<Q>,
+          Q: Hash + Eq + ?Sized,
+          S: BuildHasher {
+
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K, V, Q, S> IndexMut<&Q> for IndexedHashMap<K, V, S>
+    where K: Hash + Eq + Borrow<Q>,
+          Q: Hash + Eq + ?Sized,
+          S: BuildHasher {
+
+    fn index_mut(&mut self, key: &Q) -> &mut V {
+        self.get_mut(key).expect("no entry found for key")
+    }
+}
+
+#[cfg(test)]
+mod test_indexed_map {
+    use std::hash::BuildHasherDefault;
+    use super::IndexedHashMap;
+    use crate::hasher::mix;
+    use crate::test_util::ConstantHasher;
+
+    fn assert_consistent<V>(map: &IndexedHashMap<u64, V>) {
+        for (i, (key, _)) in map.iter().enumerate() {
+            assert_eq!(map.get_index_of(key), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_insertion_order() {
+        let mut map = IndexedHashMap::new();
+        for key in &["content-type", "host", "accept", "x-request-id", "cookie"] {
+            map.insert(key.to_string(), key.len());
+        }
+        assert_eq!(map.insert("host".to_string(), 0), Some(4));
+        let keys: Vec<&str> = map.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["content-type", "host", "accept", "x-request-id", "cookie"]);
+        assert_eq!(map.values().cloned().collect::<Vec<_>>(), vec![12, 0, 6, 12, 6]);
+        assert_eq!(map["accept"], 6);
+        assert_eq!(map.get_full("cookie"), Some((4, &"cookie".to_string(), &6)));
+        assert_eq!(map.first(), Some((&"content-type".to_string(), &12)));
+        assert_eq!(map.last(), Some((&"cookie".to_string(), &6)));
+        assert_eq!(map.insert_full("age".to_string(), 3), (5, None));
+        assert_eq!(format!("{:?}", map.get_index(5)), "Some((\"age\", 3))");
+        assert_eq!(map.get_index(6), None);
+    }
+
+    #[test]
+    fn test_swap_remove() {
+        let mut map: IndexedHashMap<u64, u64> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(map.swap_remove(&3), Some(30));
+        assert_eq!(map.swap_remove(&3), None);
+        assert_eq!(map.keys().cloned().collect::<Vec<_>>(), vec![0, 1, 2, 9, 4, 5, 6, 7, 8]);
+        assert_eq!(map.swap_remove_index(8), Some((8, 80)));
+        assert_eq!(map.swap_remove_index(8), None);
+        assert_eq!(map.pop(), Some((7, 70)));
+        assert_eq!(map.len(), 7);
+        assert_consistent(&map);
+        assert_eq!(map[&9], 90);
+    }
+
+    #[test]
+    fn test_shift_remove() {
+        let mut map: IndexedHashMap<u64, u64> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(map.shift_remove(&3), Some(30));
+        assert_eq!(map.shift_remove_index(0), Some((0, 0)));
+        assert_eq!(map.keys().cloned().collect::<Vec<_>>(), vec![1, 2, 4, 5, 6, 7, 8, 9]);
+        assert_consistent(&map);
+        assert_eq!(map.shift_remove(&42), None);
+    }
+
+    #[test]
+    fn test_removal_keeps_probe_chains() {
+        // Every key lands in slot 0 of the position table, so removals
+        // must shift the rest of the chain back.
+        let mut map = IndexedHashMap::with_hasher(BuildHasherDefault::<ConstantHasher>::default());
+        for i in 0..20u64 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.swap_remove(&0), Some(0));
+        assert_eq!(map.shift_remove(&5), Some(5));
+        for i in (1..20).filter(|&i| i != 5) {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        for (i, (key, _)) in map.iter().enumerate() {
+            assert_eq!(map.get_index_of(key), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_sorting() {
+        let mut map: IndexedHashMap<u64, u64> = [5, 1, 4, 2, 3].iter().map(|&k| (k, 10 - k)).collect();
+        map.sort_keys();
+        assert_eq!(map.keys().cloned().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert_consistent(&map);
+        map.sort_by(|_, v1, _, v2| v1.cmp(v2));
+        assert_eq!(map.keys().cloned().collect::<Vec<_>>(), vec![5, 4, 3, 2, 1]);
+        assert_consistent(&map);
+        map.reverse();
+        assert_eq!(map.keys().cloned().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert_consistent(&map);
+        map.swap_indices(0, 4);
+        assert_eq!(map.keys().cloned().collect::<Vec<_>>(), vec![5, 2, 3, 4, 1]);
+        assert_consistent(&map);
+    }
+
+    #[test]
+    fn test_retain_and_mutation() {
+        let mut map: IndexedHashMap<u64, u64> = (0..100).map(|i| (i, i)).collect();
+        map.retain(|k, v| {
+            *v += 1;
+            k % 3 == 0
+        });
+        assert_eq!(map.len(), 34);
+        assert_eq!(map.get_index(1), Some((&3, &4)));
+        assert_consistent(&map);
+
+        map.values_mut().for_each(|v| *v *= 2);
+        if let Some((_, v)) = map.get_index_mut(0) {
+            *v = 7;
+        }
+        *map.get_mut(&3).unwrap() += 1;
+        map[&6] = 0;
+        assert_eq!(map.iter().take(3).collect::<Vec<_>>(), vec![(&0, &7), (&3, &9), (&6, &0)]);
+        for (_, v) in &mut map {
+            *v = 1;
+        }
+        assert!(map.values().all(|&v| v == 1));
+        assert_eq!(map.iter().next_back(), Some((&99, &1)));
+    }
+
+    #[test]
+    fn test_against_model() {
+        // Random operations checked against a vector of entries.
+        let mut map: IndexedHashMap<u64, u64> = IndexedHashMap::new();
+        let mut model: Vec<(u64, u64)> = Vec::new();
+        for step in 0..20_000u64 {
+            let random = mix(step);
+            let key = random % 300;
+            match random >> 60 {
+                0..=7 => {
+                    let old = map.insert(key, step);
+                    match model.iter_mut().find(|(k, _)| *k == key) {
+                        Some(entry) => assert_eq!(old, Some(std::mem::replace(&mut entry.1, step))),
+                        None => {
+                            assert_eq!(old, None);
+                            model.push((key, step));
+                        }
+                    }
+                }
+                8..=11 => {
+                    let expected = model.iter().position(|(k, _)| *k == key).map(|i| model.swap_remove(i).1);
+                    assert_eq!(map.swap_remove(&key), expected);
+                }
+                _ => {
+                    let expected = model.iter().position(|(k, _)| *k == key).map(|i| model.remove(i).1);
+                    assert_eq!(map.shift_remove(&key), expected);
+                }
+            }
+        }
+        assert_eq!(map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), model);
+        assert_consistent(&map);
+    }
+
+    #[test]
+    fn test_clear_equality_and_into_iter() {
+        let mut a: IndexedHashMap<u64, u64> = (0..50).map(|i| (i, i)).collect();
+        let b: IndexedHashMap<u64, u64> = (0..50).rev().map(|i| (i, i)).collect();
+        assert_eq!(a, b);
+        assert_eq!(a.clone(), a);
+        assert_eq!(b.into_iter().next(), Some((49, 49)));
+        let capacity = a.capacity();
+        a.clear();
+        assert!(a.is_empty());
+        assert_eq!(a.capacity(), capacity);
+        assert_eq!(a.get(&3), None);
+        a.insert(3, 3);
+        assert_eq!(a.into_iter().collect::<Vec<_>>(), vec![(3, 3)]);
+
+        let mut empty: IndexedHashMap<u64, u64> = IndexedHashMap::new();
+        assert_eq!(empty.pop(), None);
+        assert_eq!(empty.last(), None);
+        empty.reverse();
+        empty.reserve(100);
+        assert!(empty.capacity() >= 100);
+    }
+}
@@ -1,52 +1,214 @@
-use std::hash::{Hash, Hasher, BuildHasher};
-use std::borrow::BorrowMut;
+use std::borrow::Borrow;
+use std::hash::{Hash, BuildHasher};
 use std::mem;
 use std::slice;
+use std::vec;
 use std::iter::FromIterator;
-use std::fmt::{Debug, Formatter, Result};
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
 use std::ops::Index;
 
+pub mod hasher;
+pub mod sip;
+pub mod probing;
+pub mod swiss;
+pub mod set;
+pub mod concurrent;
+pub mod cache;
+pub mod ttl;
+pub mod stats;
+pub mod sharding;
+pub mod bloom;
+pub mod cuckoo;
+pub mod sketch;
+pub mod digest;
+pub mod hmac;
+pub mod rolling;
+pub mod chunker;
+pub mod perfect;
+pub mod merkle;
+pub mod indexed;
+
+#[cfg(test)]
+mod test_util;
+
+pub use hasher::{SimpleRSHasher, RSBuildHasher};
+pub use sip::RandomSipState;
+pub use probing::Probing;
+pub use swiss::SwissHashMap;
+pub use set::SimpleHashSet;
+pub use concurrent::ConcurrentHashMap;
+pub use cache::Cache;
+pub use ttl::TtlCache;
+pub use stats::MapStats;
+pub use sharding::{NodeLocator, ConsistentHashRing, RendezvousHashing};
+pub use bloom::{BloomFilter, CountingBloomFilter};
+pub use cuckoo::{CuckooHashMap, CuckooFilter};
+pub use sketch::{HyperLogLog, CountMinSketch, TopK};
+pub use digest::{Digest, Crc32, Md5, Sha1, Sha256};
+pub use hmac::Hmac;
+pub use rolling::{RollingHash, RabinKarp, BuzHash};
+pub use chunker::Chunker;
+pub use perfect::{PerfectHash, StaticMap, StaticMapBuilder};
+pub use merkle::MerkleTree;
+pub use indexed::IndexedHashMap;
+
+use probing::displacement;
+
 const MIN_DEFAULT_CAPACITY: usize = 32;
 
+/// Keep at most 3/4 of the slots occupied (live items plus tombstones),
+/// so that probing always finds an empty slot quickly.
+const LOAD_FACTOR_NUMERATOR: usize = 3;
+const LOAD_FACTOR_DENOMINATOR: usize = 4;
+
+/// Number of items a table with `buckets` slots can hold before it grows.
+fn usable_capacity(buckets: usize) -> usize {
+    buckets / LOAD_FACTOR_DENOMINATOR * LOAD_FACTOR_NUMERATOR
+}
+
+/// Number of slots required to hold `cap` items without growing.
+/// Always a power of two and never less than `MIN_DEFAULT_CAPACITY`.
+fn buckets_for(cap: usize) -> usize {
+    checked_buckets_for(cap).expect("capacity overflow")
+}
+
+/// `buckets_for`, or `None` if the number of slots overflows `usize`.
+fn checked_buckets_for(cap: usize) -> Option<usize> {
+    if cap == 0 {
+        return Some(0);
+    }
+    let min_buckets = cap
+        .checked_mul(LOAD_FACTOR_DENOMINATOR)
+        .map(|n| n.div_ceil(LOAD_FACTOR_NUMERATOR))
+        .and_then(usize::checked_next_power_of_two)?;
+    Some(min_buckets.max(MIN_DEFAULT_CAPACITY))
+}
+
+/// Errors of the fallible `SimpleHashMap` methods, `try_insert` and
+/// `try_reserve`, and of `CuckooHashMap::try_insert`. The map is left as it
+/// was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashMapError {
+    /// The table would need more slots or bytes than a `usize` can count.
+    CapacityExhausted,
+    /// The allocator could not provide memory for a larger table.
+    AllocFailed,
+    /// `try_insert` found the key in the map already.
+    DuplicateKey,
+    /// Too many keys share their buckets for a cuckoo table to place
+    /// another one.
+    TooManyCollisions,
+}
+
+impl Display for HashMapError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let message = match self {
+            HashMapError::CapacityExhausted => "capacity overflow",
+            HashMapError::AllocFailed => "memory allocation failed",
+            HashMapError::DuplicateKey => "key is already in the map",
+            HashMapError::TooManyCollisions => "too many keys collide to place another one",
+        };
+        f.write_str(message)
+    }
+}
+
+impl Error for HashMapError {}
+
+#[derive(Clone)]
 struct HashItem<K, V> {
     key: K,
     value: V,
     hash: u64,
-    del: bool,
 }
 
-pub struct SimpleHashMap<K, V> {
-    table: Vec<Option<HashItem<K, V>>>,
+#[derive(Clone)]
+enum Slot<K, V> {
+    Empty,
+    /// tombstone left by `Probing::Quadratic` and `Probing::DoubleHashing`
+    Deleted,
+    Full(HashItem<K, V>),
+}
+
+impl<K, V> Slot<K, V> {
+
+    fn item(&self) -> Option<&HashItem<K, V>> {
+        match self {
+            Slot::Full(item) => Some(item),
+            _ => None,
+        }
+    }
+
+    fn item_mut(&mut self) -> Option<&mut HashItem<K, V>> {
+        match self {
+            Slot::Full(item) => Some(item),
+            _ => None,
+        }
+    }
+
+    fn take(&mut self) -> Option<HashItem<K, V>> {
+        match mem::replace(self, Slot::Empty) {
+            Slot::Full(item) => Some(item),
+            _ => None,
+        }
+    }
+}
+
+/// A hash map with open addressing over a single array of tagged slots,
+/// using the collision strategy picked in `Probing`.
+///
+/// [`SwissHashMap`] is the alternative layout: control bytes kept apart
+/// from the items and probed a group at a time. It is a separate type
+/// rather than a mode of this one, with the same core API (constructors,
+/// `insert`, `get`, `remove`, `entry`, `reserve` and iteration), so code
+/// sticking to those switches layouts by changing the type name only.
+#[derive(Clone)]
+pub struct SimpleHashMap<K, V, S = RSBuildHasher> {
+    table: Vec<Slot<K, V>>,
     capacity: usize,
     len: usize,
+    deleted: usize,
+    hash_builder: S,
+    probing: Probing,
 }
 
+/// `SimpleHashMap` keyed with a random SipHash key per map, use it for maps
+/// holding untrusted input such as HTTP header names.
+///
+/// Create one with `SipHashMap::default()` or `SimpleHashMap::with_hasher`.
+pub type SipHashMap<K, V> = SimpleHashMap<K, V, RandomSipState>;
+
 pub struct Iter<'a, K: 'a, V: 'a> {
-    iter: slice::Iter<'a, Option<HashItem<K, V>>>,
+    iter: slice::Iter<'a, Slot<K, V>>,
     consumed: usize,
     current_len: usize,
 }
 
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            iter: self.iter.clone(),
+            consumed: self.consumed,
+            current_len: self.current_len,
+        }
+    }
+}
+
 impl<'a, K, V> Iterator for Iter<'a, K, V> {
     type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<(&'a K, &'a V)> {
         loop {
-            let iter_item_opt = self.iter.next();
-            if iter_item_opt.is_none() {
-                return None;
-            }
-            match iter_item_opt.unwrap() {
-                Some(item) => {
-                    if !item.del {
-                        self.consumed += 1;
-                        return Some((&item.key, &item.value))
-                    }
-                },
-                None => continue
+            if let Slot::Full(item) = self.iter.next()? {
+                self.consumed += 1;
+                return Some((&item.key, &item.value))
             }
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
 }
 
 impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {
@@ -55,81 +217,355 @@ impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {
     }
 }
 
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    iter: slice::IterMut<'a, Slot<K, V>>,
+    consumed: usize,
+    current_len: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        loop {
+            if let Slot::Full(item) = self.iter.next()? {
+                self.consumed += 1;
+                return Some((&item.key, &mut item.value))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {
+    fn len(&self) -> usize {
+        self.current_len - self.consumed
+    }
+}
+
+/// Owning iterator over the items of a map, in table order.
+pub struct IntoIter<K, V> {
+    iter: vec::IntoIter<Slot<K, V>>,
+    consumed: usize,
+    current_len: usize,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        loop {
+            if let Slot::Full(item) = self.iter.next()? {
+                self.consumed += 1;
+                return Some((item.key, item.value))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {
+    fn len(&self) -> usize {
+        self.current_len - self.consumed
+    }
+}
+
+/// Takes the items out of a map, see `SimpleHashMap::drain`.
+///
+/// Each item taken leaves a tombstone and is counted out of the map right
+/// away, so the map stays consistent at every step. The items which are
+/// not yielded are dropped along with the iterator, which then clears the
+/// tombstones. A leaked iterator leaves the map with the items not yielded
+/// yet.
+pub struct Drain<'a, K: 'a, V: 'a> {
+    table: &'a mut [Slot<K, V>],
+    index: usize,
+    len: &'a mut usize,
+    deleted: &'a mut usize,
+}
+
+impl<'a, K, V> Iterator for Drain<'a, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        while let Some(slot) = self.table.get_mut(self.index) {
+            self.index += 1;
+            if slot.item().is_some() {
+                if let Slot::Full(item) = mem::replace(slot, Slot::Deleted) {
+                    *self.len -= 1;
+                    *self.deleted += 1;
+                    return Some((item.key, item.value));
+                }
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Drain<'a, K, V> {
+    fn len(&self) -> usize {
+        *self.len
+    }
+}
+
+impl<'a, K, V> Drop for Drain<'a, K, V> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+        for slot in self.table.iter_mut() {
+            *slot = Slot::Empty;
+        }
+        *self.deleted = 0;
+    }
+}
+
 pub struct Keys<'a, K: 'a, V: 'a> {
     inner: Iter<'a, K, V>,
 }
 
+impl<'a, K, V> Clone for Keys<'a, K, V> {
+    fn clone(&self) -> Self {
+        Keys { inner: self.inner.clone() }
+    }
+}
+
 impl<'a, K, V> Iterator for Keys<'a, K, V> {
     type Item = &'a K;
     fn next(&mut self) -> Option<&'a K> {
         self.inner.next().map(|(k, _)| k)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Keys<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
 }
 
 pub struct Values<'a, K: 'a, V: 'a> {
     inner: Iter<'a, K, V>,
 }
 
+impl<'a, K, V> Clone for Values<'a, K, V> {
+    fn clone(&self) -> Self {
+        Values { inner: self.inner.clone() }
+    }
+}
+
 impl<'a, K, V> Iterator for Values<'a, K, V> {
     type Item = &'a V;
     fn next(&mut self) -> Option<&'a V> {
         self.inner.next().map(|(_, v)| v)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Values<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+pub struct ValuesMut<'a, K: 'a, V: 'a> {
+    inner: IterMut<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for ValuesMut<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+pub struct IntoKeys<K, V> {
+    inner: IntoIter<K, V>,
+}
+
+impl<K, V> Iterator for IntoKeys<K, V> {
+    type Item = K;
+    fn next(&mut self) -> Option<K> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoKeys<K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+pub struct IntoValues<K, V> {
+    inner: IntoIter<K, V>,
+}
+
+impl<K, V> Iterator for IntoValues<K, V> {
+    type Item = V;
+    fn next(&mut self) -> Option<V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoValues<K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<K: Hash + Eq, V> SimpleHashMap<K, V, RSBuildHasher> {
+
+    pub fn new() -> SimpleHashMap<K, V, RSBuildHasher> {
+        SimpleHashMap::with_hasher(Default::default())
+    }
+
+    pub fn with_capacity(capacity: usize) -> SimpleHashMap<K, V, RSBuildHasher> {
+        SimpleHashMap::with_capacity_and_hasher(capacity, Default::default())
+    }
+
+    /// Creates an empty map which resolves collisions with `probing`.
+    pub fn with_probing(probing: Probing) -> SimpleHashMap<K, V, RSBuildHasher> {
+        let mut map = SimpleHashMap::new();
+        map.set_probing(probing);
+        map
+    }
 }
 
-impl<K: Hash + Eq, V> SimpleHashMap<K, V> {
+impl<K, V, S> SimpleHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
 
-    pub fn new() -> SimpleHashMap<K, V> {
+    /// Creates an empty map which will use the given hash builder to hash
+    /// keys, e.g. `std::collections::hash_map::RandomState`.
+    pub fn with_hasher(hash_builder: S) -> SimpleHashMap<K, V, S> {
         SimpleHashMap {
             table: Vec::new(),
             capacity: 0,
             len: 0,
+            deleted: 0,
+            hash_builder,
+            probing: Probing::default(),
         }
     }
 
-    pub fn with_capacity(capacity: usize) -> SimpleHashMap<K, V> {
-        let mut tab = Vec::with_capacity(capacity);
-        for i in 0..capacity {
-            tab.push(None);
-        }
-        assert_eq!(tab.capacity(), capacity);
-        assert_eq!(tab.len(), capacity);
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> SimpleHashMap<K, V, S> {
+        let buckets = buckets_for(capacity);
         SimpleHashMap {
-            table: tab,
-            capacity: capacity,
+            table: Self::empty_table(buckets),
+            capacity: buckets,
             len: 0,
+            deleted: 0,
+            hash_builder,
+            probing: Probing::default(),
+        }
+    }
+
+    /// Returns a reference to the map's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Returns the collision resolution strategy of the map.
+    pub fn probing(&self) -> Probing {
+        self.probing
+    }
+
+    /// Switches the collision resolution strategy, every item is rehashed
+    /// into a table of the same size.
+    pub fn set_probing(&mut self, probing: Probing) {
+        if self.probing != probing {
+            self.probing = probing;
+            self.resize(self.capacity);
+        }
+    }
+
+    fn empty_table(buckets: usize) -> Vec<Slot<K, V>> {
+        Self::try_empty_table(buckets).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Allocates a table of `buckets` empty slots, reporting a failed
+    /// allocation rather than aborting the process.
+    fn try_empty_table(buckets: usize) -> Result<Vec<Slot<K, V>>, HashMapError> {
+        let bytes = buckets.checked_mul(mem::size_of::<Slot<K, V>>());
+        if bytes.is_none_or(|bytes| bytes > isize::MAX as usize) {
+            return Err(HashMapError::CapacityExhausted);
         }
+        let mut tab = Vec::new();
+        tab.try_reserve_exact(buckets).map_err(|_| HashMapError::AllocFailed)?;
+        tab.resize_with(buckets, || Slot::Empty);
+        Ok(tab)
+    }
+}
+
+impl<K, V, S> Default for SimpleHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher + Default {
+    fn default() -> SimpleHashMap<K, V, S> {
+        SimpleHashMap::with_hasher(Default::default())
     }
 }
 
 ///
-/// Subset implementation of original HashMap functions
+/// Implementation of original HashMap functions
 ///
-/// `TODO:`
-/// support retain
-/// support reserve
-/// support expand
-/// support resize
-/// support get_mut
-/// support entry
-/// support clone
-/// support shrink
-/// support size_hint
-impl<K, V> SimpleHashMap<K, V> where K: Hash + Eq {
+/// Lookups take any borrowed form of the key, as `get::<str>` on a map
+/// with `String` keys, as long as it hashes and compares like the key.
+impl<K, V, S> SimpleHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
 
+    /// Returns the number of elements the map can hold without reallocating.
     pub fn capacity(&self) -> usize {
-        self.capacity
+        usable_capacity(self.capacity)
     }
 
-    pub fn keys(&self) -> Keys<K, V> {
+    pub fn keys(&self) -> Keys<'_, K, V> {
         Keys { inner: self.iter() }
     }
 
-    pub fn values(&self) -> Values<K, V> {
+    pub fn into_keys(self) -> IntoKeys<K, V> {
+        IntoKeys { inner: self.into_iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
         Values { inner: self.iter() }
     }
 
-    pub fn iter(&self) -> Iter<K, V> {
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut { inner: self.iter_mut() }
+    }
+
+    pub fn into_values(self) -> IntoValues<K, V> {
+        IntoValues { inner: self.into_iter() }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
             iter: self.table.iter(),
             consumed: 0,
@@ -137,264 +573,655 @@ impl<K, V> SimpleHashMap<K, V> where K: Hash + Eq {
         }
     }
 
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            iter: self.table.iter_mut(),
+            consumed: 0,
+            current_len: self.len,
+        }
+    }
+
     pub fn len(&self) -> usize { self.len }
 
     pub fn is_empty(&self) -> bool { self.len() == 0 }
 
+    /// Removes all items, but keeps the allocated memory for reuse.
     pub fn clear(&mut self) {
+        for slot in self.table.iter_mut() {
+            *slot = Slot::Empty;
+        }
         self.len = 0;
-        self.table.drain(..);
+        self.deleted = 0;
     }
 
-    pub fn get(&self, k: &K) -> Option<&V> {
-        if self.capacity == 0 {
-            return None;
+    /// Clears the map, returning all items as an iterator. The allocated
+    /// memory is kept for reuse.
+    ///
+    /// The map is empty once the iterator is dropped. If it is leaked
+    /// instead, the map keeps the items it has not yielded.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        Drain {
+            table: &mut self.table,
+            index: 0,
+            len: &mut self.len,
+            deleted: &mut self.deleted,
         }
+    }
 
-        match self.find_index(k) {
-            Some(index) => {
-                if let Some(ref item) = &self.table[index] {
-                    Some(&item.value)
-                } else {
-                    panic!("find index but have no value")
+    /// Retains only the items for which `f` returns `true`, visiting each
+    /// item exactly once.
+    ///
+    /// The scan starts right after an empty slot, which a backward shift
+    /// never moves an item across, so the items that a removal moves back
+    /// all come from the part of the table not visited yet.
+    pub fn retain<F>(&mut self, mut f: F) where F: FnMut(&K, &mut V) -> bool {
+        let start = match self.table.iter().position(|slot| matches!(slot, Slot::Empty)) {
+            Some(empty) => empty + 1,
+            None => return,
+        };
+        let mut i = 0;
+        while i < self.capacity {
+            let idx = (start + i) % self.capacity;
+            if let Some(item) = self.table[idx].item_mut() {
+                if !f(&item.key, &mut item.value) {
+                    self.remove_at(idx);
+                    // the hole may have been filled by the next item of the cluster
+                    continue;
                 }
-            },
-            None => None,
+            }
+            i += 1;
         }
     }
 
-    pub fn contains_key(&self, k: &K) -> bool {
-        if self.capacity == 0 {
-            return false;
-        }
-        match self.find_index(k) {
-            Some(_) => true,
-            _ => false
+    /// Reserves capacity for at least `additional` more elements.
+    ///
+    /// The table grows to the next power of two that keeps the load factor
+    /// under 3/4, and every item is rehashed into it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity overflows or cannot be allocated, see
+    /// `try_reserve`.
+    pub fn reserve(&mut self, additional: usize) {
+        if let Err(e) = self.try_reserve(additional) {
+            panic!("{}", e);
         }
     }
 
-    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
-
-        // allocate minimum capacity, lazy evaluation
-        if self.capacity == 0 {
-            self.capacity = MIN_DEFAULT_CAPACITY;
-            self.table = Vec::with_capacity(MIN_DEFAULT_CAPACITY);
-            for _ in 0..self.capacity {
-                self.table.push(None);
-            }
-            self.len = 0;
-            assert_eq!(self.len(), 0);
+    /// Tries to reserve capacity for at least `additional` more elements,
+    /// like `reserve`, but returns an error instead of panicking when the
+    /// capacity overflows or the allocator fails. The map is unchanged on
+    /// error.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), HashMapError> {
+        let required = self.len.checked_add(additional).ok_or(HashMapError::CapacityExhausted)?;
+        if required > self.capacity() {
+            let buckets = checked_buckets_for(required).ok_or(HashMapError::CapacityExhausted)?;
+            self.try_resize(buckets)?;
         }
+        Ok(())
+    }
 
-        // make sure there's a usable place
-        if self.len() >= self.capacity {
-            panic!("insert failed, cache is full")
+    /// Shrinks the capacity of the map as much as possible, while keeping
+    /// the load factor under 3/4.
+    pub fn shrink_to_fit(&mut self) {
+        let buckets = buckets_for(self.len);
+        if buckets < self.capacity {
+            self.resize(buckets);
         }
+    }
 
-        let mut state = self.build_hasher();
-        k.hash(&mut state);
-        let hash_value = state.finish();
-
-        let origin = (hash_value as usize) % self.capacity;
-//        println!("[insert] key hash value = {:x}, original position = {}", hash_value, origin);
+    /// Moves every item into a fresh table of `buckets` slots. Tombstones
+    /// are dropped along the way.
+    ///
+    /// Items are placed by their stored `hash`, so keys are never hashed
+    /// again and never compared, because they are known to be distinct.
+    fn resize(&mut self, buckets: usize) {
+        if let Err(e) = self.try_resize(buckets) {
+            panic!("{}", e);
+        }
+    }
 
-        let new_item = HashItem {
-            key: k,
-            value: v,
-            hash: hash_value,
-            del: false
-        };
+    /// `resize`, which allocates the new table before touching the old one,
+    /// so that the map is unchanged if the allocation fails.
+    fn try_resize(&mut self, buckets: usize) -> Result<(), HashMapError> {
+        debug_assert!(usable_capacity(buckets) >= self.len);
+        let old_table = mem::replace(&mut self.table, Self::try_empty_table(buckets)?);
+        self.capacity = buckets;
+        self.len = 0;
+        self.deleted = 0;
 
-        let mut idx = origin;
-        while self.try_to_insert_at(idx, &new_item.key).is_none() {
-            idx = (idx + 1) % self.capacity;
-            if idx == origin {
-                panic!("cache is full, but can not find a hole")
+        for mut slot in old_table {
+            if let Some(item) = slot.take() {
+                self.insert_unique(item);
             }
         }
-
-        let old_value = self.table.remove(idx);
-        self.table.insert(idx, Some(new_item));
-        self.len += 1;
-
-        old_value.filter(|item|!item.del)
-            .map_or(None::<V>, |old_item| Some(old_item.value))
+        Ok(())
     }
 
-    fn try_to_insert_at(&self, index: usize, k: &K) -> Option<usize> {
-        match &self.table[index] {
-            None => {
-                println!("find an empty position at {}", index);
-                Some(index)
-            },
-            Some(item) if item.del => {
-                println!("find position {} with deleted status", index);
-                Some(index)
-            },
-            Some(item) if !item.del && &item.key == k => {
-                println!("find position {} with existing key", index);
-                Some(index)
-            },
-            _ => {
-                None
+    /// Inserts an item whose key is known not to be in the map.
+    ///
+    /// The table has room for it, and every probe sequence visits all
+    /// slots, so an empty one turns up before the sequence ends.
+    fn insert_unique(&mut self, item: HashItem<K, V>) {
+        for i in 0..self.capacity {
+            let idx = self.probing.position(item.hash, i, self.capacity);
+            match &self.table[idx] {
+                Slot::Full(other) if !self.richer_than(other, idx, i) => {},
+                _ => return self.place(idx, item),
             }
         }
+        unreachable!("table is full, but the load factor keeps a hole")
     }
 
-    pub fn remove(&mut self, k: &K) -> Option<V> {
-        if self.capacity == 0 {
-            return None;
-        }
-        match self.find_index(k) {
-            Some(index) => {
-                let mut old_item_opt = self.table.remove(index);
-                if let Some(mut item) = old_item_opt {
-                    item.del = true;
-                    let mut ret = None;
-                    unsafe {
-                        let old_value: V = mem::transmute_copy(&item.value);
-                        ret = Some(old_value);
-                    }
-                    self.table.insert(index, Some(item));
-                    self.len -= 1;
-                    ret
-                } else {
-                    None
-                }
-            }
-            None => None
-        }
+    /// Whether `Probing::RobinHood` hands slot `index`, which holds `item`,
+    /// over to an item which got there with `i` probes.
+    fn richer_than(&self, item: &HashItem<K, V>, index: usize, i: usize) -> bool {
+        self.probing == Probing::RobinHood && displacement(item.hash, index, self.capacity) < i
     }
 
-    fn find_index(&self, k: &K) -> Option<usize> {
-        let mut state = self.build_hasher();
-        k.hash(&mut state);
-        let hash_value = state.finish();
-        let origin = (hash_value as usize) % self.capacity;
-//        println!("[find_index] key hash value = {:x}, original position = {}",
-//                 hash_value, origin);
+    /// Puts a new item at slot `index`, found by `probe` or `insert_unique`.
+    ///
+    /// With `Probing::RobinHood` the slot may hold a richer item, which is
+    /// carried forward, swapping places with every item that is closer to
+    /// its origin, until an empty slot takes the last one.
+    fn place(&mut self, index: usize, item: HashItem<K, V>) {
+        self.len += 1;
+        let mut carry = match mem::replace(&mut self.table[index], Slot::Full(item)) {
+            Slot::Empty => return,
+            Slot::Deleted => {
+                self.deleted -= 1;
+                return;
+            },
+            Slot::Full(displaced) => displaced,
+        };
 
-        let mut idx = origin;
+        let mut idx = index;
         loop {
-            if self.exists(idx, k, hash_value) {
-                return Some(idx);
-            }
             idx = (idx + 1) % self.capacity;
-            if idx == origin {
-                break;
+            let distance = displacement(carry.hash, idx, self.capacity);
+            match &mut self.table[idx] {
+                Slot::Full(item) => {
+                    if displacement(item.hash, idx, self.capacity) < distance {
+                        mem::swap(item, &mut carry);
+                    }
+                },
+                slot => {
+                    *slot = Slot::Full(carry);
+                    return;
+                },
             }
         }
-        None
     }
 
-    fn exists(&self, index: usize, k: &K, hash: u64) -> bool {
-        let item_opt = &self.table[index];
-        if let Some(ref item) = item_opt {
-            if &item.key == k && item.hash == hash && !item.del {
-                true
-            } else {
-                false
-            }
-        } else {
-            false
+    /// Makes sure one more item can be inserted without exceeding the load
+    /// factor, counting tombstones as occupied slots.
+    fn reserve_one(&mut self) {
+        if let Err(e) = self.try_reserve_one() {
+            panic!("{}", e);
         }
     }
-}
 
-impl<K, V> BuildHasher for SimpleHashMap<K, V> {
-    type Hasher = SimpleRSHasher;
+    fn try_reserve_one(&mut self) -> Result<(), HashMapError> {
+        if self.len + self.deleted < self.capacity() {
+            return Ok(());
+        }
+        if self.len < self.capacity() {
+            // mostly tombstones, rehash in place to reclaim them
+            self.try_resize(self.capacity)
+        } else if self.capacity == 0 {
+            self.try_resize(MIN_DEFAULT_CAPACITY)
+        } else {
+            self.try_resize(self.capacity.checked_mul(2).ok_or(HashMapError::CapacityExhausted)?)
+        }
+    }
 
-    fn build_hasher(&self) -> Self::Hasher {
-        SimpleRSHasher(0)
+    pub fn get<Q>(&self, k: &Q) -> Option<&V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        self.get_key_value(k).map(|(_, v)| v)
     }
-}
 
-impl<'a, K, V> IntoIterator for &'a SimpleHashMap<K, V> where K: Hash + Eq {
-    type Item = (&'a K, &'a V);
-    type IntoIter = Iter<'a, K, V>;
+    /// Returns the key stored in the map along with its value.
+    pub fn get_key_value<Q>(&self, k: &Q) -> Option<(&K, &V)> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let item = self.table[self.find_index(k)?].item()?;
+        Some((&item.key, &item.value))
+    }
 
-    fn into_iter(self) -> Iter<'a, K, V> {
-        self.iter()
+    pub fn get_mut<Q>(&mut self, k: &Q) -> Option<&mut V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let index = self.find_index(k)?;
+        self.table[index].item_mut().map(|item| &mut item.value)
     }
-}
 
-impl<K, V> FromIterator<(K, V)> for SimpleHashMap<K, V> where K: Hash + Eq {
+    pub fn contains_key<Q>(&self, k: &Q) -> bool where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        self.find_index(k).is_some()
+    }
 
-    fn from_iter<I: IntoIterator<Item=(K, V)>>(iter: I) -> SimpleHashMap<K, V> {
-        let mut map = SimpleHashMap::new();
-        for (key, value) in iter {
-            map.insert(key, value);
+    /// Inserts a key-value pair, and returns the value it replaces.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table has to grow and cannot, see `try_insert`.
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        match self.entry(k) {
+            Entry::Occupied(mut entry) => Some(entry.insert(v)),
+            Entry::Vacant(entry) => {
+                entry.insert(v);
+                None
+            }
         }
-        map
     }
-}
 
-impl<K, V> PartialEq for SimpleHashMap<K, V>
-    where K: Hash + Eq,
-          V: PartialEq {
+    /// Inserts a key-value pair and returns a mutable reference to the
+    /// value, unless the key is in the map already.
+    ///
+    /// Unlike `insert`, an existing value is never replaced, and a table
+    /// which cannot grow is reported instead of panicking. On error the
+    /// items of the map are unchanged and `v` is dropped.
+    pub fn try_insert(&mut self, k: K, v: V) -> Result<&mut V, HashMapError> {
+        let hash = self.hash_builder.hash_one(&k);
+        if let Probe::Found(_) = self.probe(&k, hash) {
+            return Err(HashMapError::DuplicateKey);
+        }
+        // growing rehashes every item, so the slot is looked for afterwards
+        self.try_reserve_one()?;
+        match self.probe(&k, hash) {
+            Probe::Vacant(index) => Ok(VacantEntry { map: self, key: k, hash, index }.insert(v)),
+            Probe::Found(_) | Probe::Full => unreachable!("reserved table has no hole for a new key"),
+        }
+    }
 
-    fn eq(&self, other: &SimpleHashMap<K, V>) -> bool {
-        if self.len() != other.len() {
-            return false;
+    /// Gets the given key's corresponding entry in the map for in-place
+    /// manipulation.
+    ///
+    /// The key is hashed and probed once, a vacant entry remembers the
+    /// slot where it will be inserted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table has to grow and cannot, even when the key is in
+    /// the map already, since the table grows before the probe.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        // allocate minimum capacity lazily, grow once the load factor is reached
+        self.reserve_one();
+
+        let hash = self.hash_builder.hash_one(&key);
+        match self.probe(&key, hash) {
+            Probe::Found(index) => Entry::Occupied(OccupiedEntry {
+                map: self,
+                index,
+            }),
+            Probe::Vacant(index) => Entry::Vacant(VacantEntry {
+                map: self,
+                key,
+                hash,
+                index,
+            }),
+            Probe::Full => unreachable!("table is full, but the load factor keeps a hole"),
         }
+    }
 
-        self.iter().all(
-            |(key, value)| other.get(key).map_or(
-                false, |v| *v == *value)
-        )
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        self.remove_entry(k).map(|(_, v)| v)
     }
-}
 
-impl<K, V> Debug for SimpleHashMap<K, V>
-    where K: Hash + Eq + Debug,
-          V: Debug {
-    fn fmt(&self, f: &mut Formatter) -> Result {
-        f.debug_map().entries(self.iter()).finish()
+    /// Removes a key from the map, returning the stored key and its value.
+    pub fn remove_entry<Q>(&mut self, k: &Q) -> Option<(K, V)> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let item = self.remove_at(self.find_index(k)?);
+        Some((item.key, item.value))
     }
-}
 
-impl<'a, K, V> Index<&'a K> for SimpleHashMap<K, V>
-    where K: Hash + Eq + Sized {
+    /// Takes the item at `index` out of the table.
+    ///
+    /// Linear and Robin Hood probing close the hole with backward-shift
+    /// deletion: every following item of the cluster which may live in the
+    /// hole, i.e. whose original position is not between the hole and
+    /// itself, moves back one hole at a time. The cluster stays contiguous,
+    /// so probe chains never get longer by removal. The other strategies
+    /// leave a tombstone, which the next rehash cleans up.
+    fn remove_at(&mut self, index: usize) -> HashItem<K, V> {
+        self.len -= 1;
+        if !self.probing.shifts_back() {
+            self.deleted += 1;
+            let removed = mem::replace(&mut self.table[index], Slot::Deleted);
+            return match removed {
+                Slot::Full(item) => item,
+                _ => panic!("find index but have no value"),
+            };
+        }
 
-    type Output = V;
+        let removed = self.table[index].take().expect("find index but have no value");
 
-    fn index(&self, key: &K) -> &V {
-        self.get(key).expect("no entry found for key")
+        let mut hole = index;
+        let mut idx = (index + 1) % self.capacity;
+        while let Slot::Full(item) = &self.table[idx] {
+            let distance_to_origin = displacement(item.hash, idx, self.capacity);
+            if self.probing == Probing::RobinHood && distance_to_origin == 0 {
+                // everything from here on is sorted behind an item at home
+                break;
+            }
+            let distance_to_hole = (idx + self.capacity - hole) % self.capacity;
+            if distance_to_origin >= distance_to_hole {
+                self.table[hole] = mem::replace(&mut self.table[idx], Slot::Empty);
+                hole = idx;
+            }
+            idx = (idx + 1) % self.capacity;
+        }
+
+        removed
+    }
+
+    fn find_index<Q>(&self, k: &Q) -> Option<usize> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        if self.len == 0 {
+            return None;
+        }
+        match self.probe(k, self.hash_builder.hash_one(k)) {
+            Probe::Found(index) => Some(index),
+            Probe::Vacant(_) | Probe::Full => None,
+        }
+    }
+
+    /// Walks the probe chain of `k`, starting from its original position.
+    ///
+    /// An empty slot ends the chain, tombstones do not. If the key is not
+    /// found, the first tombstone or empty slot on the chain is returned as
+    /// the place to insert it. Robin Hood probing also stops at the first
+    /// item closer to its origin than `k` would be, since `k` would have
+    /// taken that slot. A chain without any free slot, which the load
+    /// factor rules out, ends with `Probe::Full`.
+    fn probe<Q>(&self, k: &Q, hash: u64) -> Probe where K: Borrow<Q>, Q: Eq + ?Sized {
+        let mut vacant = None;
+        for i in 0..self.capacity {
+            let idx = self.probing.position(hash, i, self.capacity);
+            match &self.table[idx] {
+                Slot::Empty => return Probe::Vacant(vacant.unwrap_or(idx)),
+                Slot::Deleted => {
+                    vacant.get_or_insert(idx);
+                },
+                Slot::Full(item) if item.hash == hash && item.key.borrow() == k => return Probe::Found(idx),
+                Slot::Full(item) if self.richer_than(item, idx, i) => return Probe::Vacant(idx),
+                Slot::Full(_) => {},
+            }
+        }
+        vacant.map_or(Probe::Full, Probe::Vacant)
+    }
+
+    /// Number of slots inspected to find each item, in table order.
+    fn probe_lengths(&self) -> Vec<usize> {
+        self.table.iter().enumerate()
+            .filter_map(|(idx, slot)| slot.item().map(|item| self.probe_length(item.hash, idx)))
+            .collect()
+    }
+
+    /// Number of slots inspected to find the item with `hash` at `idx`.
+    fn probe_length(&self, hash: u64, idx: usize) -> usize {
+        (0..self.capacity)
+            .position(|i| self.probing.position(hash, i, self.capacity) == idx)
+            .expect("item is not on its probe sequence") + 1
     }
 }
 
+enum Probe {
+    Found(usize),
+    Vacant(usize),
+    Full,
+}
 
-///Use simple RS hash function.
+/// A view into a single entry in a map, which may either be vacant or
+/// occupied.
 ///
-///Refer to http://www.partow.net/programming/hashfunctions/
+/// This enum is constructed from the [`entry`] method on [`SimpleHashMap`].
 ///
-pub struct SimpleRSHasher(u64);
+/// [`entry`]: struct.SimpleHashMap.html#method.entry
+pub enum Entry<'a, K: 'a, V: 'a, S: 'a = RSBuildHasher> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K: 'a, V: 'a, S: 'a = RSBuildHasher> {
+    map: &'a mut SimpleHashMap<K, V, S>,
+    index: usize,
+}
+
+pub struct VacantEntry<'a, K: 'a, V: 'a, S: 'a = RSBuildHasher> {
+    map: &'a mut SimpleHashMap<K, V, S>,
+    key: K,
+    hash: u64,
+    index: usize,
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> Entry<'a, K, V, S> {
+
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V: Default, S: BuildHasher> Entry<'a, K, V, S> {
+
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
 
-impl Hasher for SimpleRSHasher {
+    pub fn key(&self) -> &K {
+        &self.item().key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.item().value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.table[self.index].item_mut().expect("occupied entry has no value").value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.table[self.index].item_mut().expect("occupied entry has no value").value
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Takes the value out of the entry, and returns it.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Takes the key and value out of the entry, and returns them.
+    pub fn remove_entry(self) -> (K, V) {
+        let item = self.map.remove_at(self.index);
+        (item.key, item.value)
+    }
+
+    fn item(&self) -> &HashItem<K, V> {
+        self.map.table[self.index].item().expect("occupied entry has no value")
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Sets the value of the entry at the slot found by the probe, and
+    /// returns a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let new_item = HashItem {
+            key: self.key,
+            value,
+            hash: self.hash,
+        };
+        let map = self.map;
+        map.place(self.index, new_item);
+        &mut map.table[self.index].item_mut().expect("vacant entry was just filled").value
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a SimpleHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut SimpleHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for SimpleHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            iter: self.table.into_iter(),
+            consumed: 0,
+            current_len: self.len,
+        }
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for SimpleHashMap<K, V, S>
+    where K: Hash + Eq,
+          S: BuildHasher + Default {
+
+    /// # Panics
+    ///
+    /// Panics if the table has to grow and cannot, see `try_reserve`.
+    fn from_iter<I: IntoIterator<Item=(K, V)>>(iter: I) -> SimpleHashMap<K, V, S> {
+        let mut map = SimpleHashMap::with_hasher(Default::default());
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for SimpleHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+
+    /// Reserves room for the lower bound of the iterator up front, or half
+    /// of it when the map is not empty, since some keys may already be in.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table has to grow and cannot, see `try_reserve`.
+    fn extend<I: IntoIterator<Item=(K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        let additional = if self.is_empty() { iter.size_hint().0 } else { iter.size_hint().0.div_ceil(2) };
+        self.reserve(additional);
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K, V, S> Extend<(&'a K, &'a V)> for SimpleHashMap<K, V, S>
+    where K: 'a + Hash + Eq + Copy,
+          V: 'a + Copy,
+          S: BuildHasher {
 
-    fn finish(&self) -> u64 {
-        self.0
+    /// # Panics
+    ///
+    /// Panics if the table has to grow and cannot, see `try_reserve`.
+    fn extend<I: IntoIterator<Item=(&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(&key, &value)| (key, value)));
     }
+}
 
-    fn write(&mut self, bytes: &[u8]) {
-        let b: u64 = 378551;
-        let mut a: u64 = 63689;
-        let mut hash: u64 = 0;
+impl<K, V, S> PartialEq for SimpleHashMap<K, V, S>
+    where K: Hash + Eq,
+          V: PartialEq,
+          S: BuildHasher {
 
-        for byte in bytes {
-            hash = hash.wrapping_mul(a).wrapping_add(*byte as u64);
-            a = a.wrapping_mul(b);
+    fn eq(&self, other: &SimpleHashMap<K, V, S>) -> bool {
+        if self.len() != other.len() {
+            return false;
         }
 
-        self.0 = hash;
+        self.iter().all(
+            |(key, value)| other.get(key) == Some(value)
+        )
+    }
+}
+
+impl<K, V, S> Eq for SimpleHashMap<K, V, S>
+    where K: Hash + Eq,
+          V: Eq,
+          S: BuildHasher {}
+
+impl<K, V, S> Debug for SimpleHashMap<K, V, S>
+    where K: Hash + Eq + Debug,
+          V: Debug,
+          S: BuildHasher {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, Q: ?Sized, V, S> Index<&Q> for SimpleHashMap<K, V, S>
+    where K: Hash + Eq + Borrow<Q>,
+          Q: Hash + Eq,
+          S: BuildHasher {
+
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
     }
 }
 
-/// copy part of unit tests from HashMap
+
+/// unit tests ported from HashMap
 #[cfg(test)]
 mod test_simple_map {
-    use super::SimpleHashMap;
-    use super::MIN_DEFAULT_CAPACITY;
+    use super::{SimpleHashMap, SipHashMap, Probing, HashMapError};
+    use super::Entry::{Occupied, Vacant};
+    use super::hasher::{FnvBuildHasher, FxBuildHasher, xorshift};
+    use super::test_util::Droppable;
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::mem;
 
     #[test]
     fn test_zero_capacities() {
@@ -436,23 +1263,69 @@ mod test_simple_map {
     }
 
     #[test]
-    fn test_empty_iter() {
-        let mut m: SimpleHashMap<i32, bool> = SimpleHashMap::new();
-        assert_eq!(m.keys().next(), None);
-        assert_eq!(m.values().next(), None);
-        assert_eq!(m.iter().next(), None);
+    fn test_clone() {
+        let mut m = SimpleHashMap::new();
         assert_eq!(m.len(), 0);
-        assert!(m.is_empty());
+        assert!(m.insert(1, 2).is_none());
+        assert_eq!(m.len(), 1);
+        assert!(m.insert(2, 4).is_none());
+        assert_eq!(m.len(), 2);
+        let m2 = m.clone();
+        assert_eq!(*m2.get(&1).unwrap(), 2);
+        assert_eq!(*m2.get(&2).unwrap(), 4);
+        assert_eq!(m2.len(), 2);
+    }
+
+    #[test]
+    fn test_clone_keeps_tombstones() {
+        let mut m = SimpleHashMap::with_probing(Probing::Quadratic);
+        for i in 0..100 {
+            m.insert(i, i.to_string());
+        }
+        for i in (0..100).step_by(2) {
+            m.remove(&i);
+        }
+        let mut m2 = m.clone();
+        assert_eq!(m2, m);
+        assert_eq!(m2.deleted, m.deleted);
+        for i in 0..100 {
+            m2.insert(i, i.to_string());
+        }
+        assert_eq!(m2.len(), 100);
+        assert_eq!(m.len(), 50);
+    }
+
+    #[test]
+    fn test_empty_entry() {
+        let mut m: SimpleHashMap<i32, bool> = SimpleHashMap::new();
+        match m.entry(0) {
+            Occupied(_) => panic!(),
+            Vacant(_) => {}
+        }
+        assert!(*m.entry(0).or_insert(true));
+        assert_eq!(m.len(), 1);
+    }
+
+    #[test]
+    fn test_empty_iter() {
+        let mut empty: SimpleHashMap<i32, i32> = SimpleHashMap::new();
+        assert_eq!(empty.drain().next(), None);
+        assert_eq!(empty.keys().next(), None);
+        assert_eq!(empty.values().next(), None);
+        assert_eq!(empty.values_mut().next(), None);
+        assert_eq!(empty.iter().next(), None);
+        assert_eq!(empty.iter_mut().next(), None);
+        assert_eq!(empty.len(), 0);
+        assert!(empty.is_empty());
+        assert_eq!(empty.into_iter().next(), None);
     }
 
     #[test]
     fn test_lots_of_insertions() {
-//        let mut m = SimpleHashMap::new();
-        let mut m = SimpleHashMap::with_capacity(MIN_DEFAULT_CAPACITY*100);
+        let mut m = SimpleHashMap::new();
 
         // Try this a few times to make sure we never screw up the hashmap's
         // internal state.
-//        for _ in 0..10 {//TODO: take long time, improve later
         for _ in 0..10 {
             assert!(m.is_empty());
 
@@ -510,12 +1383,27 @@ mod test_simple_map {
         }
     }
 
+    #[test]
+    fn test_find_mut() {
+        let mut m = SimpleHashMap::new();
+        assert!(m.insert(1, 12).is_none());
+        assert!(m.insert(2, 8).is_none());
+        assert!(m.insert(5, 14).is_none());
+        let new = 100;
+        match m.get_mut(&5) {
+            None => panic!(),
+            Some(x) => *x = new,
+        }
+        assert_eq!(m.get(&5), Some(&new));
+        assert_eq!(m.get_mut(&3), None);
+    }
+
     #[test]
     fn test_insert_overwrite() {
         let mut m = SimpleHashMap::new();
         assert!(m.insert(1, 2).is_none());
         assert_eq!(*m.get(&1).unwrap(), 2);
-        assert!(!m.insert(1, 3).is_none());
+        assert!(m.insert(1, 3).is_some());
         assert_eq!(*m.get(&1).unwrap(), 3);
     }
 
@@ -567,8 +1455,14 @@ mod test_simple_map {
         assert_eq!(m.remove(&1), None);
     }
 
-    //TODO: support auto resize
-    /*
+    #[test]
+    fn test_remove_entry() {
+        let mut m = SimpleHashMap::new();
+        m.insert(1, 2);
+        assert_eq!(m.remove_entry(&1), Some((1, 2)));
+        assert_eq!(m.remove(&1), None);
+    }
+
     #[test]
     fn test_iterate() {
         let mut m = SimpleHashMap::with_capacity(4);
@@ -585,7 +1479,6 @@ mod test_simple_map {
         }
         assert_eq!(observed, 0xFFFF_FFFF);
     }
-    */
 
     #[test]
     fn test_keys() {
@@ -609,6 +1502,44 @@ mod test_simple_map {
         assert!(values.contains(&'c'));
     }
 
+    #[test]
+    fn test_values_mut() {
+        let vec = vec![(1, 1), (2, 2), (3, 3)];
+        let mut map: SimpleHashMap<_, _> = vec.into_iter().collect();
+        for value in map.values_mut() {
+            *value *= 2
+        }
+        let values: Vec<_> = map.values().cloned().collect();
+        assert_eq!(values.len(), 3);
+        assert!(values.contains(&2));
+        assert!(values.contains(&4));
+        assert!(values.contains(&6));
+    }
+
+    #[test]
+    fn test_into_keys() {
+        let vec = vec![(1, 'a'), (2, 'b'), (3, 'c')];
+        let map: SimpleHashMap<_, _> = vec.into_iter().collect();
+        let keys: Vec<_> = map.into_keys().collect();
+
+        assert_eq!(keys.len(), 3);
+        assert!(keys.contains(&1));
+        assert!(keys.contains(&2));
+        assert!(keys.contains(&3));
+    }
+
+    #[test]
+    fn test_into_values() {
+        let vec = vec![(1, 'a'), (2, 'b'), (3, 'c')];
+        let map: SimpleHashMap<_, _> = vec.into_iter().collect();
+        let values: Vec<_> = map.into_values().collect();
+
+        assert_eq!(values.len(), 3);
+        assert!(values.contains(&'a'));
+        assert!(values.contains(&'b'));
+        assert!(values.contains(&'c'));
+    }
+
     #[test]
     fn test_find() {
         let mut m = SimpleHashMap::new();
@@ -678,53 +1609,1125 @@ mod test_simple_map {
     }
 
     #[test]
-    fn test_index() {
-        let mut map = SimpleHashMap::new();
+    fn test_size_hint() {
+        let xs = [(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)];
 
-        map.insert(1, 2);
-        map.insert(2, 1);
-        map.insert(3, 4);
+        let map: SimpleHashMap<_, _> = xs.iter().cloned().collect();
 
-        assert_eq!(map[&2], 1);
+        let mut iter = map.iter();
+
+        for _ in iter.by_ref().take(3) {}
+
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        assert_eq!(map.keys().size_hint(), (6, Some(6)));
+        assert_eq!(map.values().len(), 6);
     }
 
     #[test]
-    #[should_panic]
-    fn test_index_nonexistent() {
-        let mut map = SimpleHashMap::new();
+    fn test_mut_size_hint() {
+        let xs = [(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)];
 
-        map.insert(1, 2);
-        map.insert(2, 1);
-        map.insert(3, 4);
+        let mut map: SimpleHashMap<_, _> = xs.iter().cloned().collect();
+
+        let mut iter = map.iter_mut();
 
-        map[&4];
+        for _ in iter.by_ref().take(3) {}
+
+        assert_eq!(iter.size_hint(), (3, Some(3)));
     }
 
-    // TODO: support auto resize
-    /*
     #[test]
-    fn test_capacity_not_less_than_len() {
-        let mut a = SimpleHashMap::new();
-        let mut item = 0;
+    fn test_iter_mut_len() {
+        let xs = [(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)];
 
-        for _ in 0..116 {
-            a.insert(item, 0);
-            item += 1;
-        }
+        let mut map: SimpleHashMap<_, _> = xs.iter().cloned().collect();
 
-        assert!(a.capacity() > a.len());
+        let mut iter = map.iter_mut();
 
-        let free = a.capacity() - a.len();
-        for _ in 0..free {
-            a.insert(item, 0);
-            item += 1;
-        }
+        for _ in iter.by_ref().take(3) {}
 
-        assert_eq!(a.len(), a.capacity());
+        assert_eq!(iter.len(), 3);
+    }
 
-        // Insert at capacity should cause allocation.
-        a.insert(item, 0);
-        assert!(a.capacity() > a.len());
+    #[test]
+    fn test_into_iter_len() {
+        let xs = [(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)];
+
+        let map: SimpleHashMap<_, _> = xs.iter().cloned().collect();
+
+        let mut iter = map.into_iter();
+        assert_eq!(iter.size_hint(), (6, Some(6)));
+
+        for _ in iter.by_ref().take(4) {}
+
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.count(), 2);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut m: SimpleHashMap<_, _> = (0..10).map(|i| (i, i)).collect();
+        for (k, v) in &mut m {
+            *v += *k * 10;
+        }
+        for (k, v) in m.iter_mut() {
+            assert_eq!(*v, *k * 11);
+            *v = 0;
+        }
+        assert!(m.values().all(|&v| v == 0));
+    }
+
+    #[test]
+    fn test_move_iter() {
+        let hm = {
+            let mut hm = SimpleHashMap::new();
+
+            hm.insert('a', 1);
+            hm.insert('b', 2);
+
+            hm
+        };
+
+        let v = hm.into_iter().collect::<Vec<(char, i32)>>();
+        assert!(v == [('a', 1), ('b', 2)] || v == [('b', 2), ('a', 1)]);
+    }
+
+    #[test]
+    fn test_borrowed_lookups() {
+        let mut m: SimpleHashMap<String, usize> = SimpleHashMap::new();
+        for (i, word) in ["one", "two", "three"].iter().enumerate() {
+            m.insert(word.to_string(), i);
+        }
+        assert_eq!(m.get("two"), Some(&1));
+        assert_eq!(m.get::<str>("four"), None);
+        assert!(m.contains_key("three"));
+        assert_eq!(m["one"], 0);
+        assert_eq!(m.get_key_value("one"), Some((&"one".to_string(), &0)));
+        *m.get_mut("three").unwrap() += 10;
+        assert_eq!(m["three"], 12);
+        assert_eq!(m.remove_entry("two"), Some(("two".to_string(), 1)));
+        assert_eq!(m.remove("two"), None);
+        assert_eq!(m.len(), 2);
+
+        let mut v: SimpleHashMap<Vec<u8>, bool> = SimpleHashMap::new();
+        v.insert(b"bytes".to_vec(), true);
+        assert_eq!(v.get(&b"bytes"[..]), Some(&true));
+    }
+
+    #[test]
+    fn test_occupied_entry_key() {
+        let mut a = SimpleHashMap::new();
+        let key = "hello there";
+        let value = "value goes here";
+        assert!(a.is_empty());
+        a.insert(key, value);
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[key], value);
+
+        match a.entry(key) {
+            Vacant(_) => panic!(),
+            Occupied(e) => assert_eq!(key, *e.key()),
+        }
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[key], value);
+    }
+
+    #[test]
+    fn test_vacant_entry_key() {
+        let mut a = SimpleHashMap::new();
+        let key = "hello there";
+        let value = "value goes here";
+
+        assert!(a.is_empty());
+        match a.entry(key) {
+            Occupied(_) => panic!(),
+            Vacant(e) => {
+                assert_eq!(key, *e.key());
+                e.insert(value);
+            }
+        }
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[key], value);
+    }
+
+    #[test]
+    fn test_occupied_entry_remove_entry() {
+        let mut a = SimpleHashMap::new();
+        a.insert("key".to_string(), 1);
+        match a.entry("key".to_string()) {
+            Vacant(_) => panic!(),
+            Occupied(e) => assert_eq!(e.remove_entry(), ("key".to_string(), 1)),
+        }
+        assert!(a.is_empty());
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut a: SimpleHashMap<_, _> = (0..10).map(|i| (i, i)).collect();
+        a.extend((5..20).map(|i| (i, i * 2)));
+        assert_eq!(a.len(), 20);
+        for i in 0..20 {
+            let expected = if i < 5 { i } else { i * 2 };
+            assert_eq!(a[&i], expected);
+        }
+    }
+
+    #[test]
+    fn test_extend_ref() {
+        let mut a = SimpleHashMap::new();
+        a.insert(1, "one");
+        let mut b = SimpleHashMap::new();
+        b.insert(2, "two");
+        b.insert(3, "three");
+
+        a.extend(&b);
+
+        assert_eq!(a.len(), 3);
+        assert_eq!(a[&1], "one");
+        assert_eq!(a[&2], "two");
+        assert_eq!(a[&3], "three");
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut map: SimpleHashMap<i32, i32> = (0..100).map(|x| (x, x * 10)).collect();
+
+        map.retain(|&k, _| k % 2 == 0);
+        assert_eq!(map.len(), 50);
+        assert_eq!(map[&2], 20);
+        assert_eq!(map[&4], 40);
+        assert_eq!(map[&6], 60);
+    }
+
+    #[test]
+    fn test_retain_mutates_values() {
+        let mut map: SimpleHashMap<i32, i32> = (0..100).map(|x| (x, x)).collect();
+        map.retain(|_, v| {
+            *v += 1;
+            *v % 3 != 0
+        });
+        assert_eq!(map.len(), 67);
+        for k in 0..100 {
+            assert_eq!(map.get(&k).cloned(), if (k + 1) % 3 == 0 { None } else { Some(k + 1) });
+        }
+    }
+
+    #[test]
+    fn test_retain_visits_each_item_once() {
+        for &probing in &STRATEGIES {
+            let keys = random_keys(3000);
+            let mut m: SimpleHashMap<u64, usize> = SimpleHashMap::with_probing(probing);
+            for (i, key) in keys.iter().enumerate() {
+                m.insert(*key % 2000, i);
+            }
+            let mut expected: std::collections::HashMap<u64, usize> = m.iter().map(|(&k, &v)| (k, v)).collect();
+
+            let mut visits = std::collections::HashMap::new();
+            m.retain(|&k, _| {
+                *visits.entry(k).or_insert(0) += 1;
+                k % 3 != 0
+            });
+            expected.retain(|&k, _| k % 3 != 0);
+
+            assert!(visits.values().all(|&n| n == 1), "{:?}", probing);
+            assert_eq!(m.len(), expected.len());
+            for (k, v) in &expected {
+                assert_eq!(m.get(k), Some(v), "{:?} key {}", probing, k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_retain_cluster_wraps_around() {
+        let mut m: IdentityMap<u64> = SimpleHashMap::default();
+        // one cluster from slot 30 over the end of the table to slot 3
+        for &k in &[30, 62, 94, 31, 126, 0] {
+            m.insert(k, k);
+        }
+        let mut visited = Vec::new();
+        m.retain(|&k, _| {
+            visited.push(k);
+            k != 30 && k != 62
+        });
+        visited.sort_unstable();
+        assert_eq!(visited, vec![0, 30, 31, 62, 94, 126]);
+        assert_eq!(m.len(), 4);
+        for &k in &[94, 31, 126, 0] {
+            assert_eq!(m.get(&k), Some(&k));
+        }
+    }
+
+    #[test]
+    fn test_clear() {
+        for &probing in &STRATEGIES {
+            let mut m = SimpleHashMap::with_probing(probing);
+            for i in 0..100 {
+                m.insert(i, i);
+            }
+            m.remove(&3);
+            let cap = m.capacity();
+            m.clear();
+            assert!(m.is_empty());
+            assert_eq!(m.capacity(), cap);
+            assert_eq!(m.deleted, 0);
+            assert_eq!(m.get(&1), None);
+            assert_eq!(m.iter().count(), 0);
+
+            m.insert(1, 1);
+            assert_eq!(m.get(&1), Some(&1));
+            assert_eq!(m.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut m: SimpleHashMap<_, _> = (0..100).map(|i| (i, i * 2)).collect();
+        m.remove(&5);
+        let cap = m.capacity();
+        {
+            let mut drain = m.drain();
+            assert_eq!(drain.len(), 99);
+            let mut drained: Vec<_> = drain.by_ref().take(10).collect();
+            assert_eq!(drain.size_hint(), (89, Some(89)));
+            drained.extend(drain);
+            drained.sort_unstable();
+            let expected: Vec<_> = (0..100).filter(|&i| i != 5).map(|i| (i, i * 2)).collect();
+            assert_eq!(drained, expected);
+        }
+        assert!(m.is_empty());
+        assert_eq!(m.capacity(), cap);
+        assert_eq!(m.get(&1), None);
+
+        m.insert(7, 7);
+        assert_eq!(m.len(), 1);
+        assert_eq!(m[&7], 7);
+    }
+
+    /// Leaking a drain, which safe code can do, must not corrupt the map.
+    #[test]
+    fn test_leaked_drain() {
+        for &probing in &STRATEGIES {
+            let mut m = SimpleHashMap::with_probing(probing);
+            for i in 0..20 {
+                m.insert(i, i);
+            }
+            mem::forget(m.drain());
+            assert_eq!(m.len(), 20);
+            assert_eq!(m.iter().count(), 20);
+
+            let mut drain = m.drain();
+            let taken: Vec<_> = drain.by_ref().take(5).collect();
+            mem::forget(drain);
+            assert_eq!(m.len(), 15);
+            assert_eq!(m.iter().count(), 15);
+            for (k, _) in &taken {
+                assert_eq!(m.get(k), None);
+            }
+
+            for i in 0..1000 {
+                m.insert(i, i);
+            }
+            assert_eq!(m.len(), 1000);
+            assert!((0..1000).all(|i| m[&i] == i));
+            m.drain();
+            assert!(m.is_empty());
+            assert_eq!(m.iter().count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_index() {
+        let mut map = SimpleHashMap::new();
+
+        map.insert(1, 2);
+        map.insert(2, 1);
+        map.insert(3, 4);
+
+        assert_eq!(map[&2], 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_nonexistent() {
+        let mut map = SimpleHashMap::new();
+
+        map.insert(1, 2);
+        map.insert(2, 1);
+        map.insert(3, 4);
+
+        let _ = map[&4];
+    }
+
+    #[test]
+    fn test_capacity_not_less_than_len() {
+        let mut a = SimpleHashMap::new();
+        let mut item = 0;
+
+        for _ in 0..116 {
+            a.insert(item, 0);
+            item += 1;
+        }
+
+        assert!(a.capacity() > a.len());
+
+        let free = a.capacity() - a.len();
+        for _ in 0..free {
+            a.insert(item, 0);
+            item += 1;
+        }
+
+        assert_eq!(a.len(), a.capacity());
+
+        // Insert at capacity should cause allocation.
+        a.insert(item, 0);
+        assert!(a.capacity() > a.len());
+    }
+
+    #[test]
+    fn test_reserve_shrink_to_fit() {
+        let mut m = SimpleHashMap::new();
+        m.insert(0, 0);
+        m.remove(&0);
+        assert!(m.capacity() >= m.len());
+        for i in 0..128 {
+            m.insert(i, i);
+        }
+        m.reserve(256);
+
+        for i in 0..128 {
+            assert_eq!(m.remove(&i), Some(i), "{}", i);
+        }
+        m.shrink_to_fit();
+        assert_eq!(m.len(), 0);
+        assert_eq!(m.capacity(), 0);
+        assert_eq!(m.remove(&0), None);
+        m.insert(0, 0);
+        assert_eq!(m.len(), 1);
+        assert!(m.capacity() >= m.len());
+        assert_eq!(m.remove(&0), Some(0));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut m: SimpleHashMap<i32, i32> = SimpleHashMap::new();
+        m.reserve(100);
+        let cap = m.capacity();
+        assert!(cap >= 100);
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.capacity(), cap);
+        for i in 0..100 {
+            assert_eq!(m.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_shrink_keeps_items() {
+        let mut m = SimpleHashMap::new();
+        for i in 0..1000 {
+            m.insert(i, i * 2);
+        }
+        for i in 100..1000 {
+            m.remove(&i);
+        }
+        let before = m.capacity();
+        m.shrink_to_fit();
+        assert!(m.capacity() < before);
+        assert!(m.capacity() >= m.len());
+        assert_eq!(m.len(), 100);
+        for i in 0..100 {
+            assert_eq!(m.get(&i), Some(&(i * 2)));
+        }
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let mut m: SimpleHashMap<u8, ()> = SimpleHashMap::new();
+        m.insert(1, ());
+
+        assert_eq!(m.try_reserve(usize::MAX), Err(HashMapError::CapacityExhausted));
+        assert_eq!(m.try_reserve(usize::MAX / 2), Err(HashMapError::CapacityExhausted));
+        // a fine number of slots, but more bytes than the address space
+        assert_eq!(m.try_reserve(usize::MAX / 64), Err(HashMapError::CapacityExhausted));
+
+        // failures leave the map as it was
+        assert_eq!(m.len(), 1);
+        assert_eq!(m.capacity(), 24);
+        assert_eq!(m.get(&1), Some(&()));
+
+        assert_eq!(m.try_reserve(100), Ok(()));
+        assert!(m.capacity() >= 101);
+        assert_eq!(m.get(&1), Some(&()));
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn test_reserve_overflow() {
+        let mut m: SimpleHashMap<u8, ()> = SimpleHashMap::new();
+        m.insert(1, ());
+        m.reserve(usize::MAX);
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut m = SimpleHashMap::new();
+        assert_eq!(m.try_insert(1, "a"), Ok(&mut "a"));
+        *m.try_insert(2, "b").unwrap() = "c";
+        assert_eq!(m.try_insert(1, "x"), Err(HashMapError::DuplicateKey));
+        assert_eq!(m[&1], "a");
+        assert_eq!(m[&2], "c");
+        assert_eq!(m.len(), 2);
+
+        for i in 3..1000 {
+            assert!(m.try_insert(i, "").is_ok());
+        }
+        assert_eq!(m.len(), 999);
+        assert_eq!(HashMapError::DuplicateKey.to_string(), "key is already in the map");
+    }
+
+    /// A key already in a full table is reported without growing it.
+    #[test]
+    fn test_try_insert_duplicate_does_not_grow() {
+        let mut m = SimpleHashMap::new();
+        m.insert(0, 0);
+        let capacity = m.capacity();
+        for i in 1..capacity {
+            m.insert(i, i);
+        }
+        assert_eq!(m.len(), capacity);
+        assert_eq!(m.try_insert(5, 50), Err(HashMapError::DuplicateKey));
+        assert_eq!(m.capacity(), capacity);
+        assert_eq!(m[&5], 5);
+
+        assert_eq!(m.try_insert(capacity, 0), Ok(&mut 0));
+        assert!(m.capacity() > capacity);
+    }
+
+    #[test]
+    fn test_try_insert_drops_rejected_value() {
+        let drops = Rc::new(Cell::new(0));
+        let mut m = SimpleHashMap::new();
+        assert!(m.try_insert(1, Droppable { drops: drops.clone() }).is_ok());
+        assert!(m.try_insert(1, Droppable { drops: drops.clone() }).is_err());
+        assert_eq!(drops.get(), 1);
+        assert_eq!(m.len(), 1);
+    }
+
+    /// Every public method on maps without a table, or with a table which
+    /// was emptied in some way.
+    #[test]
+    fn test_no_panics_on_empty_maps() {
+        for &probing in &STRATEGIES {
+            let mut cleared = SimpleHashMap::with_probing(probing);
+            (0..100).for_each(|i| { cleared.insert(i, i); });
+            cleared.clear();
+            let mut drained = cleared.clone();
+            (0..100).for_each(|i| { drained.insert(i, i); });
+            drained.drain();
+            let mut removed = drained.clone();
+            (0..100).for_each(|i| { removed.insert(i, i); });
+            (0..100).for_each(|i| { removed.remove(&i); });
+            let mut shrunk = removed.clone();
+            shrunk.shrink_to_fit();
+            let mut retained = removed.clone();
+            (0..100).for_each(|i| { retained.insert(i, i); });
+            retained.retain(|_, _| false);
+
+            let maps = vec![
+                SimpleHashMap::with_probing(probing),
+                SimpleHashMap::with_capacity(0),
+                cleared, drained, removed, shrunk, retained,
+            ];
+            for mut m in maps {
+                assert_eq!(m.get(&1), None);
+                assert_eq!(m.get_mut(&1), None);
+                assert_eq!(m.get_key_value(&1), None);
+                assert!(!m.contains_key(&1));
+                assert_eq!(m.remove(&1), None);
+                assert_eq!(m.remove_entry(&1), None);
+                assert_eq!(m.iter().count() + m.keys().count() + m.values_mut().count(), 0);
+                assert_eq!(m.drain().count(), 0);
+                m.retain(|_, _| true);
+                m.shrink_to_fit();
+                m.clear();
+                m.set_probing(Probing::Quadratic);
+                assert_eq!(m, SimpleHashMap::new());
+                assert_eq!(format!("{:?}", m), "{}");
+
+                if let Occupied(_) = m.entry(1) {
+                    panic!("{:?} entry in an empty map", probing);
+                }
+                assert_eq!(m.try_insert(1, 1), Ok(&mut 1));
+                assert_eq!(m.insert(1, 2), Some(1));
+                assert_eq!(m.remove(&1), Some(2));
+                assert!(m.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_removals_do_not_grow_table() {
+        let mut m = SimpleHashMap::with_capacity(8);
+        let cap = m.capacity();
+        for i in 0..10_000 {
+            m.insert(i, i);
+            assert_eq!(m.remove(&i), Some(i));
+        }
+        assert!(m.is_empty());
+        assert_eq!(m.capacity(), cap);
+    }
+
+    #[test]
+    fn test_entry() {
+        let xs = [(1, 10), (2, 20), (3, 30), (4, 40), (5, 50), (6, 60)];
+
+        let mut map: SimpleHashMap<_, _> = xs.iter().cloned().collect();
+
+        // Existing key (insert)
+        match map.entry(1) {
+            Vacant(_) => unreachable!(),
+            Occupied(mut view) => {
+                assert_eq!(view.get(), &10);
+                assert_eq!(view.insert(100), 10);
+            }
+        }
+        assert_eq!(map.get(&1).unwrap(), &100);
+        assert_eq!(map.len(), 6);
+
+        // Existing key (update)
+        match map.entry(2) {
+            Vacant(_) => unreachable!(),
+            Occupied(mut view) => {
+                let v = view.get_mut();
+                let new_v = (*v) * 10;
+                *v = new_v;
+            }
+        }
+        assert_eq!(map.get(&2).unwrap(), &200);
+        assert_eq!(map.len(), 6);
+
+        // Existing key (take)
+        match map.entry(3) {
+            Vacant(_) => unreachable!(),
+            Occupied(view) => {
+                assert_eq!(view.remove(), 30);
+            }
+        }
+        assert_eq!(map.get(&3), None);
+        assert_eq!(map.len(), 5);
+
+        // Inexistent key (insert)
+        match map.entry(10) {
+            Occupied(_) => unreachable!(),
+            Vacant(view) => {
+                assert_eq!(*view.insert(1000), 1000);
+            }
+        }
+        assert_eq!(map.get(&10).unwrap(), &1000);
+        assert_eq!(map.len(), 6);
+    }
+
+    #[test]
+    fn test_entry_take_doesnt_corrupt() {
+        fn check(m: &SimpleHashMap<i32, ()>) {
+            for k in m.keys() {
+                assert!(m.contains_key(k), "{} is in keys() but not in the map?", k);
+            }
+        }
+
+        let mut m = SimpleHashMap::new();
+
+        // Populate the map with some items.
+        for i in 0..50 {
+            let x = (i * 7919) % 1000;
+            m.insert(x, ());
+        }
+
+        for i in 0..1000 {
+            let x = (i * 7919) % 1000;
+            if let Occupied(e) = m.entry(x) {
+                e.remove();
+                check(&m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry_or_insert() {
+        let mut counts = SimpleHashMap::new();
+        for word in "a b a c b a".split(' ') {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts.get(&"a"), Some(&3));
+        assert_eq!(counts.get(&"b"), Some(&2));
+        assert_eq!(counts.get(&"c"), Some(&1));
+    }
+
+    #[test]
+    fn test_entry_or_insert_with_and_modify() {
+        let mut map = SimpleHashMap::new();
+        map.entry("poneyland").and_modify(|e| *e += 1).or_insert_with(|| 42);
+        assert_eq!(map[&"poneyland"], 42);
+
+        map.entry("poneyland").and_modify(|e| *e += 1).or_insert_with(|| 42);
+        assert_eq!(map[&"poneyland"], 43);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_entry_or_default() {
+        let mut groups: SimpleHashMap<bool, Vec<i32>> = SimpleHashMap::new();
+        for i in 0..10 {
+            groups.entry(i % 2 == 0).or_default().push(i);
+        }
+        assert_eq!(groups[&true], vec![0, 2, 4, 6, 8]);
+        assert_eq!(groups[&false], vec![1, 3, 5, 7, 9]);
+    }
+
+    #[test]
+    fn test_entry_key() {
+        let mut map = SimpleHashMap::new();
+        map.insert(1, 2);
+        assert_eq!(map.entry(1).key(), &1);
+        assert_eq!(map.entry(5).key(), &5);
+        match map.entry(5) {
+            Occupied(_) => unreachable!(),
+            Vacant(view) => assert_eq!(view.into_key(), 5),
+        }
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_entry_after_remove() {
+        let mut map = SimpleHashMap::with_capacity(8);
+        for i in 0..8 {
+            map.insert(i, i);
+        }
+        for i in 0..8 {
+            map.remove(&i);
+        }
+        for i in 0..8 {
+            *map.entry(i).or_insert(0) += i;
+        }
+        assert_eq!(map.len(), 8);
+        for i in 0..8 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_with_hasher() {
+        let mut m = SimpleHashMap::with_hasher(RandomState::new());
+        for i in 0..100 {
+            assert!(m.insert(i, i * 2).is_none());
+        }
+        for i in 0..100 {
+            assert_eq!(m.get(&i), Some(&(i * 2)));
+        }
+        assert_eq!(m.remove(&7), Some(14));
+        assert!(!m.contains_key(&7));
+        assert_eq!(m.len(), 99);
+    }
+
+    #[test]
+    fn test_with_capacity_and_hasher() {
+        let mut m = SimpleHashMap::with_capacity_and_hasher(100, FnvBuildHasher::default());
+        let cap = m.capacity();
+        assert!(cap >= 100);
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(m.capacity(), cap);
+        for i in 0..100 {
+            assert_eq!(m.get(&i.to_string()), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_from_iter_with_hasher() {
+        let xs = [(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)];
+
+        let map: SimpleHashMap<_, _, FxBuildHasher> = xs.iter().cloned().collect();
+
+        for &(k, v) in &xs {
+            assert_eq!(map.get(&k), Some(&v));
+        }
+        assert_eq!(map.len(), xs.len());
+    }
+
+    #[test]
+    fn test_sip_hash_map() {
+        let mut m: SipHashMap<String, usize> = SipHashMap::default();
+        for i in 0..200 {
+            assert!(m.insert(format!("X-Header-{}", i), i).is_none());
+        }
+        for i in 0..200 {
+            assert_eq!(m.get(&format!("X-Header-{}", i)), Some(&i));
+        }
+        assert_eq!(m.len(), 200);
+
+        // every map draws its own key
+        let other: SipHashMap<String, usize> = SipHashMap::default();
+        let key = "X-Header-0".to_string();
+        assert_ne!(m.hasher().hash_one(&key), other.hasher().hash_one(&key));
+    }
+
+    /// Places every `u64` key at `key % buckets`, to build clusters by hand.
+    #[derive(Default)]
+    struct IdentityHasher(u64);
+
+    impl Hasher for IdentityHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, _: &[u8]) {
+            unimplemented!()
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.0 = i;
+        }
+    }
+
+    type IdentityMap<V> = SimpleHashMap<u64, V, BuildHasherDefault<IdentityHasher>>;
+
+    fn slot_keys<V>(m: &IdentityMap<V>) -> Vec<Option<u64>> {
+        m.table.iter().map(|slot| slot.item().map(|item| item.key)).collect()
+    }
+
+    #[test]
+    fn test_backward_shift_delete() {
+        let mut m: IdentityMap<u64> = SimpleHashMap::default();
+        // 3 and 35 share slot 3, 4 gets pushed behind 5
+        for &k in &[3, 35, 5, 4] {
+            m.insert(k, k);
+        }
+        assert_eq!(&slot_keys(&m)[3..8], &[Some(3), Some(35), Some(5), Some(4), None]);
+
+        // 5 is at home and stays, 4 moves back over it into the hole
+        assert_eq!(m.remove(&35), Some(35));
+        assert_eq!(&slot_keys(&m)[3..8], &[Some(3), Some(4), Some(5), None, None]);
+
+        assert_eq!(m.remove(&3), Some(3));
+        assert_eq!(&slot_keys(&m)[3..8], &[None, Some(4), Some(5), None, None]);
+
+        for &k in &[4, 5] {
+            assert_eq!(m.get(&k), Some(&k));
+        }
+        assert_eq!(m.len(), 2);
+    }
+
+    #[test]
+    fn test_backward_shift_delete_wraps_around() {
+        let mut m: IdentityMap<u64> = SimpleHashMap::default();
+        m.insert(31, 31);
+        m.insert(63, 63);
+        m.insert(95, 95);
+        m.insert(0, 0);
+        let slots = slot_keys(&m);
+        assert_eq!((slots[31], slots[0], slots[1], slots[2]), (Some(31), Some(63), Some(95), Some(0)));
+
+        assert_eq!(m.remove(&31), Some(31));
+        let slots = slot_keys(&m);
+        assert_eq!((slots[31], slots[0], slots[1], slots[2]), (Some(63), Some(95), Some(0), None));
+        for &k in &[63, 95, 0] {
+            assert_eq!(m.get(&k), Some(&k));
+        }
+    }
+
+    #[test]
+    fn test_remove_heap_values() {
+        let mut m = SimpleHashMap::new();
+        for i in 0..100 {
+            m.insert(i, format!("value {}", i));
+        }
+        for i in (0..100).step_by(3) {
+            assert_eq!(m.remove(&i), Some(format!("value {}", i)));
+        }
+        for i in 0..100 {
+            m.insert(i, format!("again {}", i));
+        }
+        for i in 0..100 {
+            assert_eq!(m.get(&i), Some(&format!("again {}", i)));
+        }
+        m.shrink_to_fit();
+        assert_eq!(m.len(), 100);
+    }
+
+    #[test]
+    fn test_drops() {
+        let drops = Rc::new(Cell::new(0));
+        {
+            let mut m = SimpleHashMap::new();
+            for i in 0..100 {
+                m.insert(i, Droppable { drops: drops.clone() });
+            }
+            assert_eq!(drops.get(), 0);
+
+            for i in 0..50 {
+                let v = m.remove(&i);
+                assert!(v.is_some());
+                assert_eq!(drops.get(), i);
+                drop(v);
+                assert_eq!(drops.get(), i + 1);
+            }
+
+            // replaced values are dropped once, by the caller
+            let old = m.insert(50, Droppable { drops: drops.clone() });
+            assert_eq!(drops.get(), 50);
+            drop(old);
+            assert_eq!(drops.get(), 51);
+
+            // growing the table only moves values
+            m.reserve(1000);
+            assert_eq!(drops.get(), 51);
+        }
+        assert_eq!(drops.get(), 101);
+    }
+
+    #[test]
+    fn test_into_iter_drops() {
+        let drops = Rc::new(Cell::new(0));
+        let mut m = SimpleHashMap::new();
+        for i in 0..100 {
+            m.insert(i, Droppable { drops: drops.clone() });
+        }
+        let mut iter = m.into_iter();
+        for (i, (_, v)) in iter.by_ref().take(50).enumerate() {
+            assert_eq!(drops.get(), i);
+            drop(v);
+        }
+        assert_eq!(drops.get(), 50);
+        drop(iter);
+        assert_eq!(drops.get(), 100);
+    }
+
+    #[test]
+    fn test_drain_drops() {
+        let drops = Rc::new(Cell::new(0));
+        let mut m = SimpleHashMap::new();
+        for i in 0..100 {
+            m.insert(i, Droppable { drops: drops.clone() });
+        }
+        drop(m.drain().take(10).collect::<Vec<_>>());
+        assert_eq!(drops.get(), 100);
+        assert!(m.is_empty());
+
+        for i in 0..10 {
+            m.insert(i, Droppable { drops: drops.clone() });
+        }
+        m.retain(|&k, _| k < 5);
+        assert_eq!(drops.get(), 105);
+        m.clear();
+        assert_eq!(drops.get(), 110);
+    }
+
+    #[test]
+    fn test_drops_keys() {
+        let drops = Rc::new(Cell::new(0));
+        let mut m = SimpleHashMap::new();
+        let keys: Vec<_> = (0..10).map(|i| i.to_string()).collect();
+        for key in &keys {
+            m.insert(key.clone(), Droppable { drops: drops.clone() });
+        }
+        for key in &keys[..5] {
+            drop(m.remove(key));
+        }
+        assert_eq!(drops.get(), 5);
+        drop(m);
+        assert_eq!(drops.get(), 10);
+    }
+
+    const STRATEGIES: [Probing; 4] = [Probing::Linear, Probing::Quadratic, Probing::DoubleHashing, Probing::RobinHood];
+
+    /// Keys with pseudo random bits, the same set on every run.
+    fn random_keys(n: usize) -> Vec<u64> {
+        let mut x: u64 = 0x2545_f491_4f6c_dd1d;
+        (0..n).map(|_| xorshift(&mut x)).collect()
+    }
+
+    /// Keys whose origins pack the first half of a 4096 slot table with two
+    /// keys each, the worst case for primary clustering.
+    fn clustered_keys() -> Vec<u64> {
+        random_keys(3000).iter().enumerate()
+            .map(|(i, bits)| (bits << 12) | (i as u64 / 2))
+            .collect()
+    }
+
+    /// Builds an identity hashed map of `keys` and returns its probe lengths.
+    fn probe_lengths_of(probing: Probing, keys: &[u64]) -> Vec<usize> {
+        let mut m: IdentityMap<()> = SimpleHashMap::with_capacity_and_hasher(keys.len(), Default::default());
+        m.set_probing(probing);
+        for key in keys {
+            m.insert(*key, ());
+        }
+        for key in keys {
+            assert!(m.contains_key(key));
+        }
+        m.probe_lengths()
+    }
+
+    fn mean(lengths: &[usize]) -> f64 {
+        lengths.iter().sum::<usize>() as f64 / lengths.len() as f64
+    }
+
+    fn variance(lengths: &[usize]) -> f64 {
+        let mean = mean(lengths);
+        lengths.iter().map(|l| (*l as f64 - mean).powi(2)).sum::<f64>() / lengths.len() as f64
+    }
+
+    #[test]
+    fn test_probe_lengths_random_keys() {
+        let keys = random_keys(3000);
+        let linear = probe_lengths_of(Probing::Linear, &keys);
+        let quadratic = probe_lengths_of(Probing::Quadratic, &keys);
+        let double = probe_lengths_of(Probing::DoubleHashing, &keys);
+        let robin_hood = probe_lengths_of(Probing::RobinHood, &keys);
+
+        // Robin Hood only reorders clusters: the total is the same, the
+        // spread and the worst case are smaller
+        assert_eq!(robin_hood.iter().sum::<usize>(), linear.iter().sum::<usize>());
+        assert!(variance(&robin_hood) < variance(&linear));
+        assert!(robin_hood.iter().max() < linear.iter().max());
+
+        // leaving the cluster pays off on average
+        assert!(mean(&quadratic) < mean(&linear));
+        assert!(mean(&double) < mean(&linear));
+    }
+
+    #[test]
+    fn test_probe_lengths_clustered_keys() {
+        let keys = clustered_keys();
+        let linear = probe_lengths_of(Probing::Linear, &keys);
+        let quadratic = probe_lengths_of(Probing::Quadratic, &keys);
+        let double = probe_lengths_of(Probing::DoubleHashing, &keys);
+        let robin_hood = probe_lengths_of(Probing::RobinHood, &keys);
+
+        // one big cluster makes linear probing scan half the table
+        assert!(mean(&linear) > 500.0);
+        // keys arrive in origin order, so there is nothing to even out
+        assert_eq!(robin_hood, linear);
+        assert!(mean(&quadratic) < mean(&linear) / 10.0);
+        assert!(mean(&double) < mean(&linear) / 100.0);
+        assert!(mean(&double) < mean(&quadratic));
+    }
+
+    #[test]
+    fn test_strategies_agree_with_std() {
+        use std::collections::HashMap;
+
+        for &probing in &STRATEGIES {
+            let mut m = SimpleHashMap::with_probing(probing);
+            let mut expected = HashMap::new();
+            for (i, bits) in random_keys(5000).iter().enumerate() {
+                let key = bits % 700;
+                if bits & 0x300 == 0 {
+                    assert_eq!(m.remove(&key), expected.remove(&key), "{:?} step {}", probing, i);
+                } else {
+                    assert_eq!(m.insert(key, i), expected.insert(key, i), "{:?} step {}", probing, i);
+                }
+                assert_eq!(m.len(), expected.len());
+            }
+            for key in 0..700 {
+                assert_eq!(m.get(&key), expected.get(&key), "{:?} key {}", probing, key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_probing_keeps_items() {
+        let mut m = SimpleHashMap::new();
+        for i in 0..500 {
+            m.insert(i, i * 3);
+        }
+        for i in 0..250 {
+            m.remove(&(i * 2));
+        }
+        for &probing in STRATEGIES.iter().chain(STRATEGIES.iter().rev()) {
+            m.set_probing(probing);
+            assert_eq!(m.probing(), probing);
+            assert_eq!(m.len(), 250);
+            for i in 0..500 {
+                let expected = if i % 2 == 0 { None } else { Some(i * 3) };
+                assert_eq!(m.get(&i).cloned(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tombstones_are_reclaimed() {
+        for &probing in &[Probing::Quadratic, Probing::DoubleHashing] {
+            let mut m = SimpleHashMap::with_probing(probing);
+            m.reserve(8);
+            let cap = m.capacity();
+            for i in 0..10_000 {
+                m.insert(i, i);
+                assert_eq!(m.remove(&i), Some(i));
+            }
+            assert!(m.is_empty());
+            assert_eq!(m.capacity(), cap);
+            assert!(m.deleted < cap);
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut m: IdentityMap<u64> = SimpleHashMap::default();
+        for &k in &[3, 35, 5, 4] {
+            m.insert(k, k);
+        }
+        let stats = m.stats();
+        assert_eq!(stats.len, 4);
+        assert_eq!(stats.buckets, 32);
+        assert_eq!(stats.tombstones, 0);
+        assert_eq!(stats.load_factor, 0.125);
+        assert_eq!(stats.probe_histogram, vec![2, 1, 1]);
+        assert_eq!(stats.max_probe_length, 3);
+        assert_eq!(stats.mean_probe_length, 1.75);
+        assert_eq!(stats.longest_run, 4);
+        assert_eq!(m.dump_layout(), format!("{:>8} ...0102{}\n", 0, ".".repeat(25)));
+
+        let text = stats.to_string();
+        assert!(text.contains("4 items in 32 slots"), "{}", text);
+        assert!(text.contains("mean 1.75, max 3, longest run 4"), "{}", text);
+    }
+
+    #[test]
+    fn test_stats_longest_run_wraps_around() {
+        let mut m: IdentityMap<u64> = SimpleHashMap::default();
+        for &k in &[31, 63, 95, 10] {
+            m.insert(k, k);
+        }
+        assert_eq!(m.stats().longest_run, 3);
+    }
+
+    #[test]
+    fn test_stats_tombstones() {
+        let mut m = SimpleHashMap::with_probing(Probing::Quadratic);
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        for i in (0..100).step_by(2) {
+            m.remove(&i);
+        }
+        let stats = m.stats();
+        assert_eq!(stats.tombstones, 50);
+        assert_eq!(stats.probe_histogram.iter().sum::<usize>(), 50);
+        assert_eq!(stats.probe_histogram.len(), stats.max_probe_length);
+        assert_eq!(m.dump_layout().matches('x').count(), 50);
+        assert_eq!(m.dump_layout().lines().count(), stats.buckets / 64);
+    }
+
+    #[test]
+    fn test_stats_empty() {
+        let m: SimpleHashMap<i32, i32> = SimpleHashMap::new();
+        let stats = m.stats();
+        assert_eq!((stats.buckets, stats.max_probe_length, stats.longest_run), (0, 0, 0));
+        assert_eq!(stats.mean_probe_length, 0.0);
+        assert_eq!(m.dump_layout(), "");
+        assert!(stats.to_string().contains("0 items in 0 slots"));
     }
-    */
 }
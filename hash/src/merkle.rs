@@ -0,0 +1,520 @@
+//! Merkle tree of an append-only list of byte strings, with the inclusion
+//! and consistency proofs of Certificate Transparency (RFC 9162).
+//!
+//! Leaves are hashed as `D(0x00 || data)` and inner nodes as
+//! `D(0x01 || left || right)`, so a leaf can never pass for a node. The tree
+//! of `n > 1` leaves has the largest power of two below `n` on its left.
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+
+use super::digest::{Digest, Sha256, to_hex};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Proof that a leaf is the `index`-th of the tree of `tree_size` leaves:
+/// the sibling hashes from the leaf up to the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub index: usize,
+    pub tree_size: usize,
+    pub path: Vec<Vec<u8>>,
+}
+
+/// Proof that the tree of `old_size` leaves is a prefix of the tree of
+/// `new_size` leaves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyProof {
+    pub old_size: usize,
+    pub new_size: usize,
+    pub path: Vec<Vec<u8>>,
+}
+
+/// Merkle tree hashing with `D`. Two trees, such as those of two replicas,
+/// hold the same leaves exactly when their roots are equal, which is what
+/// `==` compares.
+///
+/// Appending costs `O(log n)` hashes, and roots and proofs of the current
+/// or any earlier size `O(log n)` hashes or lookups: the incomplete
+/// subtrees on the right edge are hashed once per root or proof.
+pub struct MerkleTree<D = Sha256> {
+    /// `levels[k][i]` is the hash of the complete subtree of the `2^k`
+    /// leaves from `i * 2^k`. Incomplete subtrees are not stored.
+    levels: Vec<Vec<Vec<u8>>>,
+    marker: PhantomData<fn() -> D>,
+}
+
+fn hash_leaf<D: Digest + Default>(data: &[u8]) -> Vec<u8> {
+    let mut digest = D::default();
+    digest.update(&[LEAF_PREFIX]);
+    digest.update(data);
+    digest.finalize()
+}
+
+fn hash_node<D: Digest + Default>(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut digest = D::default();
+    digest.update(&[NODE_PREFIX]);
+    digest.update(left);
+    digest.update(right);
+    digest.finalize()
+}
+
+/// Largest power of two strictly below `n`, for `n > 1`.
+fn split_point(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+impl<D: Digest + Default> MerkleTree<D> {
+
+    pub fn new() -> MerkleTree<D> {
+        MerkleTree { levels: vec![Vec::new()], marker: PhantomData }
+    }
+
+    /// Number of leaves.
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a leaf, returning its index.
+    pub fn append(&mut self, data: &[u8]) -> usize {
+        let index = self.len();
+        let mut hash = hash_leaf::<D>(data);
+        let mut level = 0;
+        loop {
+            self.levels[level].push(hash);
+            let nodes = &self.levels[level];
+            if nodes.len() % 2 == 1 {
+                break;
+            }
+            hash = hash_node::<D>(&nodes[nodes.len() - 2], &nodes[nodes.len() - 1]);
+            level += 1;
+            if level == self.levels.len() {
+                self.levels.push(Vec::new());
+            }
+        }
+        index
+    }
+
+    /// Hash of the `index`-th leaf.
+    pub fn leaf_hash(&self, index: usize) -> Option<&[u8]> {
+        self.levels[0].get(index).map(Vec::as_slice)
+    }
+
+    /// Root of the tree, which is the digest of no data when it is empty.
+    pub fn root(&self) -> Vec<u8> {
+        self.root_at(self.len()).expect("current size is in range")
+    }
+
+    /// Root the tree had when it held `size` leaves.
+    pub fn root_at(&self, size: usize) -> Option<Vec<u8>> {
+        if size > self.len() {
+            return None;
+        }
+        Some(self.subtree(0, size, &self.right_edge(size)))
+    }
+
+    /// Hashes of the incomplete subtrees on the right edge of the tree of
+    /// `size` leaves, indexed by the top bit of their number of leaves.
+    /// Each is the node of a stored complete subtree and the next smaller
+    /// one, so all of them cost `O(log n)` hashes.
+    fn right_edge(&self, size: usize) -> Vec<Option<Vec<u8>>> {
+        let mut edge = vec![None; usize::BITS as usize];
+        let mut right: Option<Vec<u8>> = None;
+        for level in (0..usize::BITS as usize).filter(|&level| size >> level & 1 == 1) {
+            let n = (size & ((1 << level) - 1)) + (1 << level);
+            let complete = &self.levels[level][(size - n) >> level];
+            right = Some(match right {
+                None => complete.clone(),
+                Some(right) => {
+                    let hash = hash_node::<D>(complete, &right);
+                    edge[level] = Some(hash.clone());
+                    hash
+                }
+            });
+        }
+        edge
+    }
+
+    /// Hash of the tree of leaves `start..end`, where `start` is a multiple
+    /// of a power of two at least `end - start`, as in all the subtrees of
+    /// the proofs. Such a tree is complete, or on the right edge of the tree
+    /// of `end` leaves, whose hashes are in `edge`.
+    fn subtree(&self, start: usize, end: usize, edge: &[Option<Vec<u8>>]) -> Vec<u8> {
+        let n = end - start;
+        if n == 0 {
+            return D::default().finalize();
+        }
+        let level = (usize::BITS - 1 - n.leading_zeros()) as usize;
+        if n.is_power_of_two() {
+            return self.levels[level][start >> level].clone();
+        }
+        edge[level].clone().expect("incomplete subtree is on the right edge")
+    }
+
+    /// Proof that leaf `index` is in the current tree.
+    pub fn inclusion_proof(&self, index: usize) -> Option<InclusionProof> {
+        self.inclusion_proof_at(index, self.len())
+    }
+
+    /// Proof that leaf `index` is in the tree of the first `tree_size` leaves.
+    pub fn inclusion_proof_at(&self, index: usize, tree_size: usize) -> Option<InclusionProof> {
+        if index >= tree_size || tree_size > self.len() {
+            return None;
+        }
+        let mut path = Vec::new();
+        self.inclusion_path(index, 0, tree_size, &self.right_edge(tree_size), &mut path);
+        Some(InclusionProof { index, tree_size, path })
+    }
+
+    fn inclusion_path(&self, index: usize, start: usize, end: usize, edge: &[Option<Vec<u8>>], path: &mut Vec<Vec<u8>>) {
+        let n = end - start;
+        if n <= 1 {
+            return;
+        }
+        let k = split_point(n);
+        if index < start + k {
+            self.inclusion_path(index, start, start + k, edge, path);
+            path.push(self.subtree(start + k, end, edge));
+        } else {
+            self.inclusion_path(index, start + k, end, edge, path);
+            path.push(self.subtree(start, start + k, edge));
+        }
+    }
+
+    /// Checks that `data` is the leaf of `proof` in the tree with `root`.
+    pub fn verify_inclusion(proof: &InclusionProof, data: &[u8], root: &[u8]) -> bool {
+        if proof.index >= proof.tree_size {
+            return false;
+        }
+        let (mut node, mut last) = (proof.index, proof.tree_size - 1);
+        let mut hash = hash_leaf::<D>(data);
+        for sibling in &proof.path {
+            if last == 0 {
+                return false;
+            }
+            if node % 2 == 1 || node == last {
+                hash = hash_node::<D>(sibling, &hash);
+                // A rightmost left child has no sibling on the levels it
+                // is promoted through.
+                while node % 2 == 0 && node != 0 {
+                    node >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                hash = hash_node::<D>(&hash, sibling);
+            }
+            node >>= 1;
+            last >>= 1;
+        }
+        last == 0 && hash == root
+    }
+
+    /// Proof that the tree of `old_size` leaves is a prefix of the current
+    /// tree, for `0 < old_size <= len`.
+    pub fn consistency_proof(&self, old_size: usize) -> Option<ConsistencyProof> {
+        self.consistency_proof_at(old_size, self.len())
+    }
+
+    /// Proof that the tree of `old_size` leaves is a prefix of the tree of
+    /// `new_size` leaves, for `0 < old_size <= new_size <= len`.
+    pub fn consistency_proof_at(&self, old_size: usize, new_size: usize) -> Option<ConsistencyProof> {
+        if old_size == 0 || old_size > new_size || new_size > self.len() {
+            return None;
+        }
+        let mut path = Vec::new();
+        if old_size < new_size {
+            self.consistency_path(old_size, 0, new_size, true, &self.right_edge(new_size), &mut path);
+        }
+        Some(ConsistencyProof { old_size, new_size, path })
+    }
+
+    /// `SUBPROOF` of RFC 9162: `complete` tells whether the first `m`
+    /// leaves of `start..end` are the whole old tree, whose root the
+    /// verifier already has.
+    fn consistency_path(&self, m: usize, start: usize, end: usize, complete: bool,
+                        edge: &[Option<Vec<u8>>], path: &mut Vec<Vec<u8>>) {
+        let n = end - start;
+        if m == n {
+            if !complete {
+                path.push(self.subtree(start, end, edge));
+            }
+            return;
+        }
+        let k = split_point(n);
+        if m <= k {
+            self.consistency_path(m, start, start + k, complete, edge, path);
+            path.push(self.subtree(start + k, end, edge));
+        } else {
+            self.consistency_path(m - k, start + k, end, false, edge, path);
+            path.push(self.subtree(start, start + k, edge));
+        }
+    }
+
+    /// Checks that the tree with `old_root` is a prefix of the tree with
+    /// `new_root`, with the sizes of `proof`.
+    pub fn verify_consistency(proof: &ConsistencyProof, old_root: &[u8], new_root: &[u8]) -> bool {
+        let ConsistencyProof { old_size, new_size, .. } = *proof;
+        if old_size == 0 || old_size > new_size {
+            return false;
+        }
+        if old_size == new_size {
+            return proof.path.is_empty() && old_root == new_root;
+        }
+        // The old root is the first node of the path when it is a
+        // complete subtree of the new tree.
+        let mut path: Vec<&[u8]> = proof.path.iter().map(Vec::as_slice).collect();
+        if old_size.is_power_of_two() {
+            path.insert(0, old_root);
+        }
+        let Some((first, rest)) = path.split_first() else {
+            return false;
+        };
+        let (mut node, mut last) = (old_size - 1, new_size - 1);
+        while node % 2 == 1 {
+            node >>= 1;
+            last >>= 1;
+        }
+        let (mut old_hash, mut new_hash) = (first.to_vec(), first.to_vec());
+        for sibling in rest {
+            if last == 0 {
+                return false;
+            }
+            if node % 2 == 1 || node == last {
+                old_hash = hash_node::<D>(sibling, &old_hash);
+                new_hash = hash_node::<D>(sibling, &new_hash);
+                while node % 2 == 0 && node != 0 {
+                    node >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                new_hash = hash_node::<D>(&new_hash, sibling);
+            }
+            node >>= 1;
+            last >>= 1;
+        }
+        last == 0 && old_hash == old_root && new_hash == new_root
+    }
+}
+
+impl<D: Digest + Default> Default for MerkleTree<D> {
+    fn default() -> MerkleTree<D> {
+        MerkleTree::new()
+    }
+}
+
+impl<D> Clone for MerkleTree<D> {
+    fn clone(&self) -> MerkleTree<D> {
+        MerkleTree { levels: self.levels.clone(), marker: PhantomData }
+    }
+}
+
+impl<D: Digest + Default> PartialEq for MerkleTree<D> {
+    fn eq(&self, other: &MerkleTree<D>) -> bool {
+        self.len() == other.len() && self.root() == other.root()
+    }
+}
+
+impl<D: Digest + Default> Eq for MerkleTree<D> {}
+
+impl<D: Digest + Default> Debug for MerkleTree<D> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("MerkleTree").field("len", &self.len()).field("root", &to_hex(&self.root())).finish()
+    }
+}
+
+impl<D: Digest + Default, T: AsRef<[u8]>> Extend<T> for MerkleTree<D> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.append(data.as_ref());
+        }
+    }
+}
+
+impl<D: Digest + Default, T: AsRef<[u8]>> std::iter::FromIterator<T> for MerkleTree<D> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> MerkleTree<D> {
+        let mut tree = MerkleTree::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+#[cfg(test)]
+mod test_merkle {
+    use super::{MerkleTree, InclusionProof, ConsistencyProof};
+    use crate::digest::{Sha1, HasherDigest, to_hex};
+    use crate::hasher::FnvHasher;
+    use crate::Digest;
+    use std::cell::Cell;
+
+    /// Leaves of the RFC 6962 reference tests.
+    const LEAVES: [&[u8]; 8] = [
+        b"", b"\x00", b"\x10", b"\x20\x21", b"\x30\x31", b"\x40\x41\x42\x43",
+        b"\x50\x51\x52\x53\x54\x55\x56\x57",
+        b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f",
+    ];
+
+    fn tree_of(n: usize) -> MerkleTree {
+        (0..n).map(|i| format!("leaf {}", i)).collect()
+    }
+
+    #[test]
+    fn test_reference_roots() {
+        let roots = [
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+            "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+            "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+            "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+            "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+            "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+        ];
+        let mut tree: MerkleTree = MerkleTree::new();
+        assert_eq!(to_hex(&tree.root()), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        for (i, leaf) in LEAVES.iter().enumerate() {
+            assert_eq!(tree.append(leaf), i);
+            assert_eq!(to_hex(&tree.root()), roots[i]);
+        }
+        for (size, root) in roots.iter().enumerate() {
+            assert_eq!(to_hex(&tree.root_at(size + 1).unwrap()), *root);
+        }
+        assert_eq!(tree.root_at(9), None);
+    }
+
+    #[test]
+    fn test_reference_inclusion_proof() {
+        let tree: MerkleTree = LEAVES.iter().collect();
+        let proof = tree.inclusion_proof(0).unwrap();
+        let path: Vec<String> = proof.path.iter().map(|hash| to_hex(hash)).collect();
+        assert_eq!(path, vec![
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+        ]);
+        assert_eq!(tree.consistency_proof(1).unwrap().path, proof.path);
+    }
+
+    #[test]
+    fn test_inclusion_proofs() {
+        let tree = tree_of(37);
+        for size in 1..=37 {
+            let root = tree.root_at(size).unwrap();
+            for index in 0..size {
+                let proof = tree.inclusion_proof_at(index, size).unwrap();
+                let data = format!("leaf {}", index);
+                assert!(<MerkleTree>::verify_inclusion(&proof, data.as_bytes(), &root), "{} of {}", index, size);
+                assert!(!<MerkleTree>::verify_inclusion(&proof, b"forged", &root));
+                if size > 1 {
+                    let moved = InclusionProof { index: (index + 1) % size, ..proof.clone() };
+                    assert!(!<MerkleTree>::verify_inclusion(&moved, data.as_bytes(), &root));
+                    let mut tampered = proof.clone();
+                    tampered.path[0][0] ^= 1;
+                    assert!(!<MerkleTree>::verify_inclusion(&tampered, data.as_bytes(), &root));
+                }
+            }
+        }
+        assert_eq!(tree.inclusion_proof(37), None);
+        assert_eq!(tree.inclusion_proof_at(3, 38), None);
+    }
+
+    #[test]
+    fn test_consistency_proofs() {
+        let tree = tree_of(33);
+        for new_size in 1..=33 {
+            let new_root = tree.root_at(new_size).unwrap();
+            for old_size in 1..=new_size {
+                let old_root = tree.root_at(old_size).unwrap();
+                let proof = tree.consistency_proof_at(old_size, new_size).unwrap();
+                assert!(<MerkleTree>::verify_consistency(&proof, &old_root, &new_root), "{} to {}", old_size, new_size);
+                if old_size < new_size {
+                    // Another old tree of the same size is not a prefix.
+                    let other_tree: MerkleTree = (0..old_size).map(|i| format!("other {}", i)).collect();
+                    assert!(!<MerkleTree>::verify_consistency(&proof, &other_tree.root(), &new_root));
+                    let mut tampered = proof.clone();
+                    let last = tampered.path.len() - 1;
+                    tampered.path[last][0] ^= 1;
+                    assert!(!<MerkleTree>::verify_consistency(&tampered, &old_root, &new_root));
+                }
+            }
+        }
+        assert_eq!(tree.consistency_proof(0), None);
+        assert_eq!(tree.consistency_proof_at(5, 4), None);
+        assert_eq!(tree.consistency_proof_at(5, 34), None);
+        let bad_sizes = ConsistencyProof { old_size: 0, new_size: 3, path: Vec::new() };
+        assert!(!<MerkleTree>::verify_consistency(&bad_sizes, &tree.root(), &tree.root()));
+    }
+
+    #[test]
+    fn test_root_comparison() {
+        let a = tree_of(100);
+        let mut b = tree_of(99);
+        assert_ne!(a, b);
+        b.append(b"leaf 99");
+        assert_eq!(a, b);
+        b.append(b"leaf 100");
+        assert_ne!(a, b);
+        assert_eq!(a, a.clone());
+
+        assert_eq!(a.leaf_hash(42), tree_of(43).leaf_hash(42));
+        assert_eq!(a.leaf_hash(100), None);
+        assert_eq!(format!("{:?}", MerkleTree::<Sha1>::new()),
+                   "MerkleTree { len: 0, root: \"da39a3ee5e6b4b0d3255bfef95601890afd80709\" }");
+    }
+
+    #[test]
+    fn test_hasher_digest() {
+        let tree: MerkleTree<HasherDigest<FnvHasher>> = (0..20).map(|i: u32| i.to_le_bytes()).collect();
+        assert_eq!(tree.root().len(), 8);
+        let proof = tree.inclusion_proof(7).unwrap();
+        assert!(MerkleTree::<HasherDigest<FnvHasher>>::verify_inclusion(&proof, &7u32.to_le_bytes(), &tree.root()));
+        let proof = tree.consistency_proof(13).unwrap();
+        assert!(MerkleTree::<HasherDigest<FnvHasher>>::verify_consistency(&proof, &tree.root_at(13).unwrap(),
+                                                                           &tree.root()));
+    }
+
+    thread_local! {
+        static FINALIZED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// FNV digest counting the hashes computed on this thread.
+    #[derive(Default)]
+    struct CountingDigest(HasherDigest<FnvHasher>);
+
+    impl Digest for CountingDigest {
+        fn update(&mut self, data: &[u8]) { self.0.update(data) }
+
+        fn finalize(&self) -> Vec<u8> {
+            FINALIZED.with(|count| count.set(count.get() + 1));
+            self.0.finalize()
+        }
+
+        fn reset(&mut self) { self.0.reset() }
+
+        fn output_size(&self) -> usize { self.0.output_size() }
+
+        fn block_size(&self) -> usize { self.0.block_size() }
+    }
+
+    fn hashes_of<T>(f: impl FnOnce() -> T) -> usize {
+        FINALIZED.with(|count| count.set(0));
+        f();
+        FINALIZED.with(Cell::get)
+    }
+
+    /// Trees whose size has every bit set have the longest right edge, which
+    /// each root and proof hashes only once.
+    #[test]
+    fn test_proofs_cost_log_n_hashes() {
+        let tree: MerkleTree<CountingDigest> = (0..(1u32 << 12) - 1).map(|i| i.to_le_bytes()).collect();
+        assert!(hashes_of(|| tree.root()) <= 12);
+        assert!(hashes_of(|| tree.inclusion_proof(0)) <= 12);
+        assert!(hashes_of(|| tree.inclusion_proof(2000)) <= 12);
+        assert!(hashes_of(|| tree.consistency_proof(1)) <= 12);
+        assert!(hashes_of(|| tree.consistency_proof_at(1000, (1 << 11) + (1 << 10) - 1)) <= 12);
+    }
+}
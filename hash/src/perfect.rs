@@ -0,0 +1,666 @@
+//! Minimal perfect hashing of static key sets with the CHD algorithm
+//! (compress, hash and displace), and `StaticMap` built on it.
+//!
+//! Keys are hashed into buckets of about `AVERAGE_BUCKET_SIZE` keys. The
+//! buckets are then placed largest first, searching for each a pair of
+//! displacements which sends all of its keys to free slots. A lookup costs
+//! one hash and one displacement fetch, and never probes.
+
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::{Hash, BuildHasher};
+
+use super::hasher::{FnvBuildHasher, mix};
+use super::SimpleHashMap;
+
+const PERFECT_MAGIC: &[u8; 4] = b"MPH1";
+const STATIC_MAP_MAGIC: &[u8; 4] = b"SMP1";
+
+/// Keys per bucket. Larger buckets make the displacement table smaller and
+/// the build slower.
+const AVERAGE_BUCKET_SIZE: usize = 5;
+
+/// Seeds tried before giving up, with distinct keys and a sound hasher the
+/// first one nearly always works.
+const MAX_ATTEMPTS: u64 = 32;
+
+/// First displacements tried per bucket, each with every second
+/// displacement, which bounds a bucket at `16 * len` tries. Larger first
+/// displacements only help buckets that a new seed places just as well.
+const MAX_FIRST_DISPLACEMENT: usize = 16;
+
+/// Errors of building and decoding perfect hash functions and static maps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerfectHashError {
+    /// The key set holds the same key twice.
+    DuplicateKey,
+    /// No displacements were found, the hasher keeps mapping distinct keys
+    /// to the same hash.
+    Unbuildable,
+    /// The bytes do not start with the magic of the structure.
+    BadMagic,
+    /// The bytes end before the structure does, or go on after it.
+    BadLength,
+    /// An encoded entry does not decode, or its key does not hash to its
+    /// place, for instance because the hasher differs.
+    BadEntry,
+}
+
+impl Display for PerfectHashError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let message = match self {
+            PerfectHashError::DuplicateKey => "key set holds a duplicate key",
+            PerfectHashError::Unbuildable => "hasher maps distinct keys to the same hash",
+            PerfectHashError::BadMagic => "bytes are not an encoded structure of this type",
+            PerfectHashError::BadLength => "encoded structure has the wrong length",
+            PerfectHashError::BadEntry => "encoded entry is invalid or misplaced",
+        };
+        f.write_str(message)
+    }
+}
+
+impl Error for PerfectHashError {}
+
+/// Bucket and the two displacement coefficients of a key.
+struct KeyHashes {
+    bucket: usize,
+    f1: u64,
+    f2: u64,
+}
+
+/// Minimal perfect hash function of a fixed key set: a bijection between
+/// the `len` keys and `0..len`. Other keys map to some index too, so users
+/// keep the keys around to check, as `StaticMap` does.
+#[derive(Clone)]
+pub struct PerfectHash<S = FnvBuildHasher> {
+    seed: u64,
+    len: usize,
+    displacements: Vec<(u32, u32)>,
+    hash_builder: S,
+}
+
+impl PerfectHash<FnvBuildHasher> {
+
+    pub fn build<K: Hash + Eq>(keys: &[K]) -> Result<PerfectHash<FnvBuildHasher>, PerfectHashError> {
+        PerfectHash::build_with_hasher(keys, Default::default())
+    }
+}
+
+impl<S: BuildHasher> PerfectHash<S> {
+
+    /// Builds the function of `keys`, which must be distinct.
+    pub fn build_with_hasher<K: Hash + Eq>(keys: &[K], hash_builder: S) -> Result<PerfectHash<S>, PerfectHashError> {
+        let mut seen = SimpleHashMap::with_capacity(keys.len());
+        if keys.iter().any(|key| seen.insert(key, ()).is_some()) {
+            return Err(PerfectHashError::DuplicateKey);
+        }
+        if u32::try_from(keys.len()).is_err() {
+            return Err(PerfectHashError::Unbuildable);
+        }
+        let mut hash = PerfectHash { seed: 0, len: keys.len(), displacements: Vec::new(), hash_builder };
+        let buckets = keys.len().div_ceil(AVERAGE_BUCKET_SIZE);
+        for attempt in 0..MAX_ATTEMPTS {
+            hash.seed = mix(attempt.wrapping_add(0x5EED));
+            let hashes: Vec<KeyHashes> = keys.iter().map(|key| hash.key_hashes(key, buckets)).collect();
+            if let Some(displacements) = displace(&hashes, buckets) {
+                hash.displacements = displacements;
+                return Ok(hash);
+            }
+        }
+        Err(PerfectHashError::Unbuildable)
+    }
+
+    fn key_hashes<Q: Hash + ?Sized>(&self, key: &Q, buckets: usize) -> KeyHashes {
+        let h1 = mix(self.hash_builder.hash_one((self.seed, key)));
+        let h2 = mix(h1);
+        let len = self.len.max(1) as u64;
+        KeyHashes { bucket: ((h1 >> 32) % buckets as u64) as usize, f1: h1 % len, f2: h2 % len }
+    }
+
+    /// Index of `key` in `0..len`. Distinct keys of the set get distinct
+    /// indexes, other keys any of them, and every key 0 if the set is empty.
+    pub fn index<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        if self.len == 0 {
+            return 0;
+        }
+        let hashes = self.key_hashes(key, self.displacements.len());
+        let (d1, d2) = self.displacements[hashes.bucket];
+        displaced(&hashes, d1, d2, self.len)
+    }
+
+    /// Number of keys.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Encodes the function as the magic `MPH1`, the seed, number of keys
+    /// and number of buckets as little endian u64, then the displacement
+    /// pair of each bucket as little endian u32.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(28 + self.displacements.len() * 8);
+        bytes.extend_from_slice(PERFECT_MAGIC);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&(self.len as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.displacements.len() as u64).to_le_bytes());
+        for &(d1, d2) in &self.displacements {
+            bytes.extend_from_slice(&d1.to_le_bytes());
+            bytes.extend_from_slice(&d2.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a function written by `to_bytes`, which must have used the
+    /// same kind of hasher.
+    pub fn from_bytes_with_hasher(bytes: &[u8], hash_builder: S) -> Result<PerfectHash<S>, PerfectHashError> {
+        let mut input = bytes;
+        let hash = PerfectHash::decode(&mut input, hash_builder)?;
+        if !input.is_empty() {
+            return Err(PerfectHashError::BadLength);
+        }
+        Ok(hash)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<PerfectHash<S>, PerfectHashError> where S: Default {
+        PerfectHash::from_bytes_with_hasher(bytes, Default::default())
+    }
+
+    /// Decodes a function from the front of `input`, leaving the rest.
+    fn decode(input: &mut &[u8], hash_builder: S) -> Result<PerfectHash<S>, PerfectHashError> {
+        if take(input, 4)? != PERFECT_MAGIC {
+            return Err(PerfectHashError::BadMagic);
+        }
+        let seed = read_u64(input)?;
+        let len = usize::try_from(read_u64(input)?).map_err(|_| PerfectHashError::BadLength)?;
+        let buckets = usize::try_from(read_u64(input)?).map_err(|_| PerfectHashError::BadLength)?;
+        if u32::try_from(len).is_err() || (len == 0) != (buckets == 0) || buckets.checked_mul(8).is_none_or(|size| size > input.len()) {
+            return Err(PerfectHashError::BadLength);
+        }
+        let displacements = (0..buckets)
+            .map(|_| Ok((read_u32(input)?, read_u32(input)?)))
+            .collect::<Result<_, PerfectHashError>>()?;
+        Ok(PerfectHash { seed, len, displacements, hash_builder })
+    }
+}
+
+impl<S> Debug for PerfectHash<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("PerfectHash").field("len", &self.len).field("buckets", &self.displacements.len()).finish()
+    }
+}
+
+fn displaced(hashes: &KeyHashes, d1: u32, d2: u32, len: usize) -> usize {
+    ((hashes.f2 + hashes.f1 * d1 as u64 + d2 as u64) % len as u64) as usize
+}
+
+/// Displacements sending every key to its own slot, or `None` if some
+/// bucket fits nowhere.
+fn displace(hashes: &[KeyHashes], buckets: usize) -> Option<Vec<(u32, u32)>> {
+    let len = hashes.len();
+    let mut members = vec![Vec::new(); buckets];
+    for (key, h) in hashes.iter().enumerate() {
+        members[h.bucket].push(key);
+    }
+    // Keys of a bucket with the same coefficients share a slot under every
+    // displacement, no search can separate them.
+    let mut coefficients = Vec::new();
+    for keys in &members {
+        coefficients.clear();
+        coefficients.extend(keys.iter().map(|&key| (hashes[key].f1, hashes[key].f2)));
+        coefficients.sort_unstable();
+        if coefficients.windows(2).any(|pair| pair[0] == pair[1]) {
+            return None;
+        }
+    }
+    let mut order: Vec<usize> = (0..buckets).filter(|&b| !members[b].is_empty()).collect();
+    order.sort_by_key(|&b| Reverse(members[b].len()));
+
+    let mut displacements = vec![(0, 0); buckets];
+    let mut taken = vec![false; len];
+    // Slots claimed by the displacement being tried, marked by generation
+    // so that they need no clearing between tries.
+    let mut claimed = vec![0u64; len];
+    let mut generation = 0u64;
+    let mut slots = Vec::new();
+    let first_displacements = len.min(MAX_FIRST_DISPLACEMENT) as u32;
+    for bucket in order {
+        let found = (0..first_displacements).flat_map(|d1| (0..len as u32).map(move |d2| (d1, d2))).find(|&(d1, d2)| {
+            generation += 1;
+            slots.clear();
+            for &key in &members[bucket] {
+                let slot = displaced(&hashes[key], d1, d2, len);
+                if taken[slot] || claimed[slot] == generation {
+                    return false;
+                }
+                claimed[slot] = generation;
+                slots.push(slot);
+            }
+            true
+        })?;
+        displacements[bucket] = found;
+        slots.iter().for_each(|&slot| taken[slot] = true);
+    }
+    Some(displacements)
+}
+
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], PerfectHashError> {
+    if input.len() < n {
+        return Err(PerfectHashError::BadLength);
+    }
+    let (head, rest) = input.split_at(n);
+    *input = rest;
+    Ok(head)
+}
+
+fn read_u32(input: &mut &[u8]) -> Result<u32, PerfectHashError> {
+    let mut word = [0u8; 4];
+    word.copy_from_slice(take(input, 4)?);
+    Ok(u32::from_le_bytes(word))
+}
+
+fn read_u64(input: &mut &[u8]) -> Result<u64, PerfectHashError> {
+    let mut word = [0u8; 8];
+    word.copy_from_slice(take(input, 8)?);
+    Ok(u64::from_le_bytes(word))
+}
+
+/// Keys and values which `StaticMap::to_bytes` can write out.
+pub trait Encode: Sized {
+
+    /// Appends the encoding of `self` to `out`.
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Decodes a value from the front of `input`, leaving the rest.
+    fn decode(input: &mut &[u8]) -> Option<Self>;
+}
+
+/// Encodes integers in little endian.
+macro_rules! encode_int {
+    ($($int:ty),*) => {
+        $(
+            impl Encode for $int {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(input: &mut &[u8]) -> Option<$int> {
+                    let bytes = take(input, std::mem::size_of::<$int>()).ok()?;
+                    <[u8; std::mem::size_of::<$int>()]>::try_from(bytes).ok().map(<$int>::from_le_bytes)
+                }
+            }
+        )*
+    };
+}
+
+encode_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+/// A little endian u64 length, then the bytes.
+impl Encode for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode(out);
+        out.extend_from_slice(self);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Vec<u8>> {
+        let len = usize::try_from(u64::decode(input)?).ok()?;
+        take(input, len).ok().map(<[u8]>::to_vec)
+    }
+}
+
+/// Same as the UTF-8 bytes as `Vec<u8>`.
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<String> {
+        String::from_utf8(Vec::decode(input)?).ok()
+    }
+}
+
+/// Read-only map over a fixed key set. The entries sit in a single array in
+/// the order of their perfect hash, so a lookup is one hash and one key
+/// comparison.
+#[derive(Clone)]
+pub struct StaticMap<K, V, S = FnvBuildHasher> {
+    hash: PerfectHash<S>,
+    entries: Vec<(K, V)>,
+}
+
+/// Collects the entries of a `StaticMap`.
+pub struct StaticMapBuilder<K, V, S = FnvBuildHasher> {
+    entries: Vec<(K, V)>,
+    hash_builder: S,
+}
+
+impl<K: Hash + Eq, V> StaticMapBuilder<K, V, FnvBuildHasher> {
+
+    pub fn new() -> StaticMapBuilder<K, V, FnvBuildHasher> {
+        StaticMapBuilder::with_hasher(Default::default())
+    }
+}
+
+impl<K: Hash + Eq, V> Default for StaticMapBuilder<K, V, FnvBuildHasher> {
+    fn default() -> StaticMapBuilder<K, V, FnvBuildHasher> {
+        StaticMapBuilder::new()
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> StaticMapBuilder<K, V, S> {
+
+    pub fn with_hasher(hash_builder: S) -> StaticMapBuilder<K, V, S> {
+        StaticMapBuilder { entries: Vec::new(), hash_builder }
+    }
+
+    /// Adds an entry. Keys are checked for duplicates by `build`.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.entries.push((key, value));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Computes the perfect hash of the keys and lays out the entries.
+    pub fn build(self) -> Result<StaticMap<K, V, S>, PerfectHashError> {
+        let hash = {
+            let keys: Vec<&K> = self.entries.iter().map(|(key, _)| key).collect();
+            PerfectHash::build_with_hasher(&keys, self.hash_builder)?
+        };
+        let mut slots: Vec<Option<(K, V)>> = (0..self.entries.len()).map(|_| None).collect();
+        for (key, value) in self.entries {
+            let index = hash.index(&key);
+            slots[index] = Some((key, value));
+        }
+        let entries = slots.into_iter().map(|slot| slot.expect("perfect hash is a bijection")).collect();
+        Ok(StaticMap { hash, entries })
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for StaticMapBuilder<K, V, S> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        self.entries.extend(iter);
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> StaticMap<K, V, S> {
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        match self.entries.get(self.hash.index(key)) {
+            Some((k, v)) if k.borrow() == key => Some((k, v)),
+            _ => None,
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries in the order of their perfect hash.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    pub fn perfect_hash(&self) -> &PerfectHash<S> {
+        &self.hash
+    }
+
+    /// Encodes the map as the magic `SMP1`, the bytes of its perfect hash
+    /// and then every key followed by its value, in index order.
+    pub fn to_bytes(&self) -> Vec<u8> where K: Encode, V: Encode {
+        let mut bytes = STATIC_MAP_MAGIC.to_vec();
+        bytes.extend_from_slice(&self.hash.to_bytes());
+        for (key, value) in &self.entries {
+            key.encode(&mut bytes);
+            value.encode(&mut bytes);
+        }
+        bytes
+    }
+
+    /// Decodes a map written by `to_bytes`, which must have used the same
+    /// kind of hasher. Every key is checked to hash to its own place.
+    pub fn from_bytes_with_hasher(bytes: &[u8], hash_builder: S) -> Result<StaticMap<K, V, S>, PerfectHashError>
+        where K: Encode, V: Encode {
+        let mut input = bytes;
+        if take(&mut input, 4)? != STATIC_MAP_MAGIC {
+            return Err(PerfectHashError::BadMagic);
+        }
+        let hash = PerfectHash::decode(&mut input, hash_builder)?;
+        let mut entries = Vec::with_capacity(hash.len().min(input.len()));
+        for index in 0..hash.len() {
+            let key = K::decode(&mut input).ok_or(PerfectHashError::BadEntry)?;
+            let value = V::decode(&mut input).ok_or(PerfectHashError::BadEntry)?;
+            if hash.index(&key) != index {
+                return Err(PerfectHashError::BadEntry);
+            }
+            entries.push((key, value));
+        }
+        if !input.is_empty() {
+            return Err(PerfectHashError::BadLength);
+        }
+        Ok(StaticMap { hash, entries })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<StaticMap<K, V, S>, PerfectHashError>
+        where K: Encode, V: Encode, S: Default {
+        StaticMap::from_bytes_with_hasher(bytes, Default::default())
+    }
+}
+
+impl<K: Debug, V: Debug, S> Debug for StaticMap<K, V, S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_map().entries(self.entries.iter().map(|(k, v)| (k, v))).finish()
+    }
+}
+
+#[cfg(test)]
+mod test_perfect {
+    use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+    use super::{PerfectHash, PerfectHashError, StaticMap, StaticMapBuilder};
+    use crate::hasher::{FnvBuildHasher, FxBuildHasher, mix};
+    use crate::{RSBuildHasher, RandomSipState};
+    use crate::test_util::ConstantHasher;
+
+    fn words(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("word-{}", i)).collect()
+    }
+
+    fn mime_types() -> StaticMap<String, String> {
+        let mut builder = StaticMapBuilder::new();
+        for &(ext, mime) in &[("html", "text/html"), ("css", "text/css"), ("js", "text/javascript"),
+                              ("png", "image/png"), ("json", "application/json"), ("txt", "text/plain")] {
+            builder.insert(ext.to_string(), mime.to_string());
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_bijection() {
+        for &n in &[1, 2, 3, 10, 1000, 20_000] {
+            let keys = words(n);
+            let hash = PerfectHash::build(&keys).unwrap();
+            assert_eq!(hash.len(), n);
+            let mut seen = vec![false; n];
+            for key in &keys {
+                let index = hash.index(key);
+                assert!(!seen[index], "index {} given twice", index);
+                seen[index] = true;
+            }
+        }
+    }
+
+    fn assert_bijection<S: BuildHasher>(keys: &[u64], hash_builder: S) {
+        let hash = PerfectHash::build_with_hasher(keys, hash_builder).unwrap();
+        let mut indexes: Vec<usize> = keys.iter().map(|k| hash.index(k)).collect();
+        indexes.sort_unstable();
+        assert_eq!(indexes, (0..keys.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_other_hashers() {
+        let keys: Vec<u64> = (0..5000).map(|i| i * 7919).collect();
+        assert_bijection(&keys, RSBuildHasher::default());
+        assert_bijection(&keys, FxBuildHasher::default());
+        assert_bijection(&keys, RandomSipState::new());
+    }
+
+    /// Hashes the keys `2 * i` and `2 * i + 1` to the same value.
+    #[derive(Default)]
+    struct PairHasher(u64);
+
+    impl Hasher for PairHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, _: &[u8]) {
+            unimplemented!()
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.0 = mix(self.0 ^ (i >> 1));
+        }
+    }
+
+    /// Colliding keys fail every seed at once, instead of searching all
+    /// displacement pairs of their bucket.
+    #[test]
+    fn test_colliding_hasher_is_unbuildable() {
+        let keys: Vec<u64> = (0..100_000).collect();
+        let constant = PerfectHash::build_with_hasher(&keys, BuildHasherDefault::<ConstantHasher>::default());
+        assert_eq!(constant.unwrap_err(), PerfectHashError::Unbuildable);
+        let pairs = PerfectHash::build_with_hasher(&keys, BuildHasherDefault::<PairHasher>::default());
+        assert_eq!(pairs.unwrap_err(), PerfectHashError::Unbuildable);
+        // a single pair of colliding keys among good ones
+        let mut keys: Vec<u64> = (0..50_000).map(|i| i * 2).collect();
+        keys.push(1);
+        let one_pair = PerfectHash::build_with_hasher(&keys, BuildHasherDefault::<PairHasher>::default());
+        assert_eq!(one_pair.unwrap_err(), PerfectHashError::Unbuildable);
+    }
+
+    #[test]
+    fn test_empty() {
+        let hash = PerfectHash::build::<u32>(&[]).unwrap();
+        assert!(hash.is_empty());
+        assert_eq!(hash.index(&3), 0);
+
+        let map: StaticMap<u32, u32> = StaticMapBuilder::new().build().unwrap();
+        assert!(map.is_empty());
+        assert_eq!(map.get(&3), None);
+        assert!(StaticMap::<u32, u32>::from_bytes(&map.to_bytes()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_key() {
+        assert_eq!(PerfectHash::build(&[1, 2, 1]).unwrap_err(), PerfectHashError::DuplicateKey);
+        let mut builder = StaticMapBuilder::new();
+        builder.extend(vec![("a", 1), ("b", 2), ("a", 3)]);
+        assert_eq!(builder.build().unwrap_err(), PerfectHashError::DuplicateKey);
+    }
+
+    #[test]
+    fn test_static_map() {
+        let map = mime_types();
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.get("html").map(String::as_str), Some("text/html"));
+        assert_eq!(map.get(&"json".to_string()).map(String::as_str), Some("application/json"));
+        assert_eq!(map.get_key_value("png"), Some((&"png".to_string(), &"image/png".to_string())));
+        assert!(map.contains_key("txt"));
+        assert!(!map.contains_key("gif"));
+        assert!(!map.contains_key(""));
+        let mut keys: Vec<&str> = map.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, vec!["css", "html", "js", "json", "png", "txt"]);
+        assert_eq!(map.values().count(), 6);
+        assert!(map.iter().all(|(k, v)| map.get(k) == Some(v)));
+    }
+
+    #[test]
+    fn test_large_map() {
+        let mut builder = StaticMapBuilder::new();
+        builder.extend((0..50_000u64).map(|i| (i * 3, i)));
+        let map = builder.build().unwrap();
+        for i in 0..50_000 {
+            assert_eq!(map.get(&(i * 3)), Some(&i));
+            assert_eq!(map.get(&(i * 3 + 1)), None);
+        }
+    }
+
+    #[test]
+    fn test_perfect_hash_bytes() {
+        let keys = words(3000);
+        let hash = PerfectHash::build(&keys).unwrap();
+        let decoded: PerfectHash = PerfectHash::from_bytes(&hash.to_bytes()).unwrap();
+        assert!(keys.iter().all(|key| decoded.index(key) == hash.index(key)));
+
+        let bytes = hash.to_bytes();
+        assert_eq!(PerfectHash::<FnvBuildHasher>::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+                   PerfectHashError::BadLength);
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(PerfectHash::<FnvBuildHasher>::from_bytes(&longer).unwrap_err(), PerfectHashError::BadLength);
+        assert_eq!(PerfectHash::<FnvBuildHasher>::from_bytes(b"BLM1").unwrap_err(), PerfectHashError::BadMagic);
+        assert_eq!(PerfectHash::<FnvBuildHasher>::from_bytes(b"MP").unwrap_err(), PerfectHashError::BadLength);
+        // A bucket count far beyond the bytes must not allocate.
+        let mut huge = bytes[..20].to_vec();
+        huge.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(PerfectHash::<FnvBuildHasher>::from_bytes(&huge).unwrap_err(), PerfectHashError::BadLength);
+    }
+
+    #[test]
+    fn test_static_map_bytes() {
+        let map = mime_types();
+        let bytes = map.to_bytes();
+        let decoded: StaticMap<String, String> = StaticMap::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), 6);
+        assert!(map.iter().all(|(k, v)| decoded.get(k) == Some(v)));
+
+        let mut builder = StaticMapBuilder::new();
+        builder.extend((0..1000i64).map(|i| (i - 500, vec![i as u8; (i % 5) as usize])));
+        let numbers = builder.build().unwrap();
+        let decoded: StaticMap<i64, Vec<u8>> = StaticMap::from_bytes(&numbers.to_bytes()).unwrap();
+        assert_eq!(decoded.get(&-3), Some(&vec![241; 2]));
+
+        assert_eq!(StaticMap::<String, String>::from_bytes(&bytes[..bytes.len() - 3]).unwrap_err(),
+                   PerfectHashError::BadEntry);
+        assert_eq!(StaticMap::<String, String>::from_bytes(&bytes[4..]).unwrap_err(), PerfectHashError::BadMagic);
+        // Decoding with another hasher puts keys in the wrong places.
+        assert_eq!(StaticMap::<String, String, RSBuildHasher>::from_bytes(&bytes).unwrap_err(),
+                   PerfectHashError::BadEntry);
+    }
+}
@@ -0,0 +1,92 @@
+//! Collision resolution strategies of `SimpleHashMap`.
+
+/// How `SimpleHashMap` finds another slot when the preferred one is taken.
+///
+/// Every probe sequence visits all slots of a table whose size is a power
+/// of two, which `SimpleHashMap` always is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Probing {
+    /// Try `origin + i`. Cache friendly, but neighbouring clusters merge
+    /// into long runs with a weak hash function.
+    #[default]
+    Linear,
+    /// Try `origin + i * (i + 1) / 2`, which jumps out of clusters quickly.
+    /// Keys with the same origin still share one sequence.
+    Quadratic,
+    /// Try `origin + i * step`, with an odd step derived from the whole hash,
+    /// so keys with the same origin usually take different sequences.
+    DoubleHashing,
+    /// Linear probing where an item which is farther away from its origin
+    /// takes the slot from a closer one on insertion. The mean probe length
+    /// is the same as `Linear`, but the longest one is much shorter.
+    RobinHood,
+}
+
+impl Probing {
+
+    /// Returns the slot visited by the `i`-th probe, starting from 0, for
+    /// `hash` in a table of `buckets` slots.
+    pub(crate) fn position(self, hash: u64, i: usize, buckets: usize) -> usize {
+        let origin = (hash as usize) % buckets;
+        let offset = match self {
+            Probing::Linear | Probing::RobinHood => i,
+            Probing::Quadratic => i * (i + 1) / 2,
+            Probing::DoubleHashing => i.wrapping_mul(step(hash)),
+        };
+        (origin + offset % buckets) % buckets
+    }
+
+    /// Whether removal closes the gap by moving the rest of the cluster back.
+    /// The other strategies have to leave a tombstone, because the items
+    /// behind the gap may belong to any probe sequence.
+    pub(crate) fn shifts_back(self) -> bool {
+        match self {
+            Probing::Linear | Probing::RobinHood => true,
+            Probing::Quadratic | Probing::DoubleHashing => false,
+        }
+    }
+}
+
+/// Odd step of double hashing, taken from the high bits of the hash mixed
+/// by a multiplication, since the low bits already chose the origin and
+/// some hashers, such as DEK and AP, leave the high half empty.
+fn step(hash: u64) -> usize {
+    ((hash.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize) | 1
+}
+
+/// Distance of the slot `index` from the origin of `hash`, going forward.
+pub(crate) fn displacement(hash: u64, index: usize, buckets: usize) -> usize {
+    let origin = (hash as usize) % buckets;
+    (index + buckets - origin) % buckets
+}
+
+#[cfg(test)]
+mod test_probing {
+    use super::Probing;
+
+    #[test]
+    fn test_sequences_visit_every_slot() {
+        let strategies = [Probing::Linear, Probing::Quadratic, Probing::DoubleHashing, Probing::RobinHood];
+        for &probing in &strategies {
+            for &buckets in &[32, 64, 1024] {
+                for &hash in &[0u64, 7, 0xdead_beef, u64::MAX] {
+                    let mut seen = vec![false; buckets];
+                    for i in 0..buckets {
+                        seen[probing.position(hash, i, buckets)] = true;
+                    }
+                    assert!(seen.iter().all(|s| *s), "{:?} {} {:x}", probing, buckets, hash);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_first_probe_is_origin() {
+        for &probing in &[Probing::Linear, Probing::Quadratic, Probing::DoubleHashing, Probing::RobinHood] {
+            assert_eq!(probing.position(37, 0, 32), 5);
+        }
+        assert_eq!(Probing::Linear.position(37, 3, 32), 8);
+        assert_eq!(Probing::Quadratic.position(37, 3, 32), 11);
+        assert_eq!(Probing::Linear.position(31, 1, 32), 0);
+    }
+}
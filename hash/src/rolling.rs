@@ -0,0 +1,333 @@
+//! Rolling hashes over a sliding window of bytes, and Rabin-Karp substring
+//! search built on them.
+
+use super::hasher::mix;
+use super::SimpleHashMap;
+
+/// Hash of the last `window` bytes fed to it. Sliding the window by one
+/// byte costs the same whatever its size.
+pub trait RollingHash {
+
+    /// Bytes in a full window.
+    fn window(&self) -> usize;
+
+    /// Appends `byte`, dropping the oldest byte once the window is full.
+    fn push(&mut self, byte: u8);
+
+    /// Hash of the bytes in the window, fewer than `window` of them until
+    /// it is full.
+    fn hash(&self) -> u64;
+
+    /// Whether `window` bytes were pushed since creation or the last `reset`.
+    fn is_full(&self) -> bool;
+
+    /// Empties the window.
+    fn reset(&mut self);
+}
+
+/// 256 pseudorandom words, a fixed function of `seed`.
+pub(crate) const fn random_table(seed: u64) -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = mix(seed.wrapping_add(i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        i += 1;
+    }
+    table
+}
+
+/// Ring buffer of the bytes in the window, so that the hashes know which
+/// byte leaves it.
+#[derive(Debug, Clone)]
+struct Window {
+    bytes: Vec<u8>,
+    next: usize,
+    filled: usize,
+}
+
+impl Window {
+
+    fn new(size: usize) -> Window {
+        assert!(size > 0, "window must hold at least one byte");
+        Window { bytes: vec![0; size], next: 0, filled: 0 }
+    }
+
+    /// Stores `byte`, returning the byte it pushes out of a full window.
+    fn push(&mut self, byte: u8) -> Option<u8> {
+        let old = std::mem::replace(&mut self.bytes[self.next], byte);
+        self.next = (self.next + 1) % self.bytes.len();
+        if self.filled == self.bytes.len() {
+            Some(old)
+        } else {
+            self.filled += 1;
+            None
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.filled == self.bytes.len()
+    }
+
+    fn reset(&mut self) {
+        self.next = 0;
+        self.filled = 0;
+    }
+}
+
+/// Mersenne prime `2^61 - 1`, so that products fit in 128 bits.
+const MODULUS: u64 = (1 << 61) - 1;
+const DEFAULT_BASE: u64 = 0x100_0000_01b3;
+
+fn mul_mod(a: u64, b: u64) -> u64 {
+    (a as u128 * b as u128 % MODULUS as u128) as u64
+}
+
+/// Rabin-Karp hash: the window read as a number in base `base`, modulo
+/// `2^61 - 1`. Byte `b` counts as the digit `b + 1`, so leading zero bytes
+/// still change the hash.
+#[derive(Debug, Clone)]
+pub struct RabinKarp {
+    window: Window,
+    base: u64,
+    /// `base^(window - 1)`, the weight of the oldest byte.
+    top: u64,
+    hash: u64,
+}
+
+impl RabinKarp {
+
+    pub fn new(window: usize) -> RabinKarp {
+        RabinKarp::with_base(window, DEFAULT_BASE)
+    }
+
+    /// `base` should be large and is taken modulo `2^61 - 1`.
+    pub fn with_base(window: usize, base: u64) -> RabinKarp {
+        let base = base % MODULUS;
+        let top = (1..window).fold(1, |top, _| mul_mod(top, base));
+        RabinKarp { window: Window::new(window), base, top, hash: 0 }
+    }
+}
+
+impl RollingHash for RabinKarp {
+
+    fn window(&self) -> usize {
+        self.window.bytes.len()
+    }
+
+    fn push(&mut self, byte: u8) {
+        if let Some(old) = self.window.push(byte) {
+            self.hash = (self.hash + MODULUS - mul_mod(old as u64 + 1, self.top)) % MODULUS;
+        }
+        self.hash = (mul_mod(self.hash, self.base) + byte as u64 + 1) % MODULUS;
+    }
+
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    fn is_full(&self) -> bool {
+        self.window.is_full()
+    }
+
+    fn reset(&mut self) {
+        self.window.reset();
+        self.hash = 0;
+    }
+}
+
+static BUZHASH_TABLE: [u64; 256] = random_table(0x6275_7a68_6173_6821);
+
+/// Buzhash, or cyclic polynomial hash: the XOR of a random word per byte,
+/// each rotated by its distance from the end of the window. Cheaper than
+/// `RabinKarp`, with no multiplication at all.
+#[derive(Debug, Clone)]
+pub struct BuzHash {
+    window: Window,
+    hash: u64,
+}
+
+impl BuzHash {
+
+    pub fn new(window: usize) -> BuzHash {
+        BuzHash { window: Window::new(window), hash: 0 }
+    }
+}
+
+impl RollingHash for BuzHash {
+
+    fn window(&self) -> usize {
+        self.window.bytes.len()
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.hash = self.hash.rotate_left(1) ^ BUZHASH_TABLE[byte as usize];
+        if let Some(old) = self.window.push(byte) {
+            self.hash ^= BUZHASH_TABLE[old as usize].rotate_left((self.window() % 64) as u32);
+        }
+    }
+
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    fn is_full(&self) -> bool {
+        self.window.is_full()
+    }
+
+    fn reset(&mut self) {
+        self.window.reset();
+        self.hash = 0;
+    }
+}
+
+/// Rabin-Karp hash of the whole of `bytes`.
+fn hash_of(bytes: &[u8]) -> u64 {
+    let mut hash = RabinKarp::new(bytes.len());
+    bytes.iter().for_each(|&b| hash.push(b));
+    hash.hash()
+}
+
+/// Offset of the first occurrence of `needle` in `haystack`. An empty
+/// needle is found at 0.
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    let target = hash_of(needle);
+    let mut hash = RabinKarp::new(needle.len());
+    for (i, &byte) in haystack.iter().enumerate() {
+        hash.push(byte);
+        let start = (i + 1).wrapping_sub(needle.len());
+        if hash.is_full() && hash.hash() == target && &haystack[start..=i] == needle {
+            return Some(start);
+        }
+    }
+    None
+}
+
+/// Every occurrence of every pattern in `haystack` as `(offset, pattern
+/// index)`, ordered by offset and then index. Overlapping occurrences are
+/// all reported and empty patterns are ignored.
+///
+/// A single pass per distinct pattern length, however many patterns share
+/// it.
+pub fn find_all(haystack: &[u8], patterns: &[&[u8]]) -> Vec<(usize, usize)> {
+    let mut lengths: Vec<usize> = patterns.iter().map(|p| p.len()).filter(|&len| len > 0).collect();
+    lengths.sort_unstable();
+    lengths.dedup();
+
+    let mut found = Vec::new();
+    for len in lengths {
+        let mut by_hash: SimpleHashMap<u64, Vec<usize>> = SimpleHashMap::new();
+        for (index, pattern) in patterns.iter().enumerate().filter(|(_, p)| p.len() == len) {
+            by_hash.entry(hash_of(pattern)).or_default().push(index);
+        }
+        let mut hash = RabinKarp::new(len);
+        for (i, &byte) in haystack.iter().enumerate() {
+            hash.push(byte);
+            if !hash.is_full() {
+                continue;
+            }
+            if let Some(indexes) = by_hash.get(&hash.hash()) {
+                let start = i + 1 - len;
+                let matches = indexes.iter().filter(|&&index| patterns[index] == &haystack[start..=i]);
+                found.extend(matches.map(|&index| (start, index)));
+            }
+        }
+    }
+    found.sort_unstable();
+    found
+}
+
+#[cfg(test)]
+mod test_rolling {
+    use super::{RollingHash, RabinKarp, BuzHash, find, find_all};
+    use crate::test_util::random_bytes;
+
+    /// The rolled hash must equal the hash of the window fed from scratch.
+    fn check_rolling<H: RollingHash>(mut rolling: H, fresh: impl Fn() -> H) {
+        let data = random_bytes(500, 7);
+        let window = rolling.window();
+        for (i, &byte) in data.iter().enumerate() {
+            rolling.push(byte);
+            assert_eq!(rolling.is_full(), i + 1 >= window);
+            let mut expected = fresh();
+            data[(i + 1).saturating_sub(window)..=i].iter().for_each(|&b| expected.push(b));
+            assert_eq!(rolling.hash(), expected.hash(), "position {}", i);
+        }
+        rolling.reset();
+        assert!(!rolling.is_full());
+        assert_eq!(rolling.hash(), fresh().hash());
+    }
+
+    #[test]
+    fn test_rabin_karp_rolls() {
+        for &window in &[1, 2, 16, 48] {
+            check_rolling(RabinKarp::new(window), || RabinKarp::new(window));
+        }
+        check_rolling(RabinKarp::with_base(5, 257), || RabinKarp::with_base(5, 257));
+    }
+
+    #[test]
+    fn test_buzhash_rolls() {
+        // Windows of 64 and more rotate the leaving byte all the way round.
+        for &window in &[1, 3, 32, 64, 100] {
+            check_rolling(BuzHash::new(window), || BuzHash::new(window));
+        }
+    }
+
+    #[test]
+    fn test_leading_zeros() {
+        let mut a = RabinKarp::new(4);
+        let mut b = RabinKarp::new(4);
+        a.push(1);
+        b.push(0);
+        b.push(1);
+        assert_ne!(a.hash(), b.hash());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_empty_window() {
+        BuzHash::new(0);
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find(b"hello world", b"world"), Some(6));
+        assert_eq!(find(b"hello world", b"o"), Some(4));
+        assert_eq!(find(b"hello world", b"hello world"), Some(0));
+        assert_eq!(find(b"hello world", b"worlds"), None);
+        assert_eq!(find(b"hello", b""), Some(0));
+        assert_eq!(find(b"", b"a"), None);
+        assert_eq!(find(b"aaab", b"aab"), Some(1));
+    }
+
+    #[test]
+    fn test_find_all() {
+        let patterns: [&[u8]; 5] = [b"ab", b"b", b"abc", b"", b"ab"];
+        assert_eq!(find_all(b"xabcab", &patterns),
+                   vec![(1, 0), (1, 2), (1, 4), (2, 1), (4, 0), (4, 4), (5, 1)]);
+        assert_eq!(find_all(b"aaaa", &[b"aa"]), vec![(0, 0), (1, 0), (2, 0)]);
+        assert!(find_all(b"abc", &[]).is_empty());
+        assert!(find_all(b"", &[b"a"]).is_empty());
+    }
+
+    #[test]
+    fn test_find_all_matches_naive() {
+        // A small alphabet makes plenty of matches.
+        let haystack: Vec<u8> = random_bytes(2000, 3).iter().map(|b| b'a' + b % 3).collect();
+        let patterns: Vec<Vec<u8>> = (0..30).map(|i| haystack[i * 37..i * 37 + 1 + i % 6].to_vec()).collect();
+        let pattern_refs: Vec<&[u8]> = patterns.iter().map(|p| p.as_slice()).collect();
+
+        let mut expected = Vec::new();
+        for start in 0..haystack.len() {
+            for (index, pattern) in patterns.iter().enumerate() {
+                if haystack[start..].starts_with(pattern) {
+                    expected.push((start, index));
+                }
+            }
+        }
+        assert_eq!(find_all(&haystack, &pattern_refs), expected);
+    }
+}
@@ -0,0 +1,588 @@
+use std::hash::{Hash, BuildHasher};
+use std::iter::{Chain, FromIterator};
+use std::fmt::{Debug, Formatter, Result};
+use std::ops::{BitAnd, BitOr, BitXor, Sub};
+
+use super::{SimpleHashMap, Keys, RSBuildHasher};
+
+/// Hash set implemented as a `SimpleHashMap` where the value is `()`.
+pub struct SimpleHashSet<T, S = RSBuildHasher> {
+    map: SimpleHashMap<T, (), S>,
+}
+
+pub struct Iter<'a, T: 'a> {
+    inner: Keys<'a, T, ()>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.inner.next()
+    }
+}
+
+/// Lazy iterator over the items in both sets, see `SimpleHashSet::intersection`.
+pub struct Intersection<'a, T: 'a, S: 'a> {
+    iter: Iter<'a, T>,
+    other: &'a SimpleHashSet<T, S>,
+}
+
+impl<'a, T, S> Iterator for Intersection<'a, T, S> where T: Hash + Eq, S: BuildHasher {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let other = self.other;
+        self.iter.find(|item| other.contains(item))
+    }
+}
+
+/// Lazy iterator over the items in the first set only, see
+/// `SimpleHashSet::difference`.
+pub struct Difference<'a, T: 'a, S: 'a> {
+    iter: Iter<'a, T>,
+    other: &'a SimpleHashSet<T, S>,
+}
+
+impl<'a, T, S> Iterator for Difference<'a, T, S> where T: Hash + Eq, S: BuildHasher {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let other = self.other;
+        self.iter.find(|item| !other.contains(item))
+    }
+}
+
+/// Lazy iterator over the items in exactly one of the sets, see
+/// `SimpleHashSet::symmetric_difference`.
+pub struct SymmetricDifference<'a, T: 'a, S: 'a> {
+    iter: Chain<Difference<'a, T, S>, Difference<'a, T, S>>,
+}
+
+impl<'a, T, S> Iterator for SymmetricDifference<'a, T, S> where T: Hash + Eq, S: BuildHasher {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.next()
+    }
+}
+
+/// Lazy iterator over the items in any of the sets, see `SimpleHashSet::union`.
+pub struct Union<'a, T: 'a, S: 'a> {
+    iter: Chain<Iter<'a, T>, Difference<'a, T, S>>,
+}
+
+impl<'a, T, S> Iterator for Union<'a, T, S> where T: Hash + Eq, S: BuildHasher {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.next()
+    }
+}
+
+impl<T: Hash + Eq> SimpleHashSet<T, RSBuildHasher> {
+
+    pub fn new() -> SimpleHashSet<T, RSBuildHasher> {
+        SimpleHashSet { map: SimpleHashMap::new() }
+    }
+
+    pub fn with_capacity(capacity: usize) -> SimpleHashSet<T, RSBuildHasher> {
+        SimpleHashSet { map: SimpleHashMap::with_capacity(capacity) }
+    }
+}
+
+impl<T, S> Default for SimpleHashSet<T, S> where T: Hash + Eq, S: BuildHasher + Default {
+    fn default() -> SimpleHashSet<T, S> {
+        SimpleHashSet::with_hasher(Default::default())
+    }
+}
+
+impl<T, S> SimpleHashSet<T, S> where T: Hash + Eq, S: BuildHasher {
+
+    pub fn with_hasher(hash_builder: S) -> SimpleHashSet<T, S> {
+        SimpleHashSet { map: SimpleHashMap::with_hasher(hash_builder) }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> SimpleHashSet<T, S> {
+        SimpleHashSet { map: SimpleHashMap::with_capacity_and_hasher(capacity, hash_builder) }
+    }
+
+    /// Returns a reference to the set's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { inner: self.map.keys() }
+    }
+
+    pub fn len(&self) -> usize { self.map.len() }
+
+    pub fn is_empty(&self) -> bool { self.map.is_empty() }
+
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Adds a value to the set, returns whether it was not present yet.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.map.contains_key(value)
+    }
+
+    /// Removes a value from the set, returns whether it was present.
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.map.remove(value).is_some()
+    }
+
+    /// Visits the values in `self` but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a SimpleHashSet<T, S>) -> Difference<'a, T, S> {
+        Difference { iter: self.iter(), other }
+    }
+
+    /// Visits the values in `self` or in `other`, but not in both.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a SimpleHashSet<T, S>) -> SymmetricDifference<'a, T, S> {
+        SymmetricDifference { iter: self.difference(other).chain(other.difference(self)) }
+    }
+
+    /// Visits the values in both `self` and `other`.
+    pub fn intersection<'a>(&'a self, other: &'a SimpleHashSet<T, S>) -> Intersection<'a, T, S> {
+        Intersection { iter: self.iter(), other }
+    }
+
+    /// Visits the values in `self` or in `other`, without duplicates.
+    pub fn union<'a>(&'a self, other: &'a SimpleHashSet<T, S>) -> Union<'a, T, S> {
+        Union { iter: self.iter().chain(other.difference(self)) }
+    }
+
+    pub fn is_disjoint(&self, other: &SimpleHashSet<T, S>) -> bool {
+        self.iter().all(|v| !other.contains(v))
+    }
+
+    pub fn is_subset(&self, other: &SimpleHashSet<T, S>) -> bool {
+        self.len() <= other.len() && self.iter().all(|v| other.contains(v))
+    }
+
+    pub fn is_superset(&self, other: &SimpleHashSet<T, S>) -> bool {
+        other.is_subset(self)
+    }
+}
+
+impl<'a, T, S> IntoIterator for &'a SimpleHashSet<T, S> where T: Hash + Eq, S: BuildHasher {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T, S> FromIterator<T> for SimpleHashSet<T, S>
+    where T: Hash + Eq,
+          S: BuildHasher + Default {
+
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> SimpleHashSet<T, S> {
+        let mut set = SimpleHashSet::with_hasher(Default::default());
+        set.extend(iter);
+        set
+    }
+}
+
+impl<T, S> Extend<T> for SimpleHashSet<T, S> where T: Hash + Eq, S: BuildHasher {
+
+    fn extend<I: IntoIterator<Item=T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl<'a, T, S> Extend<&'a T> for SimpleHashSet<T, S> where T: 'a + Hash + Eq + Copy, S: BuildHasher {
+
+    fn extend<I: IntoIterator<Item=&'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().cloned());
+    }
+}
+
+impl<T, S> PartialEq for SimpleHashSet<T, S> where T: Hash + Eq, S: BuildHasher {
+
+    fn eq(&self, other: &SimpleHashSet<T, S>) -> bool {
+        self.len() == other.len() && self.is_subset(other)
+    }
+}
+
+impl<T, S> Debug for SimpleHashSet<T, S> where T: Hash + Eq + Debug, S: BuildHasher {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, S> BitOr<&SimpleHashSet<T, S>> for &SimpleHashSet<T, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Default {
+
+    type Output = SimpleHashSet<T, S>;
+
+    /// Returns the union of `self` and `rhs` as a new set.
+    fn bitor(self, rhs: &SimpleHashSet<T, S>) -> SimpleHashSet<T, S> {
+        self.union(rhs).cloned().collect()
+    }
+}
+
+impl<T, S> BitAnd<&SimpleHashSet<T, S>> for &SimpleHashSet<T, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Default {
+
+    type Output = SimpleHashSet<T, S>;
+
+    /// Returns the intersection of `self` and `rhs` as a new set.
+    fn bitand(self, rhs: &SimpleHashSet<T, S>) -> SimpleHashSet<T, S> {
+        self.intersection(rhs).cloned().collect()
+    }
+}
+
+impl<T, S> BitXor<&SimpleHashSet<T, S>> for &SimpleHashSet<T, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Default {
+
+    type Output = SimpleHashSet<T, S>;
+
+    /// Returns the symmetric difference of `self` and `rhs` as a new set.
+    fn bitxor(self, rhs: &SimpleHashSet<T, S>) -> SimpleHashSet<T, S> {
+        self.symmetric_difference(rhs).cloned().collect()
+    }
+}
+
+impl<T, S> Sub<&SimpleHashSet<T, S>> for &SimpleHashSet<T, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Default {
+
+    type Output = SimpleHashSet<T, S>;
+
+    /// Returns the difference of `self` and `rhs` as a new set.
+    fn sub(self, rhs: &SimpleHashSet<T, S>) -> SimpleHashSet<T, S> {
+        self.difference(rhs).cloned().collect()
+    }
+}
+
+/// copy part of unit tests from HashSet
+#[cfg(test)]
+mod test_simple_set {
+    use super::SimpleHashSet;
+
+    #[test]
+    fn test_zero_capacities() {
+        type HS = SimpleHashSet<i32>;
+
+        let s = HS::new();
+        assert_eq!(s.capacity(), 0);
+
+        let s = HS::with_capacity(0);
+        assert_eq!(s.capacity(), 0);
+    }
+
+    #[test]
+    fn test_insert_contains_remove() {
+        let mut s = SimpleHashSet::new();
+        assert!(s.insert(1));
+        assert!(!s.insert(1));
+        assert!(s.insert(2));
+        assert_eq!(s.len(), 2);
+        assert!(s.contains(&1));
+        assert!(!s.contains(&3));
+        assert!(s.remove(&1));
+        assert!(!s.remove(&1));
+        assert!(!s.contains(&1));
+        assert_eq!(s.len(), 1);
+    }
+
+    #[test]
+    fn test_disjoint() {
+        let mut xs = SimpleHashSet::new();
+        let mut ys = SimpleHashSet::new();
+        assert!(xs.is_disjoint(&ys));
+        assert!(ys.is_disjoint(&xs));
+        assert!(xs.insert(5));
+        assert!(ys.insert(11));
+        assert!(xs.is_disjoint(&ys));
+        assert!(ys.is_disjoint(&xs));
+        assert!(xs.insert(7));
+        assert!(xs.insert(19));
+        assert!(xs.insert(4));
+        assert!(ys.insert(2));
+        assert!(ys.insert(-11));
+        assert!(xs.is_disjoint(&ys));
+        assert!(ys.is_disjoint(&xs));
+        assert!(ys.insert(7));
+        assert!(!xs.is_disjoint(&ys));
+        assert!(!ys.is_disjoint(&xs));
+    }
+
+    #[test]
+    fn test_subset_and_superset() {
+        let mut a = SimpleHashSet::new();
+        assert!(a.insert(0));
+        assert!(a.insert(5));
+        assert!(a.insert(11));
+        assert!(a.insert(7));
+
+        let mut b = SimpleHashSet::new();
+        assert!(b.insert(0));
+        assert!(b.insert(7));
+        assert!(b.insert(19));
+        assert!(b.insert(250));
+        assert!(b.insert(11));
+        assert!(b.insert(200));
+
+        assert!(!a.is_subset(&b));
+        assert!(!a.is_superset(&b));
+        assert!(!b.is_subset(&a));
+        assert!(!b.is_superset(&a));
+
+        assert!(b.insert(5));
+
+        assert!(a.is_subset(&b));
+        assert!(!a.is_superset(&b));
+        assert!(!b.is_subset(&a));
+        assert!(b.is_superset(&a));
+    }
+
+    #[test]
+    fn test_iterate() {
+        let mut a = SimpleHashSet::new();
+        for i in 0..32 {
+            assert!(a.insert(i));
+        }
+        let mut observed: u32 = 0;
+        for k in &a {
+            observed |= 1 << *k;
+        }
+        assert_eq!(observed, 0xFFFF_FFFF);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let mut a = SimpleHashSet::new();
+        let mut b = SimpleHashSet::new();
+        assert!(a.intersection(&b).next().is_none());
+
+        assert!(a.insert(11));
+        assert!(a.insert(1));
+        assert!(a.insert(3));
+        assert!(a.insert(77));
+        assert!(a.insert(103));
+        assert!(a.insert(5));
+        assert!(a.insert(-5));
+
+        assert!(b.insert(2));
+        assert!(b.insert(11));
+        assert!(b.insert(77));
+        assert!(b.insert(-9));
+        assert!(b.insert(-42));
+        assert!(b.insert(5));
+        assert!(b.insert(3));
+
+        let mut i = 0;
+        let expected = [3, 5, 11, 77];
+        for x in a.intersection(&b) {
+            assert!(expected.contains(x));
+            i += 1
+        }
+        assert_eq!(i, expected.len());
+    }
+
+    #[test]
+    fn test_difference() {
+        let mut a = SimpleHashSet::new();
+        let mut b = SimpleHashSet::new();
+
+        assert!(a.insert(1));
+        assert!(a.insert(3));
+        assert!(a.insert(5));
+        assert!(a.insert(9));
+        assert!(a.insert(11));
+
+        assert!(b.insert(3));
+        assert!(b.insert(9));
+
+        let mut i = 0;
+        let expected = [1, 5, 11];
+        for x in a.difference(&b) {
+            assert!(expected.contains(x));
+            i += 1
+        }
+        assert_eq!(i, expected.len());
+    }
+
+    #[test]
+    fn test_symmetric_difference() {
+        let mut a = SimpleHashSet::new();
+        let mut b = SimpleHashSet::new();
+
+        assert!(a.insert(1));
+        assert!(a.insert(3));
+        assert!(a.insert(5));
+        assert!(a.insert(9));
+        assert!(a.insert(11));
+
+        assert!(b.insert(-2));
+        assert!(b.insert(3));
+        assert!(b.insert(9));
+        assert!(b.insert(14));
+        assert!(b.insert(22));
+
+        let mut i = 0;
+        let expected = [-2, 1, 5, 11, 14, 22];
+        for x in a.symmetric_difference(&b) {
+            assert!(expected.contains(x));
+            i += 1
+        }
+        assert_eq!(i, expected.len());
+    }
+
+    #[test]
+    fn test_union() {
+        let mut a = SimpleHashSet::new();
+        let mut b = SimpleHashSet::new();
+        assert!(a.union(&b).next().is_none());
+        assert!(b.union(&a).next().is_none());
+
+        assert!(a.insert(1));
+        assert!(a.insert(3));
+        assert!(a.insert(11));
+        assert!(a.insert(16));
+        assert!(a.insert(19));
+        assert!(a.insert(24));
+
+        assert!(b.insert(-2));
+        assert!(b.insert(1));
+        assert!(b.insert(5));
+        assert!(b.insert(9));
+        assert!(b.insert(13));
+        assert!(b.insert(19));
+
+        let mut i = 0;
+        let expected = [-2, 1, 3, 5, 9, 11, 13, 16, 19, 24];
+        for x in a.union(&b) {
+            assert!(expected.contains(x));
+            i += 1
+        }
+        assert_eq!(i, expected.len());
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let xs = [1, 2, 2, 3, 4, 5, 6, 7, 8, 9];
+
+        let set: SimpleHashSet<_> = xs.iter().cloned().collect();
+
+        for x in &xs {
+            assert!(set.contains(x));
+        }
+
+        assert_eq!(set.iter().count(), xs.len() - 1);
+    }
+
+    #[test]
+    fn test_extend_ref() {
+        let mut a = SimpleHashSet::new();
+        a.insert(1);
+
+        a.extend(&[2, 3, 4]);
+
+        assert_eq!(a.len(), 4);
+        assert!(a.contains(&1));
+        assert!(a.contains(&2));
+        assert!(a.contains(&3));
+        assert!(a.contains(&4));
+
+        let mut b = SimpleHashSet::new();
+        b.insert(5);
+        b.insert(6);
+
+        a.extend(&b.iter().cloned().collect::<Vec<_>>());
+
+        assert_eq!(a.len(), 6);
+        assert!(a.contains(&5));
+        assert!(a.contains(&6));
+    }
+
+    #[test]
+    fn test_eq() {
+        let mut s1 = SimpleHashSet::new();
+
+        s1.insert(1);
+        s1.insert(2);
+        s1.insert(3);
+
+        let mut s2 = SimpleHashSet::new();
+
+        s2.insert(1);
+        s2.insert(2);
+
+        assert!(s1 != s2);
+
+        s2.insert(3);
+
+        assert_eq!(s1, s2);
+    }
+
+    #[test]
+    fn test_show() {
+        let mut set = SimpleHashSet::new();
+        let empty = SimpleHashSet::<i32>::new();
+
+        set.insert(1);
+        set.insert(2);
+
+        let set_str = format!("{:?}", set);
+
+        assert!(set_str == "{1, 2}" || set_str == "{2, 1}");
+        assert_eq!(format!("{:?}", empty), "{}");
+    }
+
+    #[test]
+    fn test_operators() {
+        let a: SimpleHashSet<_> = vec![1, 2, 3].into_iter().collect();
+        let b: SimpleHashSet<_> = vec![3, 4, 5].into_iter().collect();
+
+        let expected: SimpleHashSet<_> = vec![1, 2, 3, 4, 5].into_iter().collect();
+        assert_eq!(&a | &b, expected);
+
+        let expected: SimpleHashSet<_> = vec![3].into_iter().collect();
+        assert_eq!(&a & &b, expected);
+
+        let expected: SimpleHashSet<_> = vec![1, 2].into_iter().collect();
+        assert_eq!(&a - &b, expected);
+
+        let expected: SimpleHashSet<_> = vec![1, 2, 4, 5].into_iter().collect();
+        assert_eq!(&a ^ &b, expected);
+    }
+
+    #[test]
+    fn test_operators_with_strings() {
+        let a: SimpleHashSet<String> = ["x", "y"].iter().map(|s| s.to_string()).collect();
+        let b: SimpleHashSet<String> = ["y", "z"].iter().map(|s| s.to_string()).collect();
+        let union = &a | &b;
+        assert_eq!(union.len(), 3);
+        assert!(union.contains(&"z".to_string()));
+        // operands are left untouched
+        assert_eq!(a.len(), 2);
+        assert_eq!(b.len(), 2);
+    }
+}
@@ -0,0 +1,359 @@
+//! Assigning keys to a changing set of nodes, such that few keys move when
+//! a node joins or leaves.
+
+use std::cmp::Reverse;
+use std::hash::{Hash, BuildHasher};
+
+use super::hasher::{FnvBuildHasher, mix};
+
+/// Virtual nodes per node of a `ConsistentHashRing`, as in ketama.
+const DEFAULT_VIRTUAL_NODES: usize = 160;
+
+/// Maps keys to the nodes storing them.
+pub trait NodeLocator<N> {
+
+    /// Adds `node`, returns false if it is present already.
+    fn add_node(&mut self, node: N) -> bool;
+
+    /// Removes `node`, returns false if it is absent.
+    fn remove_node(&mut self, node: &N) -> bool;
+
+    /// Returns the nodes, in the order they were added.
+    fn nodes(&self) -> &[N];
+
+    /// Returns the node owning `key`, followed by up to `replicas` other
+    /// nodes for copies of it. Asking for fewer replicas returns a prefix of
+    /// the same list.
+    fn locate_replicas<K: Hash + ?Sized>(&self, key: &K, replicas: usize) -> Vec<&N>;
+
+    /// Returns the node owning `key`, `None` without nodes.
+    fn locate<K: Hash + ?Sized>(&self, key: &K) -> Option<&N> {
+        self.locate_replicas(key, 0).into_iter().next()
+    }
+}
+
+/// Consistent hashing: every node owns the arcs of a hash ring ending at its
+/// virtual nodes, and a key belongs to the first virtual node at or after
+/// its hash. Adding a node only takes keys over from its neighbours,
+/// removing one hands its keys to the following virtual nodes.
+///
+/// The default hasher is the 64 bit FNV-1a, so ring positions depend on
+/// nothing but the node and key, in every process.
+pub struct ConsistentHashRing<N, S = FnvBuildHasher> {
+    nodes: Vec<N>,
+    /// Virtual nodes as (position, index into `nodes`), sorted.
+    ring: Vec<(u64, usize)>,
+    virtual_nodes: usize,
+    hash_builder: S,
+}
+
+impl<N: Hash + Eq> ConsistentHashRing<N, FnvBuildHasher> {
+
+    pub fn new() -> ConsistentHashRing<N, FnvBuildHasher> {
+        ConsistentHashRing::with_virtual_nodes(DEFAULT_VIRTUAL_NODES)
+    }
+
+    /// Creates a ring placing every node `virtual_nodes` times, at least
+    /// once. More virtual nodes spread the keys more evenly.
+    pub fn with_virtual_nodes(virtual_nodes: usize) -> ConsistentHashRing<N, FnvBuildHasher> {
+        ConsistentHashRing::with_virtual_nodes_and_hasher(virtual_nodes, Default::default())
+    }
+}
+
+impl<N: Hash + Eq> Default for ConsistentHashRing<N, FnvBuildHasher> {
+    fn default() -> ConsistentHashRing<N, FnvBuildHasher> {
+        ConsistentHashRing::new()
+    }
+}
+
+impl<N: Hash + Eq, S: BuildHasher> ConsistentHashRing<N, S> {
+
+    pub fn with_virtual_nodes_and_hasher(virtual_nodes: usize, hash_builder: S) -> ConsistentHashRing<N, S> {
+        ConsistentHashRing { nodes: Vec::new(), ring: Vec::new(), virtual_nodes: virtual_nodes.max(1), hash_builder }
+    }
+
+    pub fn virtual_nodes(&self) -> usize {
+        self.virtual_nodes
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// The hash is mixed, so that the 32 bit hashers of this crate also
+    /// cover the whole ring.
+    fn position<K: Hash + ?Sized>(&self, key: &K) -> u64 {
+        mix(self.hash_builder.hash_one(key))
+    }
+}
+
+impl<N: Hash + Eq, S: BuildHasher> NodeLocator<N> for ConsistentHashRing<N, S> {
+
+    fn add_node(&mut self, node: N) -> bool {
+        if self.nodes.contains(&node) {
+            return false;
+        }
+        let index = self.nodes.len();
+        for i in 0..self.virtual_nodes as u64 {
+            self.ring.push((self.position(&(&node, i)), index));
+        }
+        self.ring.sort_unstable();
+        self.nodes.push(node);
+        true
+    }
+
+    fn remove_node(&mut self, node: &N) -> bool {
+        let Some(index) = self.nodes.iter().position(|n| n == node) else {
+            return false;
+        };
+        self.nodes.remove(index);
+        self.ring.retain(|&(_, i)| i != index);
+        for point in &mut self.ring {
+            if point.1 > index {
+                point.1 -= 1;
+            }
+        }
+        true
+    }
+
+    fn nodes(&self) -> &[N] {
+        &self.nodes
+    }
+
+    fn locate_replicas<K: Hash + ?Sized>(&self, key: &K, replicas: usize) -> Vec<&N> {
+        let wanted = self.nodes.len().min(replicas.saturating_add(1));
+        let mut found: Vec<usize> = Vec::with_capacity(wanted);
+        let hash = self.position(key);
+        let start = self.ring.partition_point(|&(position, _)| position < hash);
+        // walk clockwise, wrapping around, and skip nodes seen already
+        for &(_, index) in self.ring[start..].iter().chain(&self.ring[..start]) {
+            if found.len() == wanted {
+                break;
+            }
+            if !found.contains(&index) {
+                found.push(index);
+            }
+        }
+        found.into_iter().map(|i| &self.nodes[i]).collect()
+    }
+
+    fn locate<K: Hash + ?Sized>(&self, key: &K) -> Option<&N> {
+        let hash = self.position(key);
+        let start = self.ring.partition_point(|&(position, _)| position < hash);
+        let &(_, index) = self.ring.get(start).or_else(|| self.ring.first())?;
+        Some(&self.nodes[index])
+    }
+}
+
+/// Rendezvous (highest random weight) hashing: every node scores the key
+/// with a hash of both, and the highest score wins. Adding a node only
+/// takes the keys it wins, removing one hands each of its keys to the
+/// runner-up. Lookups cost O(nodes), but no memory per virtual node.
+pub struct RendezvousHashing<N, S = FnvBuildHasher> {
+    nodes: Vec<N>,
+    hash_builder: S,
+}
+
+impl<N: Hash + Eq> RendezvousHashing<N, FnvBuildHasher> {
+
+    pub fn new() -> RendezvousHashing<N, FnvBuildHasher> {
+        RendezvousHashing::with_hasher(Default::default())
+    }
+}
+
+impl<N: Hash + Eq> Default for RendezvousHashing<N, FnvBuildHasher> {
+    fn default() -> RendezvousHashing<N, FnvBuildHasher> {
+        RendezvousHashing::new()
+    }
+}
+
+impl<N: Hash + Eq, S: BuildHasher> RendezvousHashing<N, S> {
+
+    pub fn with_hasher(hash_builder: S) -> RendezvousHashing<N, S> {
+        RendezvousHashing { nodes: Vec::new(), hash_builder }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    fn score<K: Hash + ?Sized>(&self, node: &N, key: &K) -> u64 {
+        mix(self.hash_builder.hash_one((node, key)))
+    }
+}
+
+impl<N: Hash + Eq, S: BuildHasher> NodeLocator<N> for RendezvousHashing<N, S> {
+
+    fn add_node(&mut self, node: N) -> bool {
+        if self.nodes.contains(&node) {
+            return false;
+        }
+        self.nodes.push(node);
+        true
+    }
+
+    fn remove_node(&mut self, node: &N) -> bool {
+        match self.nodes.iter().position(|n| n == node) {
+            Some(index) => {
+                self.nodes.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn nodes(&self) -> &[N] {
+        &self.nodes
+    }
+
+    fn locate_replicas<K: Hash + ?Sized>(&self, key: &K, replicas: usize) -> Vec<&N> {
+        let mut ranked: Vec<(u64, &N)> = self.nodes.iter().map(|node| (self.score(node, key), node)).collect();
+        ranked.sort_unstable_by_key(|&(score, _)| Reverse(score));
+        ranked.into_iter().take(replicas.saturating_add(1)).map(|(_, node)| node).collect()
+    }
+
+    fn locate<K: Hash + ?Sized>(&self, key: &K) -> Option<&N> {
+        self.nodes.iter().max_by_key(|node| self.score(*node, key))
+    }
+}
+
+#[cfg(test)]
+mod test_sharding {
+    use std::hash::BuildHasher;
+    use super::{NodeLocator, ConsistentHashRing, RendezvousHashing};
+    use crate::hasher::{FnvBuildHasher, FxBuildHasher, DJBBuildHasher};
+    use crate::{RSBuildHasher, RandomSipState};
+
+    const KEYS: usize = 20_000;
+
+    fn node_names(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("cache-{}.example.com:11211", i)).collect()
+    }
+
+    fn owners<L: NodeLocator<String>>(locator: &L) -> Vec<String> {
+        (0..KEYS).map(|k| locator.locate(&format!("key-{}", k)).unwrap().clone()).collect()
+    }
+
+    /// Adds an 11th node to 10 and checks only keys taken over by it move,
+    /// then removes another one and checks only its keys move. Returns the
+    /// shares of keys moved.
+    fn check_movement<L: NodeLocator<String>>(mut locator: L) -> (f64, f64) {
+        let names = node_names(11);
+        for name in &names[..10] {
+            assert!(locator.add_node(name.clone()));
+        }
+        let before = owners(&locator);
+
+        assert!(locator.add_node(names[10].clone()));
+        let after_add = owners(&locator);
+        let mut moved = 0;
+        for (old, new) in before.iter().zip(&after_add) {
+            if old != new {
+                assert_eq!(new, &names[10]);
+                moved += 1;
+            }
+        }
+        let added_share = moved as f64 / KEYS as f64;
+
+        assert!(locator.remove_node(&names[3]));
+        let after_remove = owners(&locator);
+        let mut moved = 0;
+        for (old, new) in after_add.iter().zip(&after_remove) {
+            if old != new {
+                assert_eq!(old, &names[3]);
+                moved += 1;
+            }
+        }
+        (added_share, moved as f64 / KEYS as f64)
+    }
+
+    fn check_movement_with<S: BuildHasher + Clone>(hash_builder: S) {
+        let ring = ConsistentHashRing::with_virtual_nodes_and_hasher(160, hash_builder.clone());
+        let (added, removed) = check_movement(ring);
+        // ideally 1/11 and 1/11
+        assert!(added > 0.04 && added < 0.15, "ring moved {} on add", added);
+        assert!(removed > 0.04 && removed < 0.15, "ring moved {} on remove", removed);
+
+        let (added, removed) = check_movement(RendezvousHashing::with_hasher(hash_builder));
+        assert!(added > 0.07 && added < 0.11, "rendezvous moved {} on add", added);
+        assert!(removed > 0.07 && removed < 0.11, "rendezvous moved {} on remove", removed);
+    }
+
+    #[test]
+    fn test_key_movement() {
+        check_movement_with(FnvBuildHasher::default());
+        check_movement_with(FxBuildHasher::default());
+        check_movement_with(RSBuildHasher::default());
+        check_movement_with(DJBBuildHasher::default());
+        check_movement_with(RandomSipState::with_keys(1, 2));
+    }
+
+    #[test]
+    fn test_modulo_sharding_moves_most_keys() {
+        // the baseline the locators improve on
+        let hash_builder = FnvBuildHasher::default();
+        let moved = (0..KEYS)
+            .map(|k| hash_builder.hash_one(format!("key-{}", k)))
+            .filter(|hash| hash % 10 != hash % 11)
+            .count();
+        assert!(moved as f64 / KEYS as f64 > 0.85);
+    }
+
+    fn check_balance<L: NodeLocator<String>>(mut locator: L, tolerance: f64) {
+        for name in node_names(10) {
+            locator.add_node(name);
+        }
+        let owners = owners(&locator);
+        for name in locator.nodes() {
+            let share = owners.iter().filter(|owner| *owner == name).count() as f64 / KEYS as f64;
+            assert!((share - 0.1).abs() < tolerance, "{} owns {}", name, share);
+        }
+    }
+
+    #[test]
+    fn test_balance() {
+        check_balance(ConsistentHashRing::new(), 0.04);
+        check_balance(RendezvousHashing::new(), 0.01);
+    }
+
+    fn check_replicas<L: NodeLocator<String>>(mut locator: L) {
+        assert_eq!(locator.locate("key"), None);
+        assert!(locator.locate_replicas("key", 2).is_empty());
+
+        for name in node_names(5) {
+            locator.add_node(name);
+        }
+        assert!(!locator.add_node(node_names(1).remove(0)));
+        assert_eq!(locator.nodes().len(), 5);
+
+        for k in 0..100 {
+            let key = format!("key-{}", k);
+            let all = locator.locate_replicas(&key, 10);
+            assert_eq!(all.len(), 5);
+            for (i, node) in all.iter().enumerate() {
+                assert!(!all[..i].contains(node));
+            }
+            assert_eq!(locator.locate_replicas(&key, 2), &all[..3]);
+            assert_eq!(locator.locate(&key), Some(all[0]));
+        }
+        assert!(!locator.remove_node(&"unknown".to_string()));
+    }
+
+    #[test]
+    fn test_replicas() {
+        check_replicas(ConsistentHashRing::new());
+        check_replicas(ConsistentHashRing::with_virtual_nodes(0));
+        check_replicas(RendezvousHashing::new());
+    }
+
+    #[test]
+    fn test_replicas_survive_primary_loss() {
+        let mut ring = ConsistentHashRing::new();
+        for name in node_names(6) {
+            ring.add_node(name);
+        }
+        let replicas: Vec<String> = ring.locate_replicas("user:42", 2).into_iter().cloned().collect();
+        ring.remove_node(&replicas[0]);
+        assert_eq!(ring.locate("user:42"), Some(&replicas[1]));
+    }
+}
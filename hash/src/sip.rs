@@ -0,0 +1,437 @@
+//! Keyed SipHash, for maps whose keys come from untrusted input.
+//!
+//! The weak `Simple*Hasher`s let anyone who controls the keys choose
+//! colliding ones and turn every lookup into a full scan. SipHash is a PRF
+//! keyed with 128 secret bits, so collisions can not be predicted without
+//! the key. `RandomSipState` draws a fresh key for every map.
+//!
+//! Refer to https://www.aumasson.jp/siphash/siphash.pdf
+
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Debug, Formatter};
+use std::hash::{BuildHasher, Hasher};
+
+/// SipHash-c-d with `C` compression rounds and `D` finalization rounds.
+///
+/// `Debug` shows no state, every word of it is derived from the key.
+#[derive(Clone)]
+pub struct SipHasher<const C: usize, const D: usize> {
+    k0: u64,
+    k1: u64,
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    // bytes not yet compressed, in the low `ntail` bytes
+    tail: u64,
+    ntail: usize,
+    length: usize,
+}
+
+/// SipHash-1-3, the faster variant used by std's `HashMap`.
+pub type SipHasher13 = SipHasher<1, 3>;
+
+/// SipHash-2-4, the variant recommended by the paper.
+pub type SipHasher24 = SipHasher<2, 4>;
+
+impl<const C: usize, const D: usize> SipHasher<C, D> {
+
+    pub fn new_with_keys(k0: u64, k1: u64) -> SipHasher<C, D> {
+        let mut hasher = SipHasher {
+            k0,
+            k1,
+            v0: 0,
+            v1: 0,
+            v2: 0,
+            v3: 0,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        };
+        hasher.reset();
+        hasher
+    }
+
+    /// Creates a hasher with a 16 byte key, read as two little endian words.
+    pub fn new_with_key(key: &[u8; 16]) -> SipHasher<C, D> {
+        let mut k0 = [0u8; 8];
+        let mut k1 = [0u8; 8];
+        k0.copy_from_slice(&key[..8]);
+        k1.copy_from_slice(&key[8..]);
+        SipHasher::new_with_keys(u64::from_le_bytes(k0), u64::from_le_bytes(k1))
+    }
+
+    /// Restarts hashing with the same key.
+    pub fn reset(&mut self) {
+        self.v0 = self.k0 ^ 0x736f_6d65_7073_6575;
+        self.v1 = self.k1 ^ 0x646f_7261_6e64_6f6d;
+        self.v2 = self.k0 ^ 0x6c79_6765_6e65_7261;
+        self.v3 = self.k1 ^ 0x7465_6462_7974_6573;
+        self.tail = 0;
+        self.ntail = 0;
+        self.length = 0;
+    }
+
+    #[inline]
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13);
+        self.v1 ^= self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16);
+        self.v3 ^= self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21);
+        self.v3 ^= self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17);
+        self.v1 ^= self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    #[inline]
+    fn compress(&mut self, m: u64) {
+        self.v3 ^= m;
+        for _ in 0..C {
+            self.round();
+        }
+        self.v0 ^= m;
+    }
+}
+
+impl<const C: usize, const D: usize> Hasher for SipHasher<C, D> {
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.length = self.length.wrapping_add(bytes.len());
+        let mut bytes = bytes;
+
+        // fill up the tail left by the previous call first
+        if self.ntail != 0 {
+            let needed = 8 - self.ntail;
+            let fill = needed.min(bytes.len());
+            for (i, byte) in bytes[..fill].iter().enumerate() {
+                self.tail |= (*byte as u64) << (8 * (self.ntail + i));
+            }
+            self.ntail += fill;
+            bytes = &bytes[fill..];
+            if self.ntail < 8 {
+                return;
+            }
+            let m = self.tail;
+            self.compress(m);
+            self.tail = 0;
+            self.ntail = 0;
+        }
+
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(chunk);
+            self.compress(u64::from_le_bytes(word));
+        }
+
+        for (i, byte) in chunks.remainder().iter().enumerate() {
+            self.tail |= (*byte as u64) << (8 * i);
+        }
+        self.ntail = chunks.remainder().len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+
+        let b = ((self.length as u64 & 0xff) << 56) | self.tail;
+        state.compress(b);
+
+        state.v2 ^= 0xff;
+        for _ in 0..D {
+            state.round();
+        }
+
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+/// Builds `SipHasher13`s with a random key drawn once per builder, so two
+/// maps never share a key.
+///
+/// `Debug` hides the key, as std's `RandomState` does, so that logging a
+/// map's hasher does not give the key away.
+#[derive(Clone)]
+pub struct RandomSipState {
+    k0: u64,
+    k1: u64,
+}
+
+impl RandomSipState {
+
+    pub fn new() -> RandomSipState {
+        // std's RandomState is seeded from the OS and hands out a new key for
+        // every instance, use it as the entropy source
+        let entropy = RandomState::new();
+        RandomSipState {
+            k0: entropy.hash_one(0u8),
+            k1: entropy.hash_one(1u8),
+        }
+    }
+
+    pub fn with_keys(k0: u64, k1: u64) -> RandomSipState {
+        RandomSipState { k0, k1 }
+    }
+}
+
+impl<const C: usize, const D: usize> Debug for SipHasher<C, D> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("SipHasher").finish_non_exhaustive()
+    }
+}
+
+impl Debug for RandomSipState {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("RandomSipState").finish_non_exhaustive()
+    }
+}
+
+impl Default for RandomSipState {
+    fn default() -> RandomSipState {
+        RandomSipState::new()
+    }
+}
+
+impl BuildHasher for RandomSipState {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+#[cfg(test)]
+mod test_sip {
+    use super::{SipHasher13, SipHasher24, RandomSipState};
+    use std::hash::{BuildHasher, Hasher};
+
+    // Reference vectors: key is 00 01 .. 0f, message i is 00 01 .. (i-1).
+    // The 2-4 vectors are the ones shipped with the reference implementation,
+    // read as little endian words.
+    const VECTORS_24: [u64; 64] = [
+        0x726fdb47dd0e0e31,
+        0x74f839c593dc67fd,
+        0x0d6c8009d9a94f5a,
+        0x85676696d7fb7e2d,
+        0xcf2794e0277187b7,
+        0x18765564cd99a68d,
+        0xcbc9466e58fee3ce,
+        0xab0200f58b01d137,
+        0x93f5f5799a932462,
+        0x9e0082df0ba9e4b0,
+        0x7a5dbbc594ddb9f3,
+        0xf4b32f46226bada7,
+        0x751e8fbc860ee5fb,
+        0x14ea5627c0843d90,
+        0xf723ca908e7af2ee,
+        0xa129ca6149be45e5,
+        0x3f2acc7f57c29bdb,
+        0x699ae9f52cbe4794,
+        0x4bc1b3f0968dd39c,
+        0xbb6dc91da77961bd,
+        0xbed65cf21aa2ee98,
+        0xd0f2cbb02e3b67c7,
+        0x93536795e3a33e88,
+        0xa80c038ccd5ccec8,
+        0xb8ad50c6f649af94,
+        0xbce192de8a85b8ea,
+        0x17d835b85bbb15f3,
+        0x2f2e6163076bcfad,
+        0xde4daaaca71dc9a5,
+        0xa6a2506687956571,
+        0xad87a3535c49ef28,
+        0x32d892fad841c342,
+        0x7127512f72f27cce,
+        0xa7f32346f95978e3,
+        0x12e0b01abb051238,
+        0x15e034d40fa197ae,
+        0x314dffbe0815a3b4,
+        0x027990f029623981,
+        0xcadcd4e59ef40c4d,
+        0x9abfd8766a33735c,
+        0x0e3ea96b5304a7d0,
+        0xad0c42d6fc585992,
+        0x187306c89bc215a9,
+        0xd4a60abcf3792b95,
+        0xf935451de4f21df2,
+        0xa9538f0419755787,
+        0xdb9acddff56ca510,
+        0xd06c98cd5c0975eb,
+        0xe612a3cb9ecba951,
+        0xc766e62cfcadaf96,
+        0xee64435a9752fe72,
+        0xa192d576b245165a,
+        0x0a8787bf8ecb74b2,
+        0x81b3e73d20b49b6f,
+        0x7fa8220ba3b2ecea,
+        0x245731c13ca42499,
+        0xb78dbfaf3a8d83bd,
+        0xea1ad565322a1a0b,
+        0x60e61c23a3795013,
+        0x6606d7e446282b93,
+        0x6ca4ecb15c5f91e1,
+        0x9f626da15c9625f3,
+        0xe51b38608ef25f57,
+        0x958a324ceb064572,
+    ];
+
+    const VECTORS_13: [u64; 64] = [
+        0xabac0158050fc4dc,
+        0xc9f49bf37d57ca93,
+        0x82cb9b024dc7d44d,
+        0x8bf80ab8e7ddf7fb,
+        0xcf75576088d38328,
+        0xdef9d52f49533b67,
+        0xc50d2b50c59f22a7,
+        0xd3927d989bb11140,
+        0x369095118d299a8e,
+        0x25a48eb36c063de4,
+        0x79de85ee92ff097f,
+        0x70c118c1f94dc352,
+        0x78a384b157b4d9a2,
+        0x306f760c1229ffa7,
+        0x605aa111c0f95d34,
+        0xd320d86d2a519956,
+        0xcc4fdd1a7d908b66,
+        0x9cf2689063dbd80c,
+        0x8ffc389cb473e63e,
+        0xf21f9de58d297d1c,
+        0xc0dc2f46a6cce040,
+        0xb992abfe2b45f844,
+        0x7ffe7b9ba320872e,
+        0x525a0e7fdae6c123,
+        0xf464aeb267349c8c,
+        0x45cd5928705b0979,
+        0x3a3e35e3ca9913a5,
+        0xa91dc74e4ade3b35,
+        0xfb0bed02ef6cd00d,
+        0x88d93cb44ab1e1f4,
+        0x540f11d643c5e663,
+        0x2370dd1f8c21d1bc,
+        0x81157b6c16a7b60d,
+        0x4d54b9e57a8ff9bf,
+        0x759f12781f2a753e,
+        0xcea1a3bebf186b91,
+        0x2cf508d3ada26206,
+        0xb6101c2da3c33057,
+        0xb3f47496ae3a36a1,
+        0x626b57547b108392,
+        0xc1d2363299e41531,
+        0x667cc1923f1ad944,
+        0x65704ffec8138825,
+        0x24f280d1c28949a6,
+        0xc2ca1cedfaf8876b,
+        0xc2164bfc9f042196,
+        0xa16e9c9368b1d623,
+        0x49fb169c8b5114fd,
+        0x9f3143f8df074c46,
+        0xc6fdaf2412cc86b3,
+        0x7eaf49d10a52098f,
+        0x1cf313559d292f9a,
+        0xc44a30dda2f41f12,
+        0x36fae98943a71ed0,
+        0x318fb34c73f0bce6,
+        0xa27abf3670a7e980,
+        0xb4bcc0db243c6d75,
+        0x23f8d852fdb71513,
+        0x8f035f4da67d8a08,
+        0xd89cd0e5b7e8f148,
+        0xf6f4e6bcf7a644ee,
+        0xaec59ad80f1837f2,
+        0xc3b2f6154b6694e0,
+        0x9d199062b7bbb3a8,
+    ];
+
+    fn reference_key() -> [u8; 16] {
+        let mut key = [0u8; 16];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        key
+    }
+
+    fn reference_message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn test_siphash_2_4_vectors() {
+        let key = reference_key();
+        for (len, expected) in VECTORS_24.iter().enumerate() {
+            let mut hasher = SipHasher24::new_with_key(&key);
+            hasher.write(&reference_message(len));
+            assert_eq!(hasher.finish(), *expected, "message length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_siphash_1_3_vectors() {
+        let key = reference_key();
+        for (len, expected) in VECTORS_13.iter().enumerate() {
+            let mut hasher = SipHasher13::new_with_key(&key);
+            hasher.write(&reference_message(len));
+            assert_eq!(hasher.finish(), *expected, "message length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_paper_example() {
+        let mut hasher = SipHasher24::new_with_key(&reference_key());
+        hasher.write(&reference_message(15));
+        assert_eq!(hasher.finish(), 0xa129_ca61_49be_45e5);
+    }
+
+    #[test]
+    fn test_streaming_writes() {
+        let key = reference_key();
+        let message = reference_message(63);
+        for split in 0..message.len() {
+            let mut hasher = SipHasher24::new_with_key(&key);
+            let (first, rest) = message.split_at(split);
+            hasher.write(first);
+            for byte in rest {
+                hasher.write(&[*byte]);
+            }
+            assert_eq!(hasher.finish(), VECTORS_24[63], "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut hasher = SipHasher24::new_with_key(&reference_key());
+        hasher.write(b"something else");
+        hasher.reset();
+        hasher.write(&reference_message(8));
+        assert_eq!(hasher.finish(), VECTORS_24[8]);
+    }
+
+    #[test]
+    fn test_random_state_keys() {
+        let a = RandomSipState::new();
+        let b = RandomSipState::new();
+        assert_ne!(a.hash_one("key"), b.hash_one("key"));
+        assert_eq!(a.hash_one("key"), a.clone().hash_one("key"));
+
+        let fixed = RandomSipState::with_keys(1, 2);
+        let mut hasher = SipHasher13::new_with_keys(1, 2);
+        hasher.write_u64(42);
+        assert_eq!(fixed.hash_one(42u64), hasher.finish());
+    }
+
+    #[test]
+    fn test_debug_hides_keys() {
+        let state = RandomSipState::with_keys(0x1234_5678_9abc_def0, 0x0fed_cba9_8765_4321);
+        let hasher = SipHasher24::new_with_keys(0x1234_5678_9abc_def0, 0x0fed_cba9_8765_4321);
+        for text in [format!("{:?}", state), format!("{:?}", hasher)] {
+            assert!(!text.contains("k0") && !text.contains("v0"), "{}", text);
+            assert!(!text.contains(&0x1234_5678_9abc_def0u64.to_string()), "{}", text);
+        }
+        assert_eq!(format!("{:?}", state), "RandomSipState { .. }");
+    }
+}
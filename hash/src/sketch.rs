@@ -0,0 +1,556 @@
+//! Streaming estimators: the number of distinct items with `HyperLogLog`,
+//! and the count of every item with `CountMinSketch`, in fixed memory.
+
+use std::cmp::Reverse;
+use std::hash::{Hash, BuildHasher};
+use std::marker::PhantomData;
+
+use super::SimpleHashMap;
+use super::bloom::{FilterError, indexes};
+use super::hasher::{FnvBuildHasher, mix};
+
+const MIN_PRECISION: u8 = 4;
+const MAX_PRECISION: u8 = 18;
+
+enum Registers {
+    /// Nonzero registers as `index << 8 | value`, sorted.
+    Sparse(Vec<u32>),
+    Dense(Vec<u8>),
+}
+
+/// HyperLogLog cardinality estimator with `2^precision` registers.
+///
+/// Every item hashes to a register, which keeps the longest run of leading
+/// zeros seen among the rest of the hash bits. The standard error of the
+/// estimate is `1.04 / sqrt(2^precision)`, 1.6% for a precision of 12.
+///
+/// Until a quarter of the registers is set, only those are stored, in a
+/// sorted list taking at most as many bytes as the dense registers. Small
+/// cardinalities are estimated by linear counting of empty registers.
+///
+/// Refer to http://algo.inria.fr/flajolet/Publications/FlFuGaMe07.pdf
+pub struct HyperLogLog<T: ?Sized, S = FnvBuildHasher> {
+    precision: u8,
+    registers: Registers,
+    hash_builder: S,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash + ?Sized> HyperLogLog<T, FnvBuildHasher> {
+
+    /// # Panics
+    ///
+    /// Panics if `precision` is not between 4 and 18.
+    pub fn new(precision: u8) -> HyperLogLog<T, FnvBuildHasher> {
+        HyperLogLog::with_hasher(precision, Default::default())
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> HyperLogLog<T, S> {
+
+    pub fn with_hasher(precision: u8, hash_builder: S) -> HyperLogLog<T, S> {
+        assert!((MIN_PRECISION..=MAX_PRECISION).contains(&precision),
+                "precision must be between {} and {}, got {}", MIN_PRECISION, MAX_PRECISION, precision);
+        HyperLogLog { precision, registers: Registers::Sparse(Vec::new()), hash_builder, marker: PhantomData }
+    }
+
+    pub fn precision(&self) -> u8 { self.precision }
+
+    pub fn hasher(&self) -> &S { &self.hash_builder }
+
+    fn num_registers(&self) -> usize {
+        1 << self.precision
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.registers, Registers::Sparse(_))
+    }
+
+    pub fn clear(&mut self) {
+        self.registers = Registers::Sparse(Vec::new());
+    }
+
+    pub fn insert(&mut self, item: &T) {
+        let hash = mix(self.hash_builder.hash_one(item));
+        let index = (hash >> (64 - self.precision)) as usize;
+        // the marker bit bounds the run when all remaining bits are zero
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        self.update(index, rest.leading_zeros() as u8 + 1);
+    }
+
+    fn update(&mut self, index: usize, value: u8) {
+        let limit = self.num_registers() / 4;
+        match &mut self.registers {
+            Registers::Dense(registers) => {
+                registers[index] = registers[index].max(value);
+                return;
+            }
+            Registers::Sparse(entries) => {
+                match entries.binary_search_by_key(&index, |entry| (*entry >> 8) as usize) {
+                    Ok(i) => {
+                        if (entries[i] & 0xff) < value as u32 {
+                            entries[i] = (index as u32) << 8 | value as u32;
+                        }
+                        return;
+                    }
+                    Err(i) if entries.len() < limit => {
+                        entries.insert(i, (index as u32) << 8 | value as u32);
+                        return;
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+        self.densify();
+        self.update(index, value);
+    }
+
+    fn densify(&mut self) {
+        if let Registers::Sparse(entries) = &self.registers {
+            let mut registers = vec![0; self.num_registers()];
+            for entry in entries {
+                registers[(entry >> 8) as usize] = (entry & 0xff) as u8;
+            }
+            self.registers = Registers::Dense(registers);
+        }
+    }
+
+    /// Estimates the number of distinct items inserted.
+    pub fn estimate(&self) -> f64 {
+        let m = self.num_registers();
+        let (sum, zeros) = match &self.registers {
+            Registers::Sparse(entries) => {
+                let set: f64 = entries.iter().map(|entry| 0.5f64.powi((entry & 0xff) as i32)).sum();
+                (set + (m - entries.len()) as f64, m - entries.len())
+            }
+            Registers::Dense(registers) => {
+                let sum = registers.iter().map(|r| 0.5f64.powi(*r as i32)).sum();
+                (sum, registers.iter().filter(|r| **r == 0).count())
+            }
+        };
+        let m = m as f64;
+        let alpha = match self.num_registers() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let raw = alpha * m * m / sum;
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+
+    /// Adds the registers of `other`, afterwards `self` estimates the
+    /// distinct items inserted into either.
+    pub fn merge(&mut self, other: &HyperLogLog<T, S>) -> Result<(), FilterError> {
+        if self.precision != other.precision {
+            return Err(FilterError::Incompatible);
+        }
+        match &other.registers {
+            Registers::Sparse(entries) => {
+                for entry in entries {
+                    self.update((entry >> 8) as usize, (entry & 0xff) as u8);
+                }
+            }
+            Registers::Dense(other_registers) => {
+                self.densify();
+                if let Registers::Dense(registers) = &mut self.registers {
+                    registers.iter_mut().zip(other_registers).for_each(|(a, b)| *a = (*a).max(*b));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Count-Min sketch: `depth` rows of `width` counters, every item adds its
+/// count to one counter per row and the smallest of those is its estimate.
+///
+/// Estimates never fall below the true count. With a width of `e / epsilon`
+/// and a depth of `ln(1 / delta)` they exceed it by more than `epsilon`
+/// times the total count with a probability of at most `delta`.
+///
+/// With conservative update, an insertion only raises the counters which
+/// are below the new estimate, which tightens estimates considerably, but
+/// counts can not be subtracted any more.
+///
+/// Refer to http://dimacs.rutgers.edu/~graham/pubs/papers/cm-full.pdf
+pub struct CountMinSketch<T: ?Sized, S = FnvBuildHasher> {
+    counters: Vec<u64>,
+    width: usize,
+    depth: usize,
+    total: u64,
+    conservative: bool,
+    hash_builder: S,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash + ?Sized> CountMinSketch<T, FnvBuildHasher> {
+
+    /// Creates a sketch overestimating by at most `epsilon` times the total
+    /// count, except with probability `delta`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `epsilon` or `delta` is not between 0 and 1.
+    pub fn new(epsilon: f64, delta: f64) -> CountMinSketch<T, FnvBuildHasher> {
+        CountMinSketch::with_hasher(epsilon, delta, Default::default())
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> CountMinSketch<T, S> {
+
+    pub fn with_hasher(epsilon: f64, delta: f64, hash_builder: S) -> CountMinSketch<T, S> {
+        assert!(epsilon > 0.0 && epsilon < 1.0, "epsilon must be between 0 and 1, got {}", epsilon);
+        assert!(delta > 0.0 && delta < 1.0, "delta must be between 0 and 1, got {}", delta);
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil() as usize;
+        CountMinSketch::with_size_and_hasher(width, depth, hash_builder)
+    }
+
+    /// Creates a sketch of `depth` rows of `width` counters, both at least 1.
+    pub fn with_size_and_hasher(width: usize, depth: usize, hash_builder: S) -> CountMinSketch<T, S> {
+        let (width, depth) = (width.max(1), depth.max(1));
+        CountMinSketch {
+            counters: vec![0; width.checked_mul(depth).expect("capacity overflow")],
+            width,
+            depth,
+            total: 0,
+            conservative: false,
+            hash_builder,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn width(&self) -> usize { self.width }
+
+    pub fn depth(&self) -> usize { self.depth }
+
+    pub fn hasher(&self) -> &S { &self.hash_builder }
+
+    /// Returns the sum of all counts added.
+    pub fn total(&self) -> u64 { self.total }
+
+    pub fn conservative_update(&self) -> bool { self.conservative }
+
+    pub fn set_conservative_update(&mut self, conservative: bool) {
+        self.conservative = conservative;
+    }
+
+    pub fn clear(&mut self) {
+        self.counters.iter_mut().for_each(|c| *c = 0);
+        self.total = 0;
+    }
+
+    /// Counter of every row for `item`.
+    fn cells(&self, item: &T) -> impl Iterator<Item = usize> {
+        let width = self.width;
+        indexes(self.hash_builder.hash_one(item), self.depth as u32, width)
+            .enumerate()
+            .map(move |(row, column)| row * width + column)
+    }
+
+    pub fn insert(&mut self, item: &T) {
+        self.add(item, 1);
+    }
+
+    pub fn add(&mut self, item: &T, count: u64) {
+        self.total = self.total.saturating_add(count);
+        if self.conservative {
+            let estimate = self.estimate(item).saturating_add(count);
+            for cell in self.cells(item) {
+                self.counters[cell] = self.counters[cell].max(estimate);
+            }
+        } else {
+            for cell in self.cells(item) {
+                self.counters[cell] = self.counters[cell].saturating_add(count);
+            }
+        }
+    }
+
+    /// Returns an upper bound of the count of `item`.
+    pub fn estimate(&self, item: &T) -> u64 {
+        self.cells(item).map(|cell| self.counters[cell]).min().unwrap_or(0)
+    }
+
+    /// Adds the counts of `other`, which must use the same hasher.
+    pub fn merge(&mut self, other: &CountMinSketch<T, S>) -> Result<(), FilterError> {
+        if self.width != other.width || self.depth != other.depth {
+            return Err(FilterError::Incompatible);
+        }
+        self.counters.iter_mut().zip(&other.counters).for_each(|(a, b)| *a = a.saturating_add(*b));
+        self.total = self.total.saturating_add(other.total);
+        Ok(())
+    }
+}
+
+/// The `k` items with the highest estimated counts of a `CountMinSketch`.
+///
+/// Candidates are kept in a `SimpleHashMap` with their estimate. An item
+/// which is not a candidate replaces the one with the lowest estimate once
+/// its own estimate is higher.
+pub struct TopK<T, S = FnvBuildHasher> {
+    sketch: CountMinSketch<T, S>,
+    k: usize,
+    candidates: SimpleHashMap<T, u64>,
+}
+
+impl<T: Hash + Eq + Clone> TopK<T, FnvBuildHasher> {
+
+    /// Tracks `k` items over a conservative `CountMinSketch::new(epsilon, delta)`.
+    pub fn new(k: usize, epsilon: f64, delta: f64) -> TopK<T, FnvBuildHasher> {
+        let mut sketch = CountMinSketch::new(epsilon, delta);
+        sketch.set_conservative_update(true);
+        TopK::with_sketch(k, sketch)
+    }
+}
+
+impl<T: Hash + Eq + Clone, S: BuildHasher> TopK<T, S> {
+
+    pub fn with_sketch(k: usize, sketch: CountMinSketch<T, S>) -> TopK<T, S> {
+        TopK { sketch, k, candidates: SimpleHashMap::with_capacity(k) }
+    }
+
+    pub fn sketch(&self) -> &CountMinSketch<T, S> {
+        &self.sketch
+    }
+
+    pub fn insert(&mut self, item: &T) {
+        self.add(item, 1);
+    }
+
+    pub fn add(&mut self, item: &T, count: u64) {
+        self.sketch.add(item, count);
+        let estimate = self.sketch.estimate(item);
+        if self.candidates.contains_key(item) || self.candidates.len() < self.k {
+            self.candidates.insert(item.clone(), estimate);
+            return;
+        }
+        let lowest = self.candidates.iter().min_by_key(|(_, estimate)| **estimate).map(|(k, e)| (k.clone(), *e));
+        if let Some((lowest, lowest_estimate)) = lowest {
+            if estimate > lowest_estimate {
+                self.candidates.remove(&lowest);
+                self.candidates.insert(item.clone(), estimate);
+            }
+        }
+    }
+
+    /// Returns the candidates with their estimated counts, highest first.
+    pub fn top(&self) -> Vec<(&T, u64)> {
+        let mut top: Vec<_> = self.candidates.iter().map(|(item, estimate)| (item, *estimate)).collect();
+        top.sort_by_key(|&(_, estimate)| Reverse(estimate));
+        top
+    }
+}
+
+#[cfg(test)]
+mod test_sketch {
+    use std::hash::BuildHasher;
+    use super::{HyperLogLog, CountMinSketch, TopK};
+    use crate::SimpleHashMap;
+    use crate::bloom::FilterError;
+    use crate::hasher::{FnvBuildHasher, FxBuildHasher, xorshift};
+    use crate::RSBuildHasher;
+
+    /// Stream of `len` draws from `distinct` values, and the exact count of
+    /// every value.
+    fn stream(len: usize, distinct: u64, seed: u64) -> (Vec<u64>, SimpleHashMap<u64, u64>) {
+        let mut state = seed;
+        let items: Vec<u64> = (0..len).map(|_| xorshift(&mut state) % distinct).collect();
+        let mut counts = SimpleHashMap::new();
+        for item in &items {
+            *counts.entry(*item).or_insert(0) += 1;
+        }
+        (items, counts)
+    }
+
+    fn check_hyper_log_log<S: BuildHasher + Clone>(hash_builder: S) {
+        for &precision in &[10u8, 12, 14] {
+            for &distinct in &[10u64, 1000, 20_000, 200_000] {
+                let (items, counts) = stream(200_000, distinct, distinct + precision as u64);
+                let mut hll = HyperLogLog::with_hasher(precision, hash_builder.clone());
+                for item in &items {
+                    hll.insert(item);
+                }
+                let exact = counts.len() as f64;
+                let error = (hll.estimate() - exact).abs() / exact;
+                let standard_error = 1.04 / ((1u64 << precision) as f64).sqrt();
+                assert!(error < 4.0 * standard_error,
+                        "precision {} distinct {}: estimate {} error {}", precision, exact, hll.estimate(), error);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hyper_log_log_error() {
+        check_hyper_log_log(FnvBuildHasher::default());
+        check_hyper_log_log(FxBuildHasher::default());
+        check_hyper_log_log(RSBuildHasher::default());
+    }
+
+    #[test]
+    fn test_hyper_log_log_sparse() {
+        let mut hll = HyperLogLog::new(12);
+        assert_eq!(hll.estimate(), 0.0);
+        for i in 0..100u32 {
+            hll.insert(&i);
+            hll.insert(&i);
+        }
+        assert!(hll.is_sparse());
+        assert!((hll.estimate() - 100.0).abs() < 3.0, "{}", hll.estimate());
+
+        let sparse = hll.estimate();
+        hll.densify();
+        assert!(!hll.is_sparse());
+        assert_eq!(hll.estimate(), sparse);
+
+        for i in 0..10_000u32 {
+            hll.insert(&i);
+        }
+        hll.clear();
+        assert!(hll.is_sparse());
+        assert_eq!(hll.estimate(), 0.0);
+    }
+
+    #[test]
+    fn test_hyper_log_log_goes_dense() {
+        let mut hll = HyperLogLog::new(8);
+        for i in 0..1000u32 {
+            hll.insert(&i);
+        }
+        assert!(!hll.is_sparse());
+    }
+
+    #[test]
+    fn test_hyper_log_log_merge() {
+        let mut a = HyperLogLog::new(12);
+        let mut b = HyperLogLog::new(12);
+        let mut small = HyperLogLog::new(12);
+        for i in 0..30_000u32 {
+            a.insert(&i);
+        }
+        for i in 20_000..50_000u32 {
+            b.insert(&i);
+        }
+        for i in 49_990..50_010u32 {
+            small.insert(&i);
+        }
+
+        a.merge(&b).unwrap();
+        assert!((a.estimate() - 50_000.0).abs() / 50_000.0 < 0.065, "{}", a.estimate());
+
+        // dense into sparse and sparse into dense
+        let mut c = HyperLogLog::new(12);
+        c.merge(&small).unwrap();
+        c.merge(&b).unwrap();
+        b.merge(&small).unwrap();
+        assert_eq!(b.estimate(), c.estimate());
+
+        assert_eq!(a.merge(&HyperLogLog::new(10)), Err(FilterError::Incompatible));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_hyper_log_log_invalid_precision() {
+        HyperLogLog::<u32>::new(30);
+    }
+
+    /// Every estimate is at least exact, and at most `delta` of them exceed
+    /// it by more than `epsilon` times the total.
+    fn check_count_min<S: BuildHasher>(sketch: &mut CountMinSketch<u64, S>, epsilon: f64, delta: f64) {
+        let (items, counts) = stream(100_000, 10_000, 7);
+        for item in &items {
+            sketch.insert(item);
+        }
+        assert_eq!(sketch.total(), 100_000);
+        let bound = (epsilon * 100_000.0) as u64;
+        let mut exceeded = 0;
+        for (item, count) in &counts {
+            let estimate = sketch.estimate(item);
+            assert!(estimate >= *count);
+            if estimate - count > bound {
+                exceeded += 1;
+            }
+        }
+        assert!((exceeded as f64) <= delta * counts.len() as f64, "{} of {}", exceeded, counts.len());
+    }
+
+    #[test]
+    fn test_count_min_error() {
+        let mut sketch = CountMinSketch::new(0.001, 0.01);
+        assert_eq!((sketch.width(), sketch.depth()), (2719, 5));
+        check_count_min(&mut sketch, 0.001, 0.01);
+
+        let mut sketch = CountMinSketch::with_hasher(0.001, 0.01, RSBuildHasher::default());
+        check_count_min(&mut sketch, 0.001, 0.01);
+    }
+
+    #[test]
+    fn test_count_min_conservative_update() {
+        let (items, counts) = stream(100_000, 10_000, 11);
+        let mut plain = CountMinSketch::new(0.005, 0.01);
+        let mut conservative = CountMinSketch::new(0.005, 0.01);
+        conservative.set_conservative_update(true);
+        for item in &items {
+            plain.insert(item);
+            conservative.insert(item);
+        }
+        let mut plain_error = 0;
+        let mut conservative_error = 0;
+        for (item, count) in &counts {
+            assert!(conservative.estimate(item) >= *count);
+            assert!(conservative.estimate(item) <= plain.estimate(item));
+            plain_error += plain.estimate(item) - count;
+            conservative_error += conservative.estimate(item) - count;
+        }
+        assert!(conservative_error * 2 < plain_error, "{} {}", conservative_error, plain_error);
+    }
+
+    #[test]
+    fn test_count_min_merge() {
+        let mut a = CountMinSketch::new(0.01, 0.01);
+        let mut b = CountMinSketch::new(0.01, 0.01);
+        a.add("x", 5);
+        b.add("x", 7);
+        b.add("y", 1);
+        a.merge(&b).unwrap();
+        assert_eq!(a.total(), 13);
+        assert!(a.estimate("x") >= 12);
+        assert!(a.estimate("y") >= 1);
+        assert_eq!(a.merge(&CountMinSketch::new(0.1, 0.01)), Err(FilterError::Incompatible));
+
+        a.clear();
+        assert_eq!((a.total(), a.estimate("x")), (0, 0));
+    }
+
+    #[test]
+    fn test_top_k() {
+        // value i appears about 2000 / (i + 1) times, shuffled
+        let mut items = Vec::new();
+        for i in 0..2000u64 {
+            for _ in 0..2000 / (i + 1) {
+                items.push(i);
+            }
+        }
+        let mut state = 3;
+        for i in (1..items.len()).rev() {
+            items.swap(i, (xorshift(&mut state) % (i as u64 + 1)) as usize);
+        }
+
+        let mut top = TopK::new(10, 0.001, 0.01);
+        for item in &items {
+            top.insert(item);
+        }
+        let found: Vec<u64> = top.top().iter().map(|(item, _)| **item).collect();
+        assert_eq!(found.len(), 10);
+        for i in 0..5 {
+            assert!(found.contains(&i), "{} not in {:?}", i, found);
+        }
+        assert_eq!(found[0], 0);
+        let (_, first) = top.top()[0];
+        assert!((2000..2100).contains(&first), "{}", first);
+    }
+}
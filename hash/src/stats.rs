@@ -0,0 +1,113 @@
+//! Occupancy and probe length diagnostics of `SimpleHashMap`.
+
+use std::fmt::{self, Display, Formatter, Write};
+use std::hash::{Hash, BuildHasher};
+
+use super::{SimpleHashMap, Slot, Probing};
+
+/// Widest bar of the histogram printed by `MapStats`.
+const HISTOGRAM_WIDTH: usize = 50;
+
+/// Slots per line of `SimpleHashMap::dump_layout`.
+const LAYOUT_WIDTH: usize = 64;
+
+/// How full a `SimpleHashMap` is and how far its items are from their
+/// original slot, see `SimpleHashMap::stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapStats {
+    pub probing: Probing,
+    pub len: usize,
+    pub buckets: usize,
+    pub tombstones: usize,
+    /// Live items per slot.
+    pub load_factor: f64,
+    pub max_probe_length: usize,
+    pub mean_probe_length: f64,
+    /// `probe_histogram[i]` is the number of items found by the `i + 1`-th
+    /// probe.
+    pub probe_histogram: Vec<usize>,
+    /// Longest run of consecutive non-empty slots, tombstones included. With
+    /// linear probing, a lookup starting in a run may walk all of it.
+    pub longest_run: usize,
+}
+
+impl Display for MapStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{:?} probing: {} items in {} slots, load factor {:.2}, {} tombstones",
+                 self.probing, self.len, self.buckets, self.load_factor, self.tombstones)?;
+        writeln!(f, "probe length: mean {:.2}, max {}, longest run {}",
+                 self.mean_probe_length, self.max_probe_length, self.longest_run)?;
+        let highest = self.probe_histogram.iter().cloned().max().unwrap_or(0);
+        for (i, &count) in self.probe_histogram.iter().enumerate() {
+            let bar = (count * HISTOGRAM_WIDTH).div_ceil(highest);
+            writeln!(f, "{:>4} | {:<width$} {}", i + 1, "#".repeat(bar), count, width = HISTOGRAM_WIDTH)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, S> SimpleHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+
+    /// Collects occupancy and probe length statistics. It walks the probe
+    /// sequence of every item, so it is as slow as looking all of them up.
+    pub fn stats(&self) -> MapStats {
+        let lengths = self.probe_lengths();
+        let mut probe_histogram = vec![0; lengths.iter().cloned().max().unwrap_or(0)];
+        for &length in &lengths {
+            probe_histogram[length - 1] += 1;
+        }
+        MapStats {
+            probing: self.probing,
+            len: self.len,
+            buckets: self.capacity,
+            tombstones: self.deleted,
+            load_factor: if self.capacity == 0 { 0.0 } else { self.len as f64 / self.capacity as f64 },
+            max_probe_length: probe_histogram.len(),
+            mean_probe_length: if lengths.is_empty() { 0.0 } else {
+                lengths.iter().sum::<usize>() as f64 / lengths.len() as f64
+            },
+            probe_histogram,
+            longest_run: self.longest_run(),
+        }
+    }
+
+    /// Longest run of non-empty slots, wrapping around the end of the table.
+    fn longest_run(&self) -> usize {
+        let Some(first_empty) = self.table.iter().position(|slot| matches!(slot, Slot::Empty)) else {
+            return self.capacity;
+        };
+        let (mut longest, mut run) = (0, 0);
+        for i in 1..=self.capacity {
+            match self.table[(first_empty + i) % self.capacity] {
+                Slot::Empty => run = 0,
+                _ => {
+                    run += 1;
+                    longest = longest.max(run);
+                }
+            }
+        }
+        longest
+    }
+
+    /// Draws the table with one character per slot, 64 slots per line:
+    /// `.` is empty, `x` a tombstone, `0` to `9` the number of extra probes
+    /// needed to find the item there, and `+` ten or more.
+    pub fn dump_layout(&self) -> String {
+        let mut out = String::new();
+        for (line, slots) in self.table.chunks(LAYOUT_WIDTH).enumerate() {
+            let _ = write!(out, "{:>8} ", line * LAYOUT_WIDTH);
+            for (i, slot) in slots.iter().enumerate() {
+                out.push(match slot {
+                    Slot::Empty => '.',
+                    Slot::Deleted => 'x',
+                    Slot::Full(item) => {
+                        let extra = self.probe_length(item.hash, line * LAYOUT_WIDTH + i) - 1;
+                        std::char::from_digit(extra as u32, 10).unwrap_or('+')
+                    }
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
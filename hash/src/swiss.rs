@@ -0,0 +1,1040 @@
+//! SwissTable style layout for `SimpleHashMap`.
+//!
+//! `SwissHashMap` offers the same API as `SimpleHashMap`, but keeps one
+//! control byte per slot in a separate array:
+//!
+//! * `0b1111_1111` the slot is empty
+//! * `0b1000_0000` the slot is a tombstone
+//! * `0b0xxx_xxxx` the slot is full, the low 7 bits are taken from the hash
+//!
+//! The control bytes are the only record of which slots are full, the
+//! slots themselves are bare `MaybeUninit<(K, V)>` with no tag or padding
+//! of their own. A slot is initialized exactly when its control byte has
+//! the high bit clear, every `unsafe` block relies on that alone.
+//!
+//! Lookups load 8 control bytes at a time into a `u64` and find candidate
+//! slots with a few bit tricks, so only keys whose 7 hash bits match are
+//! ever compared. The table size is a power of two and indexes are masked
+//! instead of taken modulo.
+//!
+//! Refer to https://abseil.io/about/design/swisstables
+
+use std::hash::{Hash, BuildHasher};
+use std::iter::Zip;
+use std::mem::{self, MaybeUninit};
+use std::slice;
+use std::iter::FromIterator;
+use std::fmt::{Debug, Formatter, Result};
+use std::ops::Index;
+
+use super::RSBuildHasher;
+
+const MIN_DEFAULT_CAPACITY: usize = 32;
+
+/// Number of control bytes probed at once.
+const GROUP_WIDTH: usize = mem::size_of::<u64>();
+
+const EMPTY: u8 = 0b1111_1111;
+const DELETED: u8 = 0b1000_0000;
+
+/// Whether a control byte marks a full slot, i.e. an initialized one.
+fn is_full(ctrl: u8) -> bool {
+    ctrl & 0x80 == 0
+}
+
+/// Number of items a table with `buckets` slots can hold before it grows,
+/// a load factor of 7/8 since probing skips full groups quickly.
+fn usable_capacity(buckets: usize) -> usize {
+    buckets / 8 * 7
+}
+
+/// Number of slots required to hold `cap` items without growing.
+fn buckets_for(cap: usize) -> usize {
+    if cap == 0 {
+        return 0;
+    }
+    let min_buckets = cap
+        .checked_mul(8)
+        .map(|n| n.div_ceil(7))
+        .and_then(usize::checked_next_power_of_two)
+        .expect("capacity overflow");
+    min_buckets.max(MIN_DEFAULT_CAPACITY)
+}
+
+/// 7 bits of the hash for the control byte.
+///
+/// The low bits already pick the slot, and some hashers, such as DEK and
+/// AP, leave the high half empty, so fold both halves and take bits above
+/// the usual table sizes.
+fn h2(hash: u64) -> u8 {
+    (((hash ^ (hash >> 32)) >> 25) & 0x7f) as u8
+}
+
+const LO_BITS: u64 = 0x0101_0101_0101_0101;
+const HI_BITS: u64 = 0x8080_8080_8080_8080;
+
+/// Control bytes of `GROUP_WIDTH` consecutive slots, first slot lowest.
+#[derive(Clone, Copy)]
+struct Group(u64);
+
+impl Group {
+
+    fn load(ctrl: &[u8], index: usize) -> Group {
+        let mut bytes = [0u8; GROUP_WIDTH];
+        bytes.copy_from_slice(&ctrl[index..index + GROUP_WIDTH]);
+        Group(u64::from_le_bytes(bytes))
+    }
+
+    /// Slots whose control byte equals `byte`.
+    ///
+    /// The zero byte test may also report the byte right after a real
+    /// match, which is harmless since keys are compared anyway. Empty and
+    /// deleted slots never match, their high bit is set.
+    fn match_byte(self, byte: u8) -> BitMask {
+        let cmp = self.0 ^ LO_BITS.wrapping_mul(byte as u64);
+        BitMask(cmp.wrapping_sub(LO_BITS) & !cmp & HI_BITS)
+    }
+
+    /// Empty slots, the only control byte with its two high bits set.
+    fn match_empty(self) -> BitMask {
+        BitMask(self.0 & (self.0 << 1) & HI_BITS)
+    }
+
+    fn match_empty_or_deleted(self) -> BitMask {
+        BitMask(self.0 & HI_BITS)
+    }
+}
+
+/// One bit per slot of a group, the high bit of each byte.
+#[derive(Clone, Copy)]
+struct BitMask(u64);
+
+impl BitMask {
+
+    fn any(self) -> bool {
+        self.0 != 0
+    }
+
+    fn lowest(self) -> Option<usize> {
+        if self.any() {
+            Some(self.trailing_zeros())
+        } else {
+            None
+        }
+    }
+
+    /// Number of unset slots at the start of the group.
+    fn trailing_zeros(self) -> usize {
+        self.0.trailing_zeros() as usize / 8
+    }
+
+    /// Number of unset slots at the end of the group.
+    fn leading_zeros(self) -> usize {
+        self.0.leading_zeros() as usize / 8
+    }
+}
+
+impl Iterator for BitMask {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let bit = self.lowest()?;
+        self.0 &= self.0 - 1;
+        Some(bit)
+    }
+}
+
+/// Triangular probing over groups: the start of the group moves by 8, 16,
+/// 24, ... slots, which visits every group of a power of two sized table.
+struct ProbeSeq {
+    pos: usize,
+    stride: usize,
+}
+
+impl ProbeSeq {
+
+    fn move_next(&mut self, bucket_mask: usize) {
+        self.stride += GROUP_WIDTH;
+        self.pos = (self.pos + self.stride) & bucket_mask;
+    }
+}
+
+enum Probe {
+    Found(usize),
+    Vacant(usize),
+}
+
+pub struct SwissHashMap<K, V, S = RSBuildHasher> {
+    /// one byte per slot, plus a copy of the first group at the end, so a
+    /// group can be loaded from any slot without wrapping around
+    ctrl: Vec<u8>,
+    /// initialized exactly where `ctrl` holds a full control byte
+    slots: Vec<MaybeUninit<(K, V)>>,
+    bucket_mask: usize,
+    len: usize,
+    /// number of items which can still go into empty slots, tombstones do
+    /// not give it back
+    growth_left: usize,
+    hash_builder: S,
+}
+
+pub struct Iter<'a, K: 'a, V: 'a> {
+    iter: Zip<slice::Iter<'a, u8>, slice::Iter<'a, MaybeUninit<(K, V)>>>,
+    consumed: usize,
+    current_len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            let (&ctrl, slot) = self.iter.next()?;
+            if is_full(ctrl) {
+                self.consumed += 1;
+                // SAFETY: the control byte says the slot is initialized
+                let (key, value) = unsafe { slot.assume_init_ref() };
+                return Some((key, value))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {
+    fn len(&self) -> usize {
+        self.current_len - self.consumed
+    }
+}
+
+pub struct Keys<'a, K: 'a, V: 'a> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Keys<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+pub struct Values<'a, K: 'a, V: 'a> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Values<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<K: Hash + Eq, V> SwissHashMap<K, V, RSBuildHasher> {
+
+    pub fn new() -> SwissHashMap<K, V, RSBuildHasher> {
+        SwissHashMap::with_hasher(Default::default())
+    }
+
+    pub fn with_capacity(capacity: usize) -> SwissHashMap<K, V, RSBuildHasher> {
+        SwissHashMap::with_capacity_and_hasher(capacity, Default::default())
+    }
+}
+
+impl<K, V, S> SwissHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+
+    pub fn with_hasher(hash_builder: S) -> SwissHashMap<K, V, S> {
+        SwissHashMap::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> SwissHashMap<K, V, S> {
+        let mut map = SwissHashMap {
+            ctrl: Vec::new(),
+            slots: Vec::new(),
+            bucket_mask: 0,
+            len: 0,
+            growth_left: 0,
+            hash_builder,
+        };
+        map.allocate(buckets_for(capacity));
+        map
+    }
+
+    /// Returns a reference to the map's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Replaces the table with an empty one of `buckets` slots, and returns
+    /// the old control bytes and slots. The items in the old slots are not
+    /// dropped by the returned `Vec`, they have to be moved out.
+    fn allocate(&mut self, buckets: usize) -> (Vec<u8>, Vec<MaybeUninit<(K, V)>>) {
+        let mut slots = Vec::with_capacity(buckets);
+        slots.resize_with(buckets, MaybeUninit::uninit);
+        let ctrl = if buckets == 0 { Vec::new() } else { vec![EMPTY; buckets + GROUP_WIDTH] };
+        self.bucket_mask = buckets.saturating_sub(1);
+        self.len = 0;
+        self.growth_left = usable_capacity(buckets);
+        (mem::replace(&mut self.ctrl, ctrl), mem::replace(&mut self.slots, slots))
+    }
+}
+
+impl<K, V, S> SwissHashMap<K, V, S> {
+
+    /// The item in slot `index`, if its control byte says there is one.
+    fn slot(&self, index: usize) -> Option<&(K, V)> {
+        if is_full(self.ctrl[index]) {
+            // SAFETY: the control byte says the slot is initialized
+            Some(unsafe { self.slots[index].assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    fn slot_mut(&mut self, index: usize) -> Option<&mut (K, V)> {
+        if is_full(self.ctrl[index]) {
+            // SAFETY: the control byte says the slot is initialized
+            Some(unsafe { self.slots[index].assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Drops every item, leaving the control bytes to the caller.
+    fn drop_items(&mut self) {
+        for (ctrl, slot) in self.ctrl.iter_mut().zip(self.slots.iter_mut()) {
+            if is_full(*ctrl) {
+                // mark the slot first, so a panicking drop can not drop it twice
+                *ctrl = EMPTY;
+                // SAFETY: the control byte said the slot is initialized
+                unsafe { slot.assume_init_drop() };
+            }
+        }
+    }
+}
+
+impl<K, V, S> Drop for SwissHashMap<K, V, S> {
+    fn drop(&mut self) {
+        self.drop_items();
+    }
+}
+
+impl<K, V, S> Default for SwissHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher + Default {
+    fn default() -> SwissHashMap<K, V, S> {
+        SwissHashMap::with_hasher(Default::default())
+    }
+}
+
+impl<K, V, S> SwissHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+
+    /// Returns the number of elements the map can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        usable_capacity(self.slots.len())
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            iter: self.ctrl.iter().zip(self.slots.iter()),
+            consumed: 0,
+            current_len: self.len,
+        }
+    }
+
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Removes all items, but keeps the allocated memory for reuse.
+    pub fn clear(&mut self) {
+        self.drop_items();
+        for byte in self.ctrl.iter_mut() {
+            *byte = EMPTY;
+        }
+        self.len = 0;
+        self.growth_left = usable_capacity(self.slots.len());
+    }
+
+    /// Reserves capacity for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        if additional > self.growth_left {
+            let required = self.len.checked_add(additional).expect("capacity overflow");
+            self.resize(buckets_for(required.max(self.capacity())));
+        }
+    }
+
+    /// Shrinks the capacity of the map as much as possible, while keeping
+    /// the load factor under 7/8.
+    pub fn shrink_to_fit(&mut self) {
+        let buckets = buckets_for(self.len);
+        if buckets < self.slots.len() {
+            self.resize(buckets);
+        }
+    }
+
+    /// Moves every item into a fresh table of `buckets` slots, dropping all
+    /// tombstones. Keys are hashed again, only 7 bits of the hash are kept.
+    fn resize(&mut self, buckets: usize) {
+        debug_assert!(usable_capacity(buckets) >= self.len);
+        let (old_ctrl, old_slots) = self.allocate(buckets);
+        for (ctrl, slot) in old_ctrl.into_iter().zip(old_slots) {
+            if is_full(ctrl) {
+                // SAFETY: the old control byte says the slot is initialized,
+                // and the old slots are dropped without dropping their items
+                let (key, value) = unsafe { slot.assume_init() };
+                let hash = self.hash_builder.hash_one(&key);
+                let index = self.find_insert_slot(hash);
+                self.place(index, hash, key, value);
+            }
+        }
+    }
+
+    /// Makes sure one more item can go into an empty slot. A table clogged
+    /// by tombstones is rehashed at the same size, a full one doubles.
+    fn reserve_one(&mut self) {
+        if self.growth_left > 0 {
+            return;
+        }
+        if self.len < self.capacity() / 2 {
+            self.resize(self.slots.len());
+        } else {
+            self.resize(buckets_for(self.capacity() + 1));
+        }
+    }
+
+    pub fn get(&self, k: &K) -> Option<&V> {
+        self.find_index(k).and_then(|index| self.slot(index)).map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, k: &K) -> bool {
+        self.find_index(k).is_some()
+    }
+
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        match self.entry(k) {
+            Entry::Occupied(mut entry) => Some(entry.insert(v)),
+            Entry::Vacant(entry) => {
+                entry.insert(v);
+                None
+            }
+        }
+    }
+
+    /// Gets the given key's corresponding entry in the map for in-place
+    /// manipulation.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        self.reserve_one();
+
+        let hash = self.hash_builder.hash_one(&key);
+        match self.probe(&key, hash) {
+            Probe::Found(index) => Entry::Occupied(OccupiedEntry {
+                map: self,
+                index,
+            }),
+            Probe::Vacant(index) => Entry::Vacant(VacantEntry {
+                map: self,
+                key,
+                hash,
+                index,
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, k: &K) -> Option<V> {
+        self.find_index(k).map(|index| self.remove_at(index))
+    }
+
+    /// Takes the item at `index` out of the table.
+    ///
+    /// The slot may only become empty again if no probe ever saw a whole
+    /// group without an empty slot around it, otherwise lookups which went
+    /// past it would stop too early. In that case it becomes a tombstone.
+    fn remove_at(&mut self, index: usize) -> V {
+        let index_before = index.wrapping_sub(GROUP_WIDTH) & self.bucket_mask;
+        let empty_before = Group::load(&self.ctrl, index_before).match_empty();
+        let empty_after = Group::load(&self.ctrl, index).match_empty();
+        let ctrl = if empty_before.leading_zeros() + empty_after.trailing_zeros() >= GROUP_WIDTH {
+            DELETED
+        } else {
+            self.growth_left += 1;
+            EMPTY
+        };
+        assert!(is_full(self.ctrl[index]), "find index but have no value");
+        self.set_ctrl(index, ctrl);
+        self.len -= 1;
+        // SAFETY: the slot was full, and the control byte no longer says so,
+        // so the item is moved out exactly once
+        let (_, value) = unsafe { self.slots[index].assume_init_read() };
+        value
+    }
+
+    fn find_index(&self, k: &K) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        match self.probe(k, self.hash_builder.hash_one(k)) {
+            Probe::Found(index) => Some(index),
+            Probe::Vacant(_) => None,
+        }
+    }
+
+    /// Walks the groups on the probe sequence of `hash`, comparing only the
+    /// keys whose control byte matches. A group with an empty slot ends the
+    /// search, the first empty or deleted slot seen is where `k` goes.
+    fn probe(&self, k: &K, hash: u64) -> Probe {
+        let h2 = h2(hash);
+        let mut seq = ProbeSeq { pos: hash as usize & self.bucket_mask, stride: 0 };
+        let mut vacant = None;
+        loop {
+            let group = Group::load(&self.ctrl, seq.pos);
+            for bit in group.match_byte(h2) {
+                let index = (seq.pos + bit) & self.bucket_mask;
+                if let Some((key, _)) = self.slot(index) {
+                    if key == k {
+                        return Probe::Found(index);
+                    }
+                }
+            }
+            if vacant.is_none() {
+                vacant = group.match_empty_or_deleted().lowest()
+                    .map(|bit| (seq.pos + bit) & self.bucket_mask);
+            }
+            if group.match_empty().any() {
+                return Probe::Vacant(vacant.expect("group with an empty slot has a vacant slot"));
+            }
+            seq.move_next(self.bucket_mask);
+        }
+    }
+
+    /// First empty or deleted slot on the probe sequence of `hash`.
+    fn find_insert_slot(&self, hash: u64) -> usize {
+        let mut seq = ProbeSeq { pos: hash as usize & self.bucket_mask, stride: 0 };
+        loop {
+            let group = Group::load(&self.ctrl, seq.pos);
+            if let Some(bit) = group.match_empty_or_deleted().lowest() {
+                return (seq.pos + bit) & self.bucket_mask;
+            }
+            seq.move_next(self.bucket_mask);
+        }
+    }
+
+    fn place(&mut self, index: usize, hash: u64, key: K, value: V) {
+        assert!(!is_full(self.ctrl[index]), "place into a full slot");
+        if self.ctrl[index] == EMPTY {
+            self.growth_left -= 1;
+        }
+        self.slots[index].write((key, value));
+        self.set_ctrl(index, h2(hash));
+        self.len += 1;
+    }
+
+    /// Sets the control byte of `index` and of its copy after the end.
+    fn set_ctrl(&mut self, index: usize, ctrl: u8) {
+        let mirror = (index.wrapping_sub(GROUP_WIDTH) & self.bucket_mask) + GROUP_WIDTH;
+        self.ctrl[index] = ctrl;
+        self.ctrl[mirror] = ctrl;
+    }
+}
+
+/// A view into a single entry in a map, which may either be vacant or
+/// occupied.
+pub enum Entry<'a, K: 'a, V: 'a, S: 'a = RSBuildHasher> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K: 'a, V: 'a, S: 'a = RSBuildHasher> {
+    map: &'a mut SwissHashMap<K, V, S>,
+    index: usize,
+}
+
+pub struct VacantEntry<'a, K: 'a, V: 'a, S: 'a = RSBuildHasher> {
+    map: &'a mut SwissHashMap<K, V, S>,
+    key: K,
+    hash: u64,
+    index: usize,
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> Entry<'a, K, V, S> {
+
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V: Default, S: BuildHasher> Entry<'a, K, V, S> {
+
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
+
+    pub fn key(&self) -> &K {
+        &self.item().0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.item().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.slot_mut(self.index).expect("occupied entry has no value").1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.slot_mut(self.index).expect("occupied entry has no value").1
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Takes the value out of the entry, and returns it.
+    pub fn remove(self) -> V {
+        self.map.remove_at(self.index)
+    }
+
+    fn item(&self) -> &(K, V) {
+        self.map.slot(self.index).expect("occupied entry has no value")
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Sets the value of the entry at the slot found by the probe, and
+    /// returns a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let map = self.map;
+        map.place(self.index, self.hash, self.key, value);
+        &mut map.slot_mut(self.index).expect("vacant entry was just filled").1
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a SwissHashMap<K, V, S> where K: Hash + Eq, S: BuildHasher {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for SwissHashMap<K, V, S>
+    where K: Hash + Eq,
+          S: BuildHasher + Default {
+
+    fn from_iter<I: IntoIterator<Item=(K, V)>>(iter: I) -> SwissHashMap<K, V, S> {
+        let mut map = SwissHashMap::with_hasher(Default::default());
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl<K, V, S> PartialEq for SwissHashMap<K, V, S>
+    where K: Hash + Eq,
+          V: PartialEq,
+          S: BuildHasher {
+
+    fn eq(&self, other: &SwissHashMap<K, V, S>) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+
+        self.iter().all(
+            |(key, value)| other.get(key) == Some(value)
+        )
+    }
+}
+
+impl<K, V, S> Debug for SwissHashMap<K, V, S>
+    where K: Hash + Eq + Debug,
+          V: Debug,
+          S: BuildHasher {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S> Index<&K> for SwissHashMap<K, V, S>
+    where K: Hash + Eq + Sized,
+          S: BuildHasher {
+
+    type Output = V;
+
+    fn index(&self, key: &K) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+#[cfg(test)]
+mod test_swiss_map {
+    use super::{SwissHashMap, Group, EMPTY, DELETED, GROUP_WIDTH};
+    use super::Entry::{Occupied, Vacant};
+    use crate::hasher::{FxBuildHasher, xorshift};
+    use crate::test_util::Droppable;
+    use std::collections::HashMap;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn group(bytes: [u8; GROUP_WIDTH]) -> Group {
+        Group::load(&bytes, 0)
+    }
+
+    fn bits<I: Iterator<Item=usize>>(iter: I) -> Vec<usize> {
+        iter.collect()
+    }
+
+    /// The tail of the control bytes has to mirror the first group, and
+    /// the full control bytes have to count the items.
+    fn check_mirror<K, V, S>(m: &SwissHashMap<K, V, S>) {
+        let buckets = m.slots.len();
+        if buckets > 0 {
+            assert_eq!(&m.ctrl[..GROUP_WIDTH], &m.ctrl[buckets..]);
+        }
+        assert_eq!(m.ctrl[..buckets].iter().filter(|&&ctrl| ctrl & 0x80 == 0).count(), m.len);
+    }
+
+    #[test]
+    fn test_group_matches() {
+        let g = group([0x12, EMPTY, 0x12, DELETED, 0x00, 0x7f, EMPTY, 0x13]);
+        assert_eq!(bits(g.match_byte(0x12)), vec![0, 2]);
+        assert_eq!(bits(g.match_byte(0x7f)), vec![5]);
+        assert_eq!(bits(g.match_byte(0x00)), vec![4]);
+        assert_eq!(bits(g.match_empty()), vec![1, 6]);
+        assert_eq!(bits(g.match_empty_or_deleted()), vec![1, 3, 6]);
+        assert!(!g.match_byte(0x55).any());
+
+        let full = group([1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(!full.match_empty().any());
+        assert_eq!(full.match_empty().leading_zeros(), GROUP_WIDTH);
+        assert_eq!(full.match_empty().trailing_zeros(), GROUP_WIDTH);
+        assert_eq!(group([1, 2, EMPTY, 4, 5, EMPTY, 7, 8]).match_empty().leading_zeros(), 2);
+        assert_eq!(group([1, 2, EMPTY, 4, 5, EMPTY, 7, 8]).match_empty().trailing_zeros(), 2);
+    }
+
+    #[test]
+    fn test_match_byte_never_reports_special_bytes() {
+        for h2 in 0..0x80u8 {
+            let g = group([EMPTY, DELETED, EMPTY, DELETED, EMPTY, DELETED, EMPTY, DELETED]);
+            assert!(!g.match_byte(h2).any());
+        }
+    }
+
+    #[test]
+    fn test_zero_capacities() {
+        type HM = SwissHashMap<i32, i32>;
+
+        let m = HM::new();
+        assert_eq!(m.capacity(), 0);
+
+        let m = HM::with_capacity(0);
+        assert_eq!(m.capacity(), 0);
+        assert_eq!(m.get(&1), None);
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut m = SwissHashMap::new();
+        assert_eq!(m.len(), 0);
+        assert!(m.insert(1, 2).is_none());
+        assert_eq!(m.len(), 1);
+        assert!(m.insert(2, 4).is_none());
+        assert_eq!(m.len(), 2);
+        assert_eq!(*m.get(&1).unwrap(), 2);
+        assert_eq!(*m.get(&2).unwrap(), 4);
+        assert_eq!(m.insert(2, 5), Some(4));
+        assert_eq!(m[&2], 5);
+        check_mirror(&m);
+    }
+
+    #[test]
+    fn test_lots_of_insertions() {
+        let mut m = SwissHashMap::new();
+
+        for _ in 0..3 {
+            assert!(m.is_empty());
+
+            for i in 1..1001 {
+                assert!(m.insert(i, i).is_none());
+
+                for j in 1..i + 1 {
+                    assert_eq!(m.get(&j), Some(&j));
+                }
+
+                for j in i + 1..1001 {
+                    assert_eq!(m.get(&j), None);
+                }
+            }
+            check_mirror(&m);
+
+            for i in 1001..2001 {
+                assert!(!m.contains_key(&i));
+            }
+
+            // remove forwards
+            for i in 1..1001 {
+                assert!(m.remove(&i).is_some());
+
+                for j in 1..i + 1 {
+                    assert!(!m.contains_key(&j));
+                }
+
+                for j in i + 1..1001 {
+                    assert!(m.contains_key(&j));
+                }
+            }
+            check_mirror(&m);
+
+            for i in 1..1001 {
+                assert!(m.insert(i, i).is_none());
+            }
+
+            // remove backwards
+            for i in (1..1001).rev() {
+                assert!(m.remove(&i).is_some());
+
+                for j in i..1001 {
+                    assert!(!m.contains_key(&j));
+                }
+
+                for j in 1..i {
+                    assert!(m.contains_key(&j));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_capacity_not_less_than_len() {
+        let mut a = SwissHashMap::new();
+        let mut item = 0;
+
+        for _ in 0..116 {
+            a.insert(item, 0);
+            item += 1;
+        }
+
+        assert!(a.capacity() > a.len());
+
+        let free = a.capacity() - a.len();
+        for _ in 0..free {
+            a.insert(item, 0);
+            item += 1;
+        }
+
+        assert_eq!(a.len(), a.capacity());
+
+        // Insert at capacity should cause allocation.
+        a.insert(item, 0);
+        assert!(a.capacity() > a.len());
+    }
+
+    #[test]
+    fn test_reserve_shrink_to_fit() {
+        let mut m = SwissHashMap::new();
+        m.reserve(100);
+        let cap = m.capacity();
+        assert!(cap >= 100);
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.capacity(), cap);
+
+        for i in 10..100 {
+            assert_eq!(m.remove(&i), Some(i));
+        }
+        m.shrink_to_fit();
+        assert!(m.capacity() < cap);
+        for i in 0..10 {
+            assert_eq!(m.get(&i), Some(&i));
+        }
+        check_mirror(&m);
+    }
+
+    #[test]
+    fn test_tombstones_are_reclaimed() {
+        let mut m = SwissHashMap::with_capacity(8);
+        let cap = m.capacity();
+        for i in 0..10_000 {
+            m.insert(i, i);
+            assert_eq!(m.remove(&i), Some(i));
+        }
+        assert!(m.is_empty());
+        assert_eq!(m.capacity(), cap);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut m = SwissHashMap::new();
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        let cap = m.capacity();
+        m.clear();
+        assert!(m.is_empty());
+        assert_eq!(m.capacity(), cap);
+        assert_eq!(m.get(&1), None);
+        m.insert(1, 1);
+        assert_eq!(m.get(&1), Some(&1));
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut counts: SwissHashMap<&str, usize> = SwissHashMap::new();
+        for word in "a b a c b a".split(' ') {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&"a"], 3);
+        assert_eq!(counts[&"b"], 2);
+        assert_eq!(counts[&"c"], 1);
+
+        match counts.entry("b") {
+            Vacant(_) => unreachable!(),
+            Occupied(view) => assert_eq!(view.remove(), 2),
+        }
+        match counts.entry("b") {
+            Occupied(_) => unreachable!(),
+            Vacant(view) => assert_eq!(*view.insert(7), 7),
+        }
+        counts.entry("c").and_modify(|v| *v += 10).or_default();
+        assert_eq!(counts[&"c"], 11);
+        assert_eq!(counts.len(), 3);
+    }
+
+    #[test]
+    fn test_agrees_with_std() {
+        let mut m: SwissHashMap<u64, usize, FxBuildHasher> = SwissHashMap::default();
+        let mut expected = HashMap::new();
+        let mut x: u64 = 0x2545_f491_4f6c_dd1d;
+        for i in 0..20_000 {
+            xorshift(&mut x);
+            let key = x % 3000;
+            if x & 0x300 == 0 {
+                assert_eq!(m.remove(&key), expected.remove(&key), "step {}", i);
+            } else {
+                assert_eq!(m.insert(key, i), expected.insert(key, i), "step {}", i);
+            }
+            assert_eq!(m.len(), expected.len());
+        }
+        check_mirror(&m);
+        for key in 0..3000 {
+            assert_eq!(m.get(&key), expected.get(&key), "key {}", key);
+        }
+        assert_eq!(m.iter().count(), expected.len());
+    }
+
+    #[test]
+    fn test_eq_and_show() {
+        let a: SwissHashMap<_, _> = vec![(1, 2), (3, 4)].into_iter().collect();
+        let mut b = SwissHashMap::new();
+        b.insert(3, 4);
+        assert!(a != b);
+        b.insert(1, 2);
+        assert_eq!(a, b);
+
+        let map_str = format!("{:?}", a);
+        assert!(map_str == "{1: 2, 3: 4}" || map_str == "{3: 4, 1: 2}");
+    }
+
+    #[test]
+    fn test_drops() {
+        let drops = Rc::new(Cell::new(0));
+        {
+            let mut m = SwissHashMap::new();
+            for i in 0..100 {
+                m.insert(i.to_string(), Droppable { drops: drops.clone() });
+            }
+            for i in 0..50 {
+                drop(m.remove(&i.to_string()));
+            }
+            assert_eq!(drops.get(), 50);
+            m.reserve(1000);
+            assert_eq!(drops.get(), 50);
+            m.clear();
+            assert_eq!(drops.get(), 100);
+            m.insert("again".to_string(), Droppable { drops: drops.clone() });
+        }
+        assert_eq!(drops.get(), 101);
+    }
+
+    #[test]
+    fn test_iter_size_hint() {
+        let m: SwissHashMap<_, _> = (0..10).map(|i| (i, i * 2)).collect();
+        let mut iter = m.iter();
+        assert_eq!(iter.size_hint(), (10, Some(10)));
+        iter.next();
+        assert_eq!(iter.size_hint(), (9, Some(9)));
+        assert_eq!(m.keys().size_hint(), (10, Some(10)));
+        assert_eq!(m.values().len(), 10);
+        assert_eq!(m.keys().collect::<Vec<_>>().len(), 10);
+    }
+}
@@ -0,0 +1,35 @@
+//! Fixtures shared by the tests of several modules.
+
+use std::cell::Cell;
+use std::hash::Hasher;
+use std::rc::Rc;
+
+use crate::hasher::mix;
+
+/// `len` pseudo random bytes, the same for the same `seed`.
+pub fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    (0..len as u64).map(|i| mix(seed ^ i) as u8).collect()
+}
+
+/// Hashes everything to the same value, so every key collides.
+#[derive(Default)]
+pub struct ConstantHasher;
+
+impl Hasher for ConstantHasher {
+    fn finish(&self) -> u64 {
+        7
+    }
+
+    fn write(&mut self, _: &[u8]) {}
+}
+
+/// Counts its drops in `drops`, to check that maps drop every item once.
+pub struct Droppable {
+    pub drops: Rc<Cell<usize>>,
+}
+
+impl Drop for Droppable {
+    fn drop(&mut self) {
+        self.drops.set(self.drops.get() + 1);
+    }
+}
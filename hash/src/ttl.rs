@@ -0,0 +1,376 @@
+//! Cache whose items expire after a time to live.
+
+use std::hash::{Hash, BuildHasher};
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{SimpleHashMap, RSBuildHasher};
+
+/// Source of the current time of a `TtlCache`.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The real monotonic clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Virtual clock which only moves when told to, for tests. Clones share
+/// the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+
+    pub fn new() -> ManualClock {
+        ManualClock { now: Arc::new(Mutex::new(Instant::now())) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|e| e.into_inner());
+        *now += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+struct TtlItem<V> {
+    value: V,
+    /// `None` if the item never expires.
+    expires_at: Option<Instant>,
+}
+
+impl<V> TtlItem<V> {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+/// Cache whose items expire once their time to live has passed.
+///
+/// Expired items are removed lazily, when they are looked up, or all at once
+/// by `purge_expired`, which a `Sweeper` thread can call periodically. Either
+/// way the slot goes back to the underlying `SimpleHashMap`.
+pub struct TtlCache<K, V, C = SystemClock, S = RSBuildHasher> {
+    map: SimpleHashMap<K, TtlItem<V>, S>,
+    clock: C,
+    default_ttl: Option<Duration>,
+}
+
+impl<K: Hash + Eq, V> TtlCache<K, V, SystemClock, RSBuildHasher> {
+
+    /// Creates a cache whose items inserted by `insert` never expire.
+    pub fn new() -> TtlCache<K, V, SystemClock, RSBuildHasher> {
+        TtlCache::with_clock(SystemClock)
+    }
+
+    /// Creates a cache whose items inserted by `insert` expire after `ttl`.
+    pub fn with_default_ttl(ttl: Duration) -> TtlCache<K, V, SystemClock, RSBuildHasher> {
+        let mut cache = TtlCache::new();
+        cache.default_ttl = Some(ttl);
+        cache
+    }
+}
+
+impl<K: Hash + Eq, V> Default for TtlCache<K, V, SystemClock, RSBuildHasher> {
+    fn default() -> TtlCache<K, V, SystemClock, RSBuildHasher> {
+        TtlCache::new()
+    }
+}
+
+impl<K: Hash + Eq, V, C: Clock> TtlCache<K, V, C, RSBuildHasher> {
+
+    pub fn with_clock(clock: C) -> TtlCache<K, V, C, RSBuildHasher> {
+        TtlCache::with_clock_and_hasher(clock, Default::default())
+    }
+}
+
+impl<K, V, C, S> TtlCache<K, V, C, S> where K: Hash + Eq, C: Clock, S: BuildHasher {
+
+    pub fn with_clock_and_hasher(clock: C, hash_builder: S) -> TtlCache<K, V, C, S> {
+        TtlCache { map: SimpleHashMap::with_hasher(hash_builder), clock, default_ttl: None }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn default_ttl(&self) -> Option<Duration> {
+        self.default_ttl
+    }
+
+    pub fn set_default_ttl(&mut self, ttl: Option<Duration>) {
+        self.default_ttl = ttl;
+    }
+
+    /// Returns the number of items, including expired ones not removed yet.
+    pub fn len(&self) -> usize { self.map.len() }
+
+    pub fn is_empty(&self) -> bool { self.map.is_empty() }
+
+    /// Inserts an item expiring after the default time to live, if any.
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let expires_at = self.default_ttl.and_then(|ttl| self.clock.now().checked_add(ttl));
+        self.insert_item(k, TtlItem { value: v, expires_at })
+    }
+
+    /// Inserts an item expiring after `ttl`, returning the replaced value if
+    /// it had not expired yet.
+    pub fn insert_with_ttl(&mut self, k: K, v: V, ttl: Duration) -> Option<V> {
+        let expires_at = self.clock.now().checked_add(ttl);
+        self.insert_item(k, TtlItem { value: v, expires_at })
+    }
+
+    fn insert_item(&mut self, k: K, item: TtlItem<V>) -> Option<V> {
+        let now = self.clock.now();
+        self.map.insert(k, item).filter(|old| !old.is_expired(now)).map(|old| old.value)
+    }
+
+    /// Returns the value of `k`, removing it first if it has expired.
+    pub fn get(&mut self, k: &K) -> Option<&V> {
+        self.remove_if_expired(k);
+        self.map.get(k).map(|item| &item.value)
+    }
+
+    pub fn contains_key(&mut self, k: &K) -> bool {
+        self.get(k).is_some()
+    }
+
+    /// Returns how long the value of `k` lives on, `None` if it is absent or
+    /// never expires.
+    pub fn time_to_live(&mut self, k: &K) -> Option<Duration> {
+        self.remove_if_expired(k);
+        let now = self.clock.now();
+        self.map.get(k)?.expires_at.map(|at| at - now)
+    }
+
+    /// Removes the value of `k`, returning it if it had not expired yet.
+    pub fn remove(&mut self, k: &K) -> Option<V> {
+        let now = self.clock.now();
+        self.map.remove(k).filter(|item| !item.is_expired(now)).map(|item| item.value)
+    }
+
+    fn remove_if_expired(&mut self, k: &K) {
+        let now = self.clock.now();
+        if self.map.get(k).is_some_and(|item| item.is_expired(now)) {
+            self.map.remove(k);
+        }
+    }
+
+    /// Removes all expired items, returns how many.
+    pub fn purge_expired(&mut self) -> usize where K: Clone {
+        let now = self.clock.now();
+        let expired: Vec<K> = self.map.iter()
+            .filter(|(_, item)| item.is_expired(now))
+            .map(|(k, _)| k.clone())
+            .collect();
+        for k in &expired {
+            self.map.remove(k);
+        }
+        expired.len()
+    }
+}
+
+/// Background thread calling `purge_expired` on a shared `TtlCache`.
+///
+/// The thread stops when the `Sweeper` is dropped or the cache is gone.
+pub struct Sweeper {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Sweeper {
+
+    /// Starts purging `cache` every `interval`. The thread only keeps a weak
+    /// reference, so it does not keep the cache alive.
+    pub fn spawn<K, V, C, S>(cache: &Arc<Mutex<TtlCache<K, V, C, S>>>, interval: Duration) -> Sweeper
+        where K: Hash + Eq + Clone + Send + 'static,
+              V: Send + 'static,
+              C: Clock + Send + 'static,
+              S: BuildHasher + Send + 'static {
+
+        let cache: Weak<Mutex<TtlCache<K, V, C, S>>> = Arc::downgrade(cache);
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                match cache.upgrade() {
+                    Some(cache) => {
+                        cache.lock().unwrap_or_else(|e| e.into_inner()).purge_expired();
+                    }
+                    None => break,
+                }
+            }
+        });
+        Sweeper { stop: Some(stop), handle: Some(handle) }
+    }
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        // dropping the sender wakes the thread up
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_ttl_cache {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use super::{TtlCache, ManualClock, Sweeper};
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn test_lazy_expiry() {
+        let clock = ManualClock::new();
+        let mut cache = TtlCache::with_clock(clock.clone());
+        cache.insert_with_ttl(1, "a", secs(10));
+        cache.insert_with_ttl(2, "b", secs(20));
+        cache.insert(3, "c");
+
+        clock.advance(secs(9));
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.time_to_live(&1), Some(secs(1)));
+        assert_eq!(cache.time_to_live(&3), None);
+
+        clock.advance(secs(1));
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains_key(&2));
+
+        clock.advance(secs(1000));
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.get(&3), Some(&"c"));
+    }
+
+    #[test]
+    fn test_default_ttl() {
+        let clock = ManualClock::new();
+        let mut cache = TtlCache::with_clock(clock.clone());
+        cache.set_default_ttl(Some(secs(5)));
+        cache.insert("k", 1);
+        cache.insert_with_ttl("long", 2, secs(50));
+
+        clock.advance(secs(5));
+        assert_eq!(cache.get(&"k"), None);
+        assert_eq!(cache.get(&"long"), Some(&2));
+
+        assert_eq!(TtlCache::<i32, i32>::with_default_ttl(secs(1)).default_ttl(), Some(secs(1)));
+    }
+
+    #[test]
+    fn test_expired_values_are_not_returned() {
+        let clock = ManualClock::new();
+        let mut cache = TtlCache::with_clock(clock.clone());
+        cache.insert_with_ttl(1, 10, secs(1));
+        cache.insert_with_ttl(2, 20, secs(1));
+        assert_eq!(cache.insert_with_ttl(1, 11, secs(1)), Some(10));
+
+        clock.advance(secs(2));
+        assert_eq!(cache.insert_with_ttl(1, 12, secs(1)), None);
+        assert_eq!(cache.remove(&2), None);
+        assert_eq!(cache.remove(&1), Some(12));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_renewing_extends_life() {
+        let clock = ManualClock::new();
+        let mut cache = TtlCache::with_clock(clock.clone());
+        cache.insert_with_ttl(1, (), secs(10));
+        clock.advance(secs(8));
+        cache.insert_with_ttl(1, (), secs(10));
+        clock.advance(secs(8));
+        assert!(cache.contains_key(&1));
+    }
+
+    #[test]
+    fn test_huge_ttl_never_expires() {
+        let clock = ManualClock::new();
+        let mut cache = TtlCache::with_clock(clock.clone());
+        cache.insert_with_ttl(1, (), Duration::MAX);
+        clock.advance(secs(1 << 40));
+        assert!(cache.contains_key(&1));
+    }
+
+    #[test]
+    fn test_purge_expired_reclaims_slots() {
+        let clock = ManualClock::new();
+        let mut cache = TtlCache::with_clock(clock.clone());
+        for i in 0..1000 {
+            cache.insert_with_ttl(i, i, secs(1 + i as u64 % 2));
+        }
+        let capacity = cache.map.capacity();
+
+        clock.advance(secs(1));
+        assert_eq!(cache.purge_expired(), 500);
+        assert_eq!(cache.len(), 500);
+        assert!((0..1000).all(|i| cache.contains_key(&i) == (i % 2 == 1)));
+
+        for i in 1000..1500 {
+            cache.insert_with_ttl(i, i, secs(1));
+        }
+        assert_eq!(cache.map.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_sweeper() {
+        let clock = ManualClock::new();
+        let cache = Arc::new(Mutex::new(TtlCache::with_clock(clock.clone())));
+        for i in 0..100 {
+            cache.lock().unwrap().insert_with_ttl(i, i, secs(60));
+        }
+        let sweeper = Sweeper::spawn(&cache, Duration::from_millis(1));
+
+        clock.advance(secs(60));
+        for _ in 0..5000 {
+            if cache.lock().unwrap().is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(cache.lock().unwrap().is_empty());
+        drop(sweeper);
+    }
+
+    #[test]
+    fn test_sweeper_stops_without_cache() {
+        let cache = Arc::new(Mutex::new(TtlCache::<i32, i32>::new()));
+        let sweeper = Sweeper::spawn(&cache, Duration::from_millis(1));
+        drop(cache);
+        for _ in 0..5000 {
+            if sweeper.handle.as_ref().unwrap().is_finished() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(sweeper.handle.as_ref().unwrap().is_finished());
+    }
+}
@@ -68,11 +68,17 @@ fn is_base64_char(c: u8) -> bool {
 /// Encode string s using the standard Base64 alphabet
 ///
 pub fn base64_encode(s: &str) -> String {
+    base64_encode_bytes(s.as_bytes())
+}
+
+///
+/// Encode arbitrary bytes, such as a digest, using the standard Base64 alphabet
+///
+pub fn base64_encode_bytes(bytes: &[u8]) -> String {
 
     let mut buffer = [0u8;3];
 
-    let bytes = s.as_bytes();
-    let len = s.len();
+    let len = bytes.len();
 
     let loop_num = len / 3;
     let remaining = len % 3;
@@ -275,6 +281,13 @@ mod tests {
         assert_eq!(output.as_str(), expected);
     }
 
+    #[test]
+    fn test_standard_encode_bytes() {
+        assert_eq!(base64_encode_bytes(&[]).as_str(), "");
+        assert_eq!(base64_encode_bytes(&[0xff]).as_str(), "/w==");
+        assert_eq!(base64_encode_bytes(&[0x00, 0xfb, 0xff]).as_str(), "APv/");
+    }
+
     #[test]
     fn test_standard_decode() {
         let input = "";
@@ -1 +1,22 @@
-mod base64;
\ No newline at end of file
+mod base64;
+
+use hash::digest::{Digest, Md5};
+
+///
+/// Value of the `Content-MD5` header for body, the Base64 of its MD5 digest (RFC 1864)
+///
+pub fn content_md5(body: &[u8]) -> String {
+    base64::base64_encode_bytes(&Md5::digest(body))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_content_md5() {
+        assert_eq!(content_md5(b"").as_str(), "1B2M2Y8AsgTpgAmY7PhCfg==");
+        assert_eq!(content_md5(b"The quick brown fox jumps over the lazy dog").as_str(), "nhB9nTcrtoJr2B01QqQZ1g==");
+    }
+}
@@ -1,3 +1,5 @@
+extern crate hash;
+
 pub mod data_structure;
 pub mod concurrency;
 pub mod design_pattern;